@@ -0,0 +1,70 @@
+use std::io::{BufRead, Write};
+
+use regex::Regex;
+
+use crate::explain::Explainer;
+
+// What a match looks like and which interpretation it hints at. The first capture group is
+// the code.
+const PATTERNS: &[(&str, Option<&str>)] = &[
+    (
+        r"(?i)\b(?:win32 )?error(?: code)?\s*[:=#]?\s*(\d{1,5})\b",
+        Some("Win32"),
+    ),
+    (
+        r"(?i)\bstatus(?: code)?\s*[:=]?\s*(0x[0-9a-f]{8})\b",
+        Some("NTSTATUS"),
+    ),
+    (r"(?i)\b(0x[0-9a-f]{8})\b", None),
+];
+
+// Echoes each line of input with the message of every code found in it appended, e.g.
+// "open failed: 0x80070005 [0x80070005: Access is denied.]". Lines without a code that
+// resolves are echoed as they are.
+pub fn annotate(
+    input: impl BufRead,
+    mut out: impl Write,
+    explainer: &mut Explainer,
+) -> anyhow::Result<()> {
+    let patterns: Vec<(Regex, Option<&str>)> = PATTERNS
+        .iter()
+        .map(|(pattern, prefer)| (Regex::new(pattern).unwrap(), *prefer))
+        .collect();
+
+    for line in input.lines() {
+        let line = line?;
+        let mut annotations = Vec::new();
+        // Codes already explained by an earlier, more specific pattern are skipped.
+        let mut seen = Vec::new();
+        for (regex, prefer) in &patterns {
+            for captures in regex.captures_iter(&line) {
+                let code_match = captures.get(1).unwrap();
+                if seen.contains(&code_match.start()) {
+                    continue;
+                }
+                seen.push(code_match.start());
+                let code = match parse_code(code_match.as_str()) {
+                    Some(code) => code,
+                    None => continue,
+                };
+                if let Some(text) = explainer.best(code, *prefer) {
+                    let text: Vec<&str> = text.lines().map(|line| line.trim()).collect();
+                    annotations.push(format!("[{}: {}]", code_match.as_str(), text.join(" ")));
+                }
+            }
+        }
+        if annotations.is_empty() {
+            writeln!(out, "{}", line)?;
+        } else {
+            writeln!(out, "{} {}", line, annotations.join(" "))?;
+        }
+    }
+    Ok(())
+}
+
+fn parse_code(s: &str) -> Option<u32> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
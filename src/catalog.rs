@@ -0,0 +1,624 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use regex::Regex;
+
+use crate::decode::DecodedId;
+use crate::dump::{read_raw_tables, DumpOptions, Languages};
+use crate::message::MessageEntry;
+use crate::msgtable::{self, Block};
+use crate::table::{self, Error, RawMessageTable, ResourceId};
+
+// Snapshot files start with the magic and a format version, which is bumped whenever the
+// layout changes. Older versions are rejected rather than guessed at.
+const SNAPSHOT_MAGIC: &[u8; 4] = b"MCAT";
+const SNAPSHOT_VERSION: u32 = 1;
+
+// Every message of a module, in every language. Queries return entries ordered by ID and
+// then language.
+//
+// Only the block headers are parsed up front. The entries of a block are decoded the first
+// time an ID in it is looked up and cached from then on, so resolving a handful of IDs
+// doesn't pay for decoding the whole table. With strict options, every entry is checked
+// when the catalog is opened, so a table the dump would fail on fails the open as well.
+// Otherwise a block that fails to decode is treated as empty.
+//
+// A catalog is Send + Sync, so one catalog can be queried from many threads at once.
+// Clones share the same storage and cache and are cheap.
+#[derive(Clone)]
+pub struct MessageCatalog {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    // Ordered by language.
+    tables: Vec<LazyTable>,
+    options: DumpOptions,
+}
+
+#[derive(Clone)]
+struct LazyTable {
+    resource: ResourceId,
+    lang: u16,
+    // Empty for tables built from entries, which have no resource data. Their cells are all
+    // filled up front instead.
+    data: Vec<u8>,
+    from_entries: bool,
+    blocks: Vec<Block>,
+    // One cell per block.
+    entries: Vec<OnceLock<Vec<MessageEntry>>>,
+}
+
+// Fails to compile if MessageCatalog stops being shareable between threads.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<MessageCatalog>();
+};
+
+impl LazyTable {
+    fn new(raw: RawMessageTable, options: &DumpOptions) -> table::Result<LazyTable> {
+        let blocks = msgtable::read_blocks(&raw.data)?;
+        if options.strict {
+            for block in &blocks {
+                msgtable::check_block(&raw.data, block, options.decoding)?;
+            }
+        }
+        Ok(LazyTable {
+            resource: raw.resource,
+            lang: raw.lang,
+            data: raw.data,
+            from_entries: false,
+            entries: blocks.iter().map(|_| OnceLock::new()).collect(),
+            blocks,
+        })
+    }
+
+    // entries must be ordered by ID. Each run of consecutive IDs becomes a block, as in a
+    // real table.
+    fn from_entries(resource: ResourceId, lang: u16, entries: Vec<MessageEntry>) -> LazyTable {
+        let mut runs: Vec<Vec<MessageEntry>> = Vec::new();
+        for entry in entries {
+            match runs.last_mut() {
+                Some(run)
+                    if run.last().map(|last| last.id().checked_add(1))
+                        == Some(Some(entry.id())) =>
+                {
+                    run.push(entry)
+                }
+                _ => runs.push(vec![entry]),
+            }
+        }
+        LazyTable {
+            resource,
+            lang,
+            data: Vec::new(),
+            from_entries: true,
+            blocks: runs
+                .iter()
+                .map(|run| Block {
+                    low_id: run[0].id(),
+                    high_id: run[run.len() - 1].id(),
+                    offset_to_entries: 0,
+                })
+                .collect(),
+            entries: runs.into_iter().map(OnceLock::from).collect(),
+        }
+    }
+
+    fn block_entries(&self, index: usize, options: &DumpOptions) -> &[MessageEntry] {
+        self.entries[index].get_or_init(|| {
+            let entries = msgtable::parse_block(&self.data, &self.blocks[index], options.decoding)
+                .unwrap_or_default();
+            entries
+                .into_iter()
+                .filter(|entry| options.wants_id(entry.id))
+                .map(|mut entry| {
+                    if !options.include_raw {
+                        entry.raw = Vec::new();
+                    }
+                    MessageEntry::new(entry, &self.resource, self.lang)
+                })
+                .collect()
+        })
+    }
+
+    fn by_range<'a>(
+        &'a self,
+        range: &RangeInclusive<u32>,
+        options: &'a DumpOptions,
+    ) -> impl Iterator<Item = &'a MessageEntry> + 'a {
+        let range = range.clone();
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter(move |(_, block)| {
+                block.low_id <= *range.end() && *range.start() <= block.high_id
+            })
+            .flat_map(move |(index, _)| self.block_entries(index, options))
+    }
+}
+
+impl MessageCatalog {
+    pub fn open(path: impl AsRef<Path>) -> table::Result<MessageCatalog> {
+        MessageCatalog::open_with(path, &DumpOptions::new().languages(Languages::All))
+    }
+
+    pub fn open_with(
+        path: impl AsRef<Path>,
+        options: &DumpOptions,
+    ) -> table::Result<MessageCatalog> {
+        MessageCatalog::from_raw_tables(read_raw_tables(path.as_ref(), options)?, options)
+    }
+
+    // Tables that fail to parse fail the catalog, unless options aren't strict, in which case
+    // they're left out.
+    fn from_raw_tables(
+        raws: Vec<RawMessageTable>,
+        options: &DumpOptions,
+    ) -> table::Result<MessageCatalog> {
+        let mut tables = Vec::new();
+        for raw in raws {
+            match LazyTable::new(raw, options) {
+                Ok(table) => tables.push(table),
+                Err(_) if !options.strict => {}
+                Err(e) => return Err(e),
+            }
+        }
+        tables.sort_by_key(|table| table.lang);
+        Ok(MessageCatalog {
+            inner: Arc::new(Inner {
+                tables,
+                options: options.clone(),
+            }),
+        })
+    }
+
+    // A catalog of entries already read, e.g. by read_message_table_with. Entries are
+    // grouped into tables by resource and language. Such a catalog can't be saved, as it
+    // has no resource data to save.
+    pub fn from_entries(entries: Vec<MessageEntry>) -> MessageCatalog {
+        let mut grouped: BTreeMap<(ResourceId, u16), Vec<MessageEntry>> = BTreeMap::new();
+        for entry in entries {
+            grouped
+                .entry((entry.resource().clone(), entry.lang()))
+                .or_default()
+                .push(entry);
+        }
+        let mut tables: Vec<LazyTable> = grouped
+            .into_iter()
+            .map(|((resource, lang), mut entries)| {
+                entries.sort_by_key(|entry| entry.id());
+                LazyTable::from_entries(resource, lang, entries)
+            })
+            .collect();
+        tables.sort_by_key(|table| table.lang);
+        MessageCatalog {
+            inner: Arc::new(Inner {
+                tables,
+                options: DumpOptions::new().languages(Languages::All),
+            }),
+        }
+    }
+
+    // Combines the tables of several catalogs, e.g. of every module a service logs with, into
+    // one. The options of the first catalog apply to the result.
+    pub fn merge(catalogs: &[MessageCatalog]) -> table::Result<MessageCatalog> {
+        let options = catalogs
+            .first()
+            .map(|catalog| catalog.inner.options.clone())
+            .unwrap_or_default();
+        let mut tables = Vec::new();
+        for table in catalogs.iter().flat_map(|catalog| &catalog.inner.tables) {
+            // Tables built from entries keep the entries they were given.
+            if table.from_entries {
+                tables.push(table.clone());
+                continue;
+            }
+            tables.push(LazyTable::new(
+                RawMessageTable {
+                    resource: table.resource.clone(),
+                    lang: table.lang,
+                    data: table.data.clone(),
+                },
+                &options,
+            )?);
+        }
+        tables.sort_by_key(|table| table.lang);
+        Ok(MessageCatalog {
+            inner: Arc::new(Inner { tables, options }),
+        })
+    }
+
+    // Writes the undecoded tables to a snapshot that load reads back without touching the
+    // modules. Options aren't saved; they're given again on load.
+    pub fn save(&self, path: impl AsRef<Path>) -> table::Result<()> {
+        let path = path.as_ref();
+        if self.inner.tables.iter().any(|table| table.from_entries) {
+            return Err(Error::new(format!(
+                "can't save {}: the catalog was built from entries, so it has no tables to save",
+                path.display()
+            )));
+        }
+        let mut out = Vec::new();
+        out.extend_from_slice(SNAPSHOT_MAGIC);
+        out.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        out.extend_from_slice(&(self.inner.tables.len() as u32).to_le_bytes());
+        for table in &self.inner.tables {
+            match &table.resource {
+                ResourceId::Id(id) => {
+                    out.push(0);
+                    out.extend_from_slice(&id.to_le_bytes());
+                }
+                ResourceId::Name(name) => {
+                    out.push(1);
+                    out.extend_from_slice(&(name.len() as u32).to_le_bytes());
+                    out.extend_from_slice(name.as_bytes());
+                }
+            }
+            out.extend_from_slice(&table.lang.to_le_bytes());
+            out.extend_from_slice(&(table.data.len() as u32).to_le_bytes());
+            out.extend_from_slice(&table.data);
+        }
+        fs::write(path, out)
+            .map_err(|e| Error::new(format!("failed to write {}: {}", path.display(), e)))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> table::Result<MessageCatalog> {
+        MessageCatalog::load_with(path, &DumpOptions::new().languages(Languages::All))
+    }
+
+    pub fn load_with(
+        path: impl AsRef<Path>,
+        options: &DumpOptions,
+    ) -> table::Result<MessageCatalog> {
+        let path = path.as_ref();
+        let data = fs::read(path)
+            .map_err(|e| Error::new(format!("failed to read {}: {}", path.display(), e)))?;
+        let invalid = || Error::new(format!("{} is not a valid snapshot", path.display()));
+
+        let mut reader = SnapshotReader { data: &data };
+        if reader.bytes(SNAPSHOT_MAGIC.len()).ok_or_else(invalid)? != SNAPSHOT_MAGIC {
+            return Err(invalid());
+        }
+        let version = reader.u32().ok_or_else(invalid)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(Error::new(format!(
+                "{} is a version {} snapshot; only version {} is supported",
+                path.display(),
+                version,
+                SNAPSHOT_VERSION
+            )));
+        }
+
+        let mut raws = Vec::new();
+        for _ in 0..reader.u32().ok_or_else(invalid)? {
+            let resource = match reader.bytes(1).ok_or_else(invalid)?[0] {
+                0 => ResourceId::Id(reader.u16().ok_or_else(invalid)?),
+                1 => {
+                    let len = reader.u32().ok_or_else(invalid)? as usize;
+                    let name = reader.bytes(len).ok_or_else(invalid)?;
+                    ResourceId::Name(String::from_utf8(name.to_vec()).map_err(|_| invalid())?)
+                }
+                _ => return Err(invalid()),
+            };
+            let lang = reader.u16().ok_or_else(invalid)?;
+            let len = reader.u32().ok_or_else(invalid)? as usize;
+            let raw = RawMessageTable {
+                resource,
+                lang,
+                data: reader.bytes(len).ok_or_else(invalid)?.to_vec(),
+            };
+            if let Languages::Only(langs) = &options.languages {
+                if !langs.contains(&raw.lang) {
+                    continue;
+                }
+            }
+            raws.push(raw);
+        }
+        MessageCatalog::from_raw_tables(raws, options)
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &MessageEntry> {
+        self.by_range(0..=u32::MAX)
+    }
+
+    // Returns the entry for id in the lowest-numbered language that defines it.
+    pub fn get(&self, id: u32) -> Option<&MessageEntry> {
+        self.inner
+            .tables
+            .iter()
+            .flat_map(|table| table.by_range(&(id..=id), &self.inner.options))
+            .find(|entry| entry.id() == id)
+    }
+
+    pub fn get_lang(&self, id: u32, lang: u16) -> Option<&MessageEntry> {
+        self.inner
+            .tables
+            .iter()
+            .filter(|table| table.lang == lang)
+            .flat_map(|table| table.by_range(&(id..=id), &self.inner.options))
+            .find(|entry| entry.id() == id)
+    }
+
+    // Decodes every block that overlaps range.
+    pub fn by_range(&self, range: RangeInclusive<u32>) -> impl Iterator<Item = &MessageEntry> {
+        let mut entries: Vec<&MessageEntry> = self
+            .inner
+            .tables
+            .iter()
+            .flat_map(|table| table.by_range(&range, &self.inner.options))
+            .filter(|entry| range.contains(&entry.id()))
+            .collect();
+        // Stable, so entries of the same ID stay in language order.
+        entries.sort_by_key(|entry| entry.id());
+        entries.into_iter()
+    }
+
+    pub fn matching<'a>(&'a self, regex: &'a Regex) -> impl Iterator<Item = &'a MessageEntry> {
+        self.iter()
+            .filter(move |entry| regex.is_match(entry.text()))
+    }
+
+    // Each distinct language, in ascending order.
+    pub fn languages(&self) -> impl Iterator<Item = u16> {
+        let langs: BTreeSet<u16> = self.inner.tables.iter().map(|table| table.lang).collect();
+        langs.into_iter()
+    }
+
+    // Each distinct facility, in ascending order. Worked out from the block headers, so
+    // nothing is decoded. Every ID in a block has an entry, so the facilities are those of
+    // each range of 64K IDs, i.e. of each value of the top 16 bits, that a block reaches
+    // into, as long as the options want an ID from that part of the block.
+    pub fn facilities(&self) -> impl Iterator<Item = u16> {
+        let options = &self.inner.options;
+        let mut facilities = BTreeSet::new();
+        for block in self.inner.tables.iter().flat_map(|table| &table.blocks) {
+            for top in (block.low_id >> 16)..=(block.high_id >> 16) {
+                let first_id = top << 16;
+                let ids = block.low_id.max(first_id)..=block.high_id.min(first_id | 0xffff);
+                if options.wants_any(&ids) {
+                    facilities.insert(DecodedId::new(first_id).facility);
+                }
+            }
+        }
+        facilities.into_iter()
+    }
+}
+
+struct SnapshotReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> SnapshotReader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.data.len() < len {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(bytes)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes(4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture::{encode_message_table, FixtureEntry};
+    use crate::msgtable::{Encoding, BLOCK_SIZE};
+
+    fn raw(lang: u16, ids: &[u32]) -> RawMessageTable {
+        let entries: Vec<FixtureEntry> = ids
+            .iter()
+            .map(|&id| FixtureEntry::new(id, &text(id, lang), Encoding::Unicode))
+            .collect();
+        RawMessageTable {
+            resource: ResourceId::Id(1),
+            lang,
+            data: encode_message_table(&entries).unwrap(),
+        }
+    }
+
+    fn text(id: u32, lang: u16) -> String {
+        format!("{:#x} [{:04x}]\r\n", id, lang)
+    }
+
+    fn catalog(raws: Vec<RawMessageTable>) -> MessageCatalog {
+        MessageCatalog::from_raw_tables(raws, &DumpOptions::new()).unwrap()
+    }
+
+    // Which blocks of the first table have been decoded.
+    fn decoded(catalog: &MessageCatalog) -> Vec<bool> {
+        catalog.inner.tables[0]
+            .entries
+            .iter()
+            .map(|cell| cell.get().is_some())
+            .collect()
+    }
+
+    #[test]
+    fn blocks_are_decoded_when_looked_up() {
+        let catalog = catalog(vec![raw(0x409, &[1, 2, 0x10, 0x20])]);
+        assert_eq!(decoded(&catalog), [false, false, false]);
+        assert_eq!(catalog.get(0x10).unwrap().text(), text(0x10, 0x409));
+        assert_eq!(decoded(&catalog), [false, true, false]);
+        assert_eq!(catalog.by_range(0..=2).count(), 2);
+        assert_eq!(decoded(&catalog), [true, true, false]);
+        assert!(catalog.get(0x11).is_none());
+        assert_eq!(decoded(&catalog), [true, true, false]);
+    }
+
+    #[test]
+    fn strict_catalogs_check_every_block() {
+        // Gives the second block's entry flags no encoding has.
+        let corrupt = || {
+            let mut table = raw(0x409, &[1, 0x10]);
+            let at = 4 + BLOCK_SIZE + 8;
+            let offset = u32::from_le_bytes(table.data[at..at + 4].try_into().unwrap()) as usize;
+            table.data[offset + 2] = 7;
+            vec![table]
+        };
+
+        assert!(MessageCatalog::from_raw_tables(corrupt(), &DumpOptions::new()).is_err());
+        let lenient =
+            MessageCatalog::from_raw_tables(corrupt(), &DumpOptions::new().strict(false)).unwrap();
+        assert_eq!(lenient.get(1).unwrap().text(), text(1, 0x409));
+        assert!(lenient.get(0x10).is_none());
+    }
+
+    #[test]
+    fn from_entries_keeps_entries() {
+        let entries: Vec<MessageEntry> = catalog(vec![raw(0x409, &[1, 2, 0x10]), raw(0x407, &[1])])
+            .iter()
+            .cloned()
+            .collect();
+        let rebuilt = MessageCatalog::from_entries(entries.clone());
+        assert_eq!(rebuilt.iter().cloned().collect::<Vec<_>>(), entries);
+        assert_eq!(rebuilt.languages().collect::<Vec<_>>(), [0x407, 0x409]);
+        assert_eq!(rebuilt.get(1).unwrap().lang(), 0x407);
+        assert_eq!(rebuilt.get_lang(1, 0x409).unwrap().text(), text(1, 0x409));
+        assert!(rebuilt.get(3).is_none());
+        assert!(rebuilt
+            .save(std::env::temp_dir().join("from_entries.mcat"))
+            .is_err());
+    }
+
+    #[test]
+    fn merge_combines_tables() {
+        let from_entries =
+            MessageCatalog::from_entries(catalog(vec![raw(0x40c, &[4])]).iter().cloned().collect());
+        let merged = MessageCatalog::merge(&[
+            catalog(vec![raw(0x409, &[1, 2])]),
+            catalog(vec![raw(0x407, &[2, 3])]),
+            from_entries,
+        ])
+        .unwrap();
+        assert_eq!(
+            merged.languages().collect::<Vec<_>>(),
+            [0x407, 0x409, 0x40c]
+        );
+        assert_eq!(merged.len(), 5);
+        assert_eq!(merged.get(2).unwrap().lang(), 0x407);
+        assert_eq!(merged.get_lang(2, 0x409).unwrap().text(), text(2, 0x409));
+        assert_eq!(merged.get(4).unwrap().text(), text(4, 0x40c));
+    }
+
+    #[test]
+    fn facilities_come_from_block_bounds() {
+        let raws = || {
+            vec![raw(
+                0x409,
+                &[
+                    0x0001_ffff,
+                    0x0002_0000,
+                    0x0004_0005,
+                    0x8007_0001,
+                    0x8007_0002,
+                ],
+            )]
+        };
+        let all = catalog(raws());
+        assert_eq!(all.facilities().collect::<Vec<_>>(), [1, 2, 4, 7]);
+        assert_eq!(decoded(&all), [false, false, false]);
+
+        let options = DumpOptions::new().id_range(0x0002_0000..=0x0002_ffff);
+        let some = MessageCatalog::from_raw_tables(raws(), &options).unwrap();
+        assert_eq!(some.facilities().collect::<Vec<_>>(), [2]);
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("{}-{}.mcat", name, std::process::id()))
+    }
+
+    fn load_error(name: &str, data: &[u8]) -> String {
+        let path = temp_path(name);
+        fs::write(&path, data).unwrap();
+        let result = MessageCatalog::load(&path);
+        fs::remove_file(&path).unwrap();
+        match result {
+            Ok(_) => panic!("{} loaded", name),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    fn saved_catalog() -> MessageCatalog {
+        let mut named = raw(0x407, &[1, 2]);
+        named.resource = ResourceId::Name("STRINGS".to_string());
+        catalog(vec![raw(0x409, &[1, 0x10]), named])
+    }
+
+    fn snapshot() -> Vec<u8> {
+        let path = temp_path("snapshot");
+        saved_catalog().save(&path).unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        data
+    }
+
+    #[test]
+    fn snapshots_round_trip() {
+        let saved = saved_catalog();
+        let path = temp_path("round-trip");
+        saved.save(&path).unwrap();
+        let loaded = MessageCatalog::load(&path);
+        let only = MessageCatalog::load_with(
+            &path,
+            &DumpOptions::new().languages(Languages::Only(vec![0x409])),
+        );
+        fs::remove_file(&path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(
+            loaded.iter().collect::<Vec<_>>(),
+            saved.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            loaded.get(2).unwrap().resource(),
+            &ResourceId::Name("STRINGS".to_string())
+        );
+        assert_eq!(only.unwrap().languages().collect::<Vec<_>>(), [0x409]);
+    }
+
+    #[test]
+    fn snapshots_need_the_magic() {
+        let mut data = snapshot();
+        data[..4].copy_from_slice(b"MCAX");
+        assert!(load_error("magic", &data).contains("is not a valid snapshot"));
+    }
+
+    #[test]
+    fn other_snapshot_versions_are_rejected() {
+        let mut data = snapshot();
+        data[4..8].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+        assert!(load_error("version", &data)
+            .contains(&format!("is a version {} snapshot", SNAPSHOT_VERSION + 1)));
+    }
+
+    #[test]
+    fn truncated_snapshots_are_rejected() {
+        let data = snapshot();
+        for len in 0..data.len() {
+            assert!(load_error("truncated", &data[..len]).contains("is not a valid snapshot"));
+        }
+    }
+}
@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::mem::ManuallyDrop;
+use std::path::Path;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::System::Com::*;
+use windows::Win32::System::Ole::*;
+use windows::Win32::System::Registry::*;
+
+use crate::catalog::MessageCatalog;
+use crate::policy::LoadPolicy;
+
+// The class scripts create with CreateObject("DumpMessageTables.Catalog").
+pub const CLSID_CATALOG: GUID = GUID::from_u128(0x5c1e7a4b_93d2_4f0e_8b6a_2d4f1c9e7a31);
+const CLSID_CATALOG_STRING: &str = "{5C1E7A4B-93D2-4F0E-8B6A-2D4F1C9E7A31}";
+pub const PROG_ID: &str = "DumpMessageTables.Catalog";
+
+const CLASSES_KEY: &str = "Software\\Classes";
+
+const DISPID_LOOKUP: i32 = 1;
+const DISPID_DUMP: i32 = 2;
+const METHODS: &[(&str, i32)] = &[("Lookup", DISPID_LOOKUP), ("Dump", DISPID_DUMP)];
+
+// Set once the server process's last reference, from an object or LockServer, is released,
+// so the server can exit.
+static SHUTDOWN: (Mutex<bool>, Condvar) = (Mutex::new(false), Condvar::new());
+
+// How long the server waits for its client to create an object before it may exit.
+const STARTUP_GRACE: Duration = Duration::from_secs(30);
+
+// Late-bound methods for scripts:
+//
+//   Lookup(module, id[, lang]): the text of message id, in lang or else the lowest-numbered
+//     language that has it, or Empty if the module doesn't define it.
+//   Dump(module): every entry, one per line as ID<TAB>LANGID<TAB>text, with the text's own
+//     line breaks replaced by spaces.
+//
+// Bare module names are found as the command-line tool finds them. Each module is parsed
+// offline, and kept for later calls.
+#[implement(IDispatch)]
+struct Catalog {
+    policy: LoadPolicy,
+    catalogs: Mutex<HashMap<String, MessageCatalog>>,
+}
+
+impl Catalog {
+    fn new(policy: LoadPolicy) -> Catalog {
+        unsafe { CoAddRefServerProcess() };
+        Catalog {
+            policy,
+            catalogs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // The lock isn't held while a module's parsed, so one slow module doesn't hold up calls
+    // for others. Two calls for the same module may both parse it; the first to finish wins.
+    fn catalog(&self, module: &str) -> anyhow::Result<MessageCatalog> {
+        let poisoned = |_| Error::from(E_UNEXPECTED);
+        if let Some(catalog) = self.catalogs.lock().map_err(poisoned)?.get(module) {
+            return Ok(catalog.clone());
+        }
+        let catalog = MessageCatalog::open(self.policy.resolve(module)?)?;
+        Ok(self
+            .catalogs
+            .lock()
+            .map_err(poisoned)?
+            .entry(module.to_string())
+            .or_insert(catalog)
+            .clone())
+    }
+
+    fn lookup(&self, module: &str, id: u32, lang: Option<u16>) -> anyhow::Result<Option<String>> {
+        let catalog = self.catalog(module)?;
+        let entry = match lang {
+            Some(lang) => catalog.get_lang(id, lang),
+            None => catalog.get(id),
+        };
+        Ok(entry.map(|entry| entry.text().to_string()))
+    }
+
+    fn dump(&self, module: &str) -> anyhow::Result<String> {
+        let mut text = String::new();
+        for entry in self.catalog(module)?.iter() {
+            let line = entry
+                .text()
+                .trim_end()
+                .replace("\r\n", " ")
+                .replace('\n', " ");
+            text.push_str(&format!(
+                "{:#010x}\t{:#06x}\t{}\r\n",
+                entry.id(),
+                entry.lang(),
+                line
+            ));
+        }
+        Ok(text)
+    }
+}
+
+impl Drop for Catalog {
+    fn drop(&mut self) {
+        // There's no reporting a failure from here.
+        let _ = release_server_process();
+    }
+}
+
+// Releases a reference to the server process, signalling shutdown when it was the last.
+fn release_server_process() -> Result<()> {
+    if unsafe { CoReleaseServerProcess() } != 0 {
+        return Ok(());
+    }
+    let (done, condvar) = &SHUTDOWN;
+    *done.lock().map_err(|_| Error::from(E_UNEXPECTED))? = true;
+    condvar.notify_all();
+    Ok(())
+}
+
+impl IDispatch_Impl for Catalog {
+    fn GetTypeInfoCount(&self) -> Result<u32> {
+        Ok(0)
+    }
+
+    fn GetTypeInfo(&self, _itinfo: u32, _lcid: u32) -> Result<ITypeInfo> {
+        Err(E_NOTIMPL.into())
+    }
+
+    fn GetIDsOfNames(
+        &self,
+        _riid: *const GUID,
+        rgsznames: *const PWSTR,
+        cnames: u32,
+        _lcid: u32,
+        rgdispid: *mut i32,
+    ) -> Result<()> {
+        let names = unsafe { std::slice::from_raw_parts(rgsznames, cnames as usize) };
+        let dispids = unsafe { std::slice::from_raw_parts_mut(rgdispid, cnames as usize) };
+        let mut result = Ok(());
+        for (name, dispid) in names.iter().zip(dispids.iter_mut()) {
+            let name = unsafe { wp::utf16_to_utf8(name.0) };
+            // Names are case-insensitive, as VBScript expects. Only methods are named, so
+            // named arguments are unknown.
+            *dispid = match METHODS
+                .iter()
+                .find(|(method, _)| method.eq_ignore_ascii_case(&name))
+            {
+                Some((_, id)) => *id,
+                None => {
+                    result = Err(DISP_E_UNKNOWNNAME.into());
+                    DISPID_UNKNOWN
+                }
+            };
+        }
+        result
+    }
+
+    fn Invoke(
+        &self,
+        dispidmember: i32,
+        _riid: *const GUID,
+        _lcid: u32,
+        _wflags: u16,
+        pdispparams: *const DISPPARAMS,
+        pvarresult: *mut VARIANT,
+        pexcepinfo: *mut EXCEPINFO,
+        _puargerr: *mut u32,
+    ) -> Result<()> {
+        let params = unsafe { &*pdispparams };
+        // Arguments come last first.
+        let args: Vec<&VARIANT> = if params.rgvarg.is_null() {
+            Vec::new()
+        } else {
+            unsafe { std::slice::from_raw_parts(params.rgvarg, params.cArgs as usize) }
+                .iter()
+                .rev()
+                .collect()
+        };
+        let result = match (dispidmember, args.as_slice()) {
+            (DISPID_LOOKUP, [module, id]) | (DISPID_LOOKUP, [module, id, _]) => {
+                let lang = match args.get(2) {
+                    Some(lang) => Some(to_i32(lang)? as u16),
+                    None => None,
+                };
+                self.lookup(&to_string(module)?, to_i32(id)? as u32, lang)
+            }
+            (DISPID_DUMP, [module]) => self.dump(&to_string(module)?).map(Some),
+            (DISPID_LOOKUP | DISPID_DUMP, _) => return Err(DISP_E_BADPARAMCOUNT.into()),
+            _ => return Err(DISP_E_MEMBERNOTFOUND.into()),
+        };
+        match result {
+            Ok(text) => {
+                if !pvarresult.is_null() {
+                    unsafe { *pvarresult = text.map_or_else(VARIANT::default, string_variant) };
+                }
+                Ok(())
+            }
+            // Scripts see the description as the error's message.
+            Err(e) => {
+                if !pexcepinfo.is_null() {
+                    unsafe {
+                        *pexcepinfo = EXCEPINFO {
+                            bstrSource: ManuallyDrop::new(BSTR::from(PROG_ID)),
+                            bstrDescription: ManuallyDrop::new(BSTR::from(format!("{:#}", e))),
+                            scode: e.downcast_ref::<Error>().map_or(E_FAIL, Error::code).0,
+                            ..Default::default()
+                        };
+                    }
+                }
+                Err(DISP_E_EXCEPTION.into())
+            }
+        }
+    }
+}
+
+// Script values, which VBScript often passes by reference, coerced to what a method takes.
+// The coerced copy is cleared once read.
+fn coerce<T>(value: &VARIANT, vt: VARENUM, read: impl FnOnce(&VARIANT) -> T) -> Result<T> {
+    let mut coerced = VARIANT::default();
+    unsafe { VariantChangeType(&mut coerced, value, 0, vt) }
+        .map_err(|_| Error::from(DISP_E_TYPEMISMATCH))?;
+    let result = read(&coerced);
+    unsafe {
+        let _ = VariantClear(&mut coerced);
+    }
+    Ok(result)
+}
+
+fn to_string(value: &VARIANT) -> Result<String> {
+    coerce(value, VT_BSTR, |coerced| unsafe {
+        coerced.Anonymous.Anonymous.Anonymous.bstrVal.to_string()
+    })
+}
+
+fn to_i32(value: &VARIANT) -> Result<i32> {
+    coerce(value, VT_I4, |coerced| unsafe {
+        coerced.Anonymous.Anonymous.Anonymous.lVal
+    })
+}
+
+fn string_variant(text: String) -> VARIANT {
+    let mut variant = VARIANT::default();
+    unsafe {
+        let inner = &mut *variant.Anonymous.Anonymous;
+        inner.vt = VT_BSTR;
+        inner.Anonymous.bstrVal = ManuallyDrop::new(BSTR::from(text));
+    }
+    variant
+}
+
+#[implement(IClassFactory)]
+struct CatalogFactory {
+    policy: LoadPolicy,
+}
+
+impl IClassFactory_Impl for CatalogFactory {
+    fn CreateInstance(
+        &self,
+        punkouter: &Option<IUnknown>,
+        riid: *const GUID,
+        ppvobject: *mut *mut c_void,
+    ) -> Result<()> {
+        if punkouter.is_some() {
+            return Err(CLASS_E_NOAGGREGATION.into());
+        }
+        let dispatch: IDispatch = Catalog::new(self.policy.clone()).into();
+        unsafe { dispatch.query(&*riid, ppvobject as *mut *const c_void).ok() }
+    }
+
+    fn LockServer(&self, flock: BOOL) -> Result<()> {
+        if !flock.as_bool() {
+            return release_server_process();
+        }
+        unsafe { CoAddRefServerProcess() };
+        Ok(())
+    }
+}
+
+// Serves the class to COM until the last object is released and no client has the server
+// locked. COM starts the process this way when a client creates an object and no server is
+// running; one started otherwise exits after STARTUP_GRACE if nothing uses it.
+pub fn serve(policy: LoadPolicy) -> anyhow::Result<()> {
+    unsafe { CoInitializeEx(None, COINIT_MULTITHREADED) }
+        .map_err(|e| anyhow!("failed to initialize COM: {}", e.message()))?;
+    let factory: IClassFactory = CatalogFactory { policy }.into();
+    let cookie = unsafe {
+        CoRegisterClassObject(
+            &CLSID_CATALOG,
+            &factory,
+            CLSCTX_LOCAL_SERVER,
+            REGCLS_MULTIPLEUSE | REGCLS_SUSPENDED,
+        )
+    }
+    .map_err(|e| anyhow!("failed to register the class object: {}", e.message()))?;
+    unsafe { CoResumeClassObjects() }
+        .map_err(|e| anyhow!("failed to register the class object: {}", e.message()))?;
+
+    // The server's own reference keeps it up until its client has had the chance to create
+    // an object.
+    unsafe { CoAddRefServerProcess() };
+    thread::sleep(STARTUP_GRACE);
+    release_server_process().map_err(|e| anyhow!("failed to release the server: {}", e))?;
+
+    let poisoned = |_| anyhow!("the server's shutdown signal was poisoned");
+    let (done, condvar) = &SHUTDOWN;
+    let mut done = done.lock().map_err(poisoned)?;
+    while !*done {
+        done = condvar.wait(done).map_err(poisoned)?;
+    }
+    drop(done);
+    unsafe {
+        let _ = CoRevokeClassObject(cookie);
+        CoUninitialize();
+    }
+    Ok(())
+}
+
+// Registers exe as the class's local server, for the current user or, with all_users, for
+// every user, which needs administrator rights.
+pub fn register(exe: &Path, all_users: bool) -> anyhow::Result<()> {
+    let root = classes_root(all_users);
+    let clsid = CLSID_CATALOG_STRING;
+    let server = format!("\"{}\" com-server", exe.display());
+    set_string(root, &format!("CLSID\\{}", clsid), "", PROG_ID)?;
+    set_string(
+        root,
+        &format!("CLSID\\{}\\LocalServer32", clsid),
+        "",
+        &server,
+    )?;
+    set_string(root, &format!("CLSID\\{}\\ProgID", clsid), "", PROG_ID)?;
+    set_string(root, &format!("{}\\CLSID", PROG_ID), "", clsid)?;
+    Ok(())
+}
+
+pub fn unregister(all_users: bool) -> anyhow::Result<()> {
+    let root = classes_root(all_users);
+    for key in [
+        format!("CLSID\\{}", CLSID_CATALOG_STRING),
+        PROG_ID.to_string(),
+    ] {
+        let key_utf16 = wp::utf8_to_utf16(&format!("{}\\{}", CLASSES_KEY, key));
+        let result = unsafe { RegDeleteTreeW(root, PCWSTR(key_utf16.as_ptr())) };
+        // Unregistering what was never registered isn't an error.
+        if result != ERROR_SUCCESS && result != ERROR_FILE_NOT_FOUND {
+            return Err(anyhow!(
+                "failed to delete {}: {}",
+                key,
+                Error::from(result.to_hresult()).message()
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn classes_root(all_users: bool) -> HKEY {
+    if all_users {
+        HKEY_LOCAL_MACHINE
+    } else {
+        HKEY_CURRENT_USER
+    }
+}
+
+// Creates the key under Software\Classes if needed.
+fn set_string(root: HKEY, key: &str, name: &str, value: &str) -> anyhow::Result<()> {
+    let key_utf16 = wp::utf8_to_utf16(&format!("{}\\{}", CLASSES_KEY, key));
+    let name_utf16 = wp::utf8_to_utf16(name);
+    let value_utf16 = wp::utf8_to_utf16(value);
+    let result = unsafe {
+        RegSetKeyValueW(
+            root,
+            PCWSTR(key_utf16.as_ptr()),
+            PCWSTR(name_utf16.as_ptr()),
+            REG_SZ.0,
+            Some(value_utf16.as_ptr() as *const c_void),
+            (value_utf16.len() * 2) as u32,
+        )
+    };
+    if result != ERROR_SUCCESS {
+        return Err(anyhow!(
+            "failed to write {}: {}",
+            key,
+            Error::from(result.to_hresult()).message()
+        ));
+    }
+    Ok(())
+}
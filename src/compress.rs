@@ -0,0 +1,117 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use clap::ValueEnum;
+use flate2::write::GzEncoder;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Zstd => "zst",
+        }
+    }
+}
+
+// zstd's default level, which compresses text well at little more cost than gzip's.
+const ZSTD_LEVEL: i32 = 3;
+
+// Where output goes: stdout, or a file compressed as it's written when compression is
+// given. finish has to be called once everything's written, so an error writing the end of a
+// compressed stream fails the run rather than leaving a truncated file behind.
+pub struct Writer(Inner);
+
+enum Inner {
+    Stdout(io::StdoutLock<'static>),
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Writer {
+    pub fn stdout() -> Writer {
+        Writer(Inner::Stdout(io::stdout().lock()))
+    }
+
+    // Writes the end of a compressed stream and flushes everything to the file.
+    pub fn finish(&mut self) -> io::Result<()> {
+        match &mut self.0 {
+            Inner::Stdout(out) => out.flush(),
+            Inner::Plain(file) => file.flush(),
+            Inner::Gzip(encoder) => {
+                encoder.try_finish()?;
+                encoder.get_mut().flush()
+            }
+            Inner::Zstd(encoder) => {
+                encoder.do_finish()?;
+                encoder.get_mut().flush()
+            }
+        }
+    }
+}
+
+impl Write for Writer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            Inner::Stdout(out) => out.write(buf),
+            Inner::Plain(file) => file.write(buf),
+            Inner::Gzip(encoder) => encoder.write(buf),
+            Inner::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.0 {
+            Inner::Stdout(out) => out.flush(),
+            Inner::Plain(file) => file.flush(),
+            Inner::Gzip(encoder) => encoder.flush(),
+            Inner::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+// A Writer handed to something that keeps it, such as a formatter, that can still be
+// finished once that's done with it. Clones share the same writer.
+#[derive(Clone)]
+pub struct SharedWriter(Rc<RefCell<Writer>>);
+
+impl SharedWriter {
+    pub fn new(writer: Writer) -> SharedWriter {
+        SharedWriter(Rc::new(RefCell::new(writer)))
+    }
+
+    pub fn finish(&self) -> io::Result<()> {
+        self.0.borrow_mut().finish()
+    }
+}
+
+impl Write for SharedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+// Creates path for writing, compressed as it's written when compression is given.
+pub fn create(path: &Path, compression: Option<Compression>) -> io::Result<Writer> {
+    let file = BufWriter::new(File::create(path)?);
+    Ok(Writer(match compression {
+        None => Inner::Plain(file),
+        Some(Compression::Gzip) => {
+            Inner::Gzip(GzEncoder::new(file, flate2::Compression::default()))
+        }
+        Some(Compression::Zstd) => Inner::Zstd(zstd::Encoder::new(file, ZSTD_LEVEL)?),
+    }))
+}
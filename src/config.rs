@@ -0,0 +1,72 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::facility::FacilityRange;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    #[serde(rename = "facility")]
+    pub facilities: Vec<FacilityRange>,
+    #[serde(rename = "component")]
+    pub components: Vec<ComponentRange>,
+    // Directories whose modules may still be loaded with --no-loadlibrary.
+    pub trusted_dirs: Vec<PathBuf>,
+    // Where explain --format errlook reads symbolic names from [default: the newest
+    // Windows SDK installed].
+    pub sdk_include_dir: Option<PathBuf>,
+    pub lint: LintConfig,
+}
+
+// Defaults for the lint subcommand's options.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LintConfig {
+    pub max_entries_per_facility: Option<usize>,
+    pub max_resource_size: Option<usize>,
+}
+
+// Maps a range of message IDs to the subsystem that owns them.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ComponentRange {
+    pub first: u32,
+    pub last: u32,
+    pub name: String,
+}
+
+impl ComponentRange {
+    pub fn contains(&self, id: u32) -> bool {
+        (self.first..=self.last).contains(&id)
+    }
+}
+
+impl Config {
+    // An explicitly given path must exist; the default location is optional.
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Config> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => match default_path() {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Config::default()),
+            },
+        };
+
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read the config file {}", path.display()))?;
+        toml::from_str(&text)
+            .with_context(|| format!("failed to parse the config file {}", path.display()))
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    let app_data = env::var_os("APPDATA")?;
+    Some(
+        Path::new(&app_data)
+            .join("dump_msg_tables")
+            .join("config.toml"),
+    )
+}
@@ -0,0 +1,50 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Severity {
+    Success,
+    Informational,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            Severity::Success => "Success",
+            Severity::Informational => "Informational",
+            Severity::Warning => "Warning",
+            Severity::Error => "Error",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+// Message IDs share the NTSTATUS layout:
+//   bits 30-31: severity
+//   bit 29:     customer
+//   bits 16-27: facility
+//   bits 0-15:  code
+pub struct DecodedId {
+    pub severity: Severity,
+    pub customer: bool,
+    pub facility: u16,
+    pub code: u16,
+}
+
+impl DecodedId {
+    pub fn new(id: u32) -> DecodedId {
+        let severity = match id >> 30 {
+            0 => Severity::Success,
+            1 => Severity::Informational,
+            2 => Severity::Warning,
+            _ => Severity::Error,
+        };
+        DecodedId {
+            severity,
+            customer: id & 0x2000_0000 != 0,
+            facility: ((id >> 16) & 0xfff) as u16,
+            code: id as u16,
+        }
+    }
+}
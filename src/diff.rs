@@ -0,0 +1,346 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context};
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::output::{self, LangFormat};
+use crate::policy::LoadPolicy;
+use crate::scan::{self, CancellationToken, NameFilter};
+use crate::table::MessageTable;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DiffFormat {
+    Text,
+    Json,
+}
+
+// A module of one build tree and its counterpart in the other, if it has one.
+#[derive(Debug, Clone)]
+pub struct ModulePair {
+    // The module's path relative to its tree, as the newer tree has it.
+    pub name: String,
+    pub old: Option<PathBuf>,
+    pub new: Option<PathBuf>,
+}
+
+// Pairs old_files, found under old_dir, with new_files, found under new_dir, by their paths
+// relative to the trees and, failing that, by file name when only one module of that name is
+// left on either side. Names are compared case-insensitively.
+pub fn pair_modules(
+    old_dir: &Path,
+    old_files: &[PathBuf],
+    new_dir: &Path,
+    new_files: &[PathBuf],
+) -> Vec<ModulePair> {
+    let key = |dir: &Path, file: &Path| {
+        file.strip_prefix(dir)
+            .unwrap_or(file)
+            .to_string_lossy()
+            .to_lowercase()
+    };
+    let mut unmatched_old: BTreeMap<String, &PathBuf> = old_files
+        .iter()
+        .map(|file| (key(old_dir, file), file))
+        .collect();
+    let mut pairs = Vec::new();
+    let mut unmatched_new = Vec::new();
+    for file in new_files {
+        let relative = key(new_dir, file);
+        match unmatched_old.remove(&relative) {
+            Some(old) => pairs.push(ModulePair {
+                name: display_name(new_dir, file),
+                old: Some(old.clone()),
+                new: Some(file.clone()),
+            }),
+            None => unmatched_new.push(file),
+        }
+    }
+
+    // Modules that moved between directories.
+    let file_name = |file: &Path| {
+        file.file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_lowercase()
+    };
+    let mut old_names: HashMap<String, Vec<String>> = HashMap::new();
+    for (relative, old) in &unmatched_old {
+        old_names
+            .entry(file_name(old))
+            .or_default()
+            .push(relative.clone());
+    }
+    let mut new_names: HashMap<String, usize> = HashMap::new();
+    for file in &unmatched_new {
+        *new_names.entry(file_name(file)).or_default() += 1;
+    }
+    for file in unmatched_new {
+        let name = file_name(file);
+        let old = match old_names.get(&name) {
+            Some(relatives) if relatives.len() == 1 && new_names[&name] == 1 => {
+                unmatched_old.remove(&relatives[0])
+            }
+            _ => None,
+        };
+        pairs.push(ModulePair {
+            name: display_name(new_dir, file),
+            old: old.cloned(),
+            new: Some(file.clone()),
+        });
+    }
+    for old in unmatched_old.into_values() {
+        pairs.push(ModulePair {
+            name: display_name(old_dir, old),
+            old: Some(old.clone()),
+            new: None,
+        });
+    }
+    pairs.sort_by_key(|pair| pair.name.to_lowercase());
+    pairs
+}
+
+fn display_name(dir: &Path, file: &Path) -> String {
+    file.strip_prefix(dir)
+        .unwrap_or(file)
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+// A message that differs between the builds.
+#[derive(Debug, Clone, Serialize)]
+pub struct Change {
+    pub id: u32,
+    pub lang: u16,
+    pub kind: ChangeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_text: Option<String>,
+}
+
+// Compares messages by ID and language, whichever resource they're in. Only the first
+// definition of an ID in a language counts, as with FormatMessage.
+pub fn diff_tables(old: &[MessageTable], new: &[MessageTable]) -> Vec<Change> {
+    let messages = |tables: &[MessageTable]| {
+        let mut messages: BTreeMap<(u32, u16), String> = BTreeMap::new();
+        for table in tables {
+            for entry in &table.entries {
+                messages
+                    .entry((entry.id, table.lang))
+                    .or_insert_with(|| entry.text.clone());
+            }
+        }
+        messages
+    };
+    let mut old = messages(old);
+    let mut changes = Vec::new();
+    for ((id, lang), new_text) in messages(new) {
+        let (kind, old_text) = match old.remove(&(id, lang)) {
+            Some(old_text) if old_text == new_text => continue,
+            Some(old_text) => (ChangeKind::Changed, Some(old_text)),
+            None => (ChangeKind::Added, None),
+        };
+        changes.push(Change {
+            id,
+            lang,
+            kind,
+            old_text,
+            new_text: Some(new_text),
+        });
+    }
+    for ((id, lang), old_text) in old {
+        changes.push(Change {
+            id,
+            lang,
+            kind: ChangeKind::Removed,
+            old_text: Some(old_text),
+            new_text: None,
+        });
+    }
+    changes.sort_by_key(|change| (change.id, change.lang));
+    changes
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ModuleStatus {
+    // Only in the new build.
+    Added,
+    // Only in the old build.
+    Removed,
+    Changed,
+    Unchanged,
+    // Either build's copy couldn't be read.
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ModuleDiff {
+    pub module: String,
+    pub status: ModuleStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<Change>,
+}
+
+// How many pairs of modules are read at a time, so memory doesn't grow with the trees.
+const PAIRS_PER_SCAN: usize = 64;
+
+// Reads every module under old_dir and new_dir that filter admits and compares each with its
+// counterpart, a batch of pairs at a time. Modules only in one tree have all their messages
+// added or removed. The trees mustn't overlap, or modules in both would be paired with
+// themselves or reported as moved.
+pub fn diff_trees(
+    old_dir: &Path,
+    new_dir: &Path,
+    policy: &LoadPolicy,
+    filter: &NameFilter,
+) -> anyhow::Result<Vec<ModuleDiff>> {
+    let canonical = |dir: &Path| {
+        fs::canonicalize(dir).with_context(|| format!("failed to read {}", dir.display()))
+    };
+    let (old_root, new_root) = (canonical(old_dir)?, canonical(new_dir)?);
+    if old_root.starts_with(&new_root) || new_root.starts_with(&old_root) {
+        bail!(
+            "{} and {} overlap; the builds must be in separate trees",
+            old_dir.display(),
+            new_dir.display()
+        );
+    }
+
+    let old_files = scan::module_files_recursive(old_dir, filter)?;
+    let new_files = scan::module_files_recursive(new_dir, filter)?;
+    let cancel = CancellationToken::new();
+    let mut diffs = Vec::new();
+    for pairs in pair_modules(old_dir, &old_files, new_dir, &new_files).chunks(PAIRS_PER_SCAN) {
+        let paths: Vec<PathBuf> = pairs
+            .iter()
+            .flat_map(|pair| pair.old.iter().chain(&pair.new))
+            .cloned()
+            .collect();
+        let results = scan::scan_modules(&paths, policy, &cancel, &())?;
+        let mut tables: HashMap<PathBuf, anyhow::Result<Vec<MessageTable>>> =
+            paths.into_iter().zip(results).collect();
+        let mut take = |path: &Option<PathBuf>| match path {
+            Some(path) => tables
+                .remove(path)
+                .unwrap_or_else(|| Err(anyhow!("{} is in both builds", path.display()))),
+            None => Ok(Vec::new()),
+        };
+
+        for pair in pairs {
+            let diff = match (take(&pair.old), take(&pair.new)) {
+                (Ok(old), Ok(new)) => {
+                    let changes = diff_tables(&old, &new);
+                    let status = match (&pair.old, &pair.new) {
+                        (None, _) => ModuleStatus::Added,
+                        (_, None) => ModuleStatus::Removed,
+                        _ if changes.is_empty() => ModuleStatus::Unchanged,
+                        _ => ModuleStatus::Changed,
+                    };
+                    ModuleDiff {
+                        module: pair.name.clone(),
+                        status,
+                        error: None,
+                        changes,
+                    }
+                }
+                (Err(e), _) | (_, Err(e)) => ModuleDiff {
+                    module: pair.name.clone(),
+                    status: ModuleStatus::Failed,
+                    error: Some(format!("{:#}", e)),
+                    changes: Vec::new(),
+                },
+            };
+            diffs.push(diff);
+        }
+    }
+    Ok(diffs)
+}
+
+// Every module of either build except the unchanged ones, with the first line of each
+// message that changed, then a count of each kind of change.
+pub fn write_text_report(
+    out: &mut dyn Write,
+    diffs: &[ModuleDiff],
+    lang_format: LangFormat,
+) -> io::Result<()> {
+    let first_line = |text: &Option<String>| {
+        text.as_deref()
+            .and_then(|text| text.lines().next())
+            .unwrap_or_default()
+            .to_string()
+    };
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+    for diff in diffs {
+        match diff.status {
+            ModuleStatus::Unchanged => continue,
+            ModuleStatus::Added => writeln!(out, "{}: only in the new build", diff.module)?,
+            ModuleStatus::Removed => writeln!(out, "{}: only in the old build", diff.module)?,
+            ModuleStatus::Failed => writeln!(
+                out,
+                "{}: {}",
+                diff.module,
+                diff.error.as_deref().unwrap_or_default()
+            )?,
+            ModuleStatus::Changed => writeln!(out, "{}:", diff.module)?,
+        }
+        for change in &diff.changes {
+            let (marker, text) = match change.kind {
+                ChangeKind::Added => {
+                    added += 1;
+                    ('+', first_line(&change.new_text))
+                }
+                ChangeKind::Removed => {
+                    removed += 1;
+                    ('-', first_line(&change.old_text))
+                }
+                ChangeKind::Changed => {
+                    changed += 1;
+                    (
+                        '~',
+                        format!(
+                            "{} -> {}",
+                            first_line(&change.old_text),
+                            first_line(&change.new_text)
+                        ),
+                    )
+                }
+            };
+            writeln!(
+                out,
+                "  {} {:>8x} [{}]: {}",
+                marker,
+                change.id,
+                output::format_lang(lang_format, change.lang),
+                text
+            )?;
+        }
+    }
+    let modules = diffs
+        .iter()
+        .filter(|diff| diff.status != ModuleStatus::Unchanged)
+        .count();
+    writeln!(
+        out,
+        "{} of {} modules differ: {} messages added, {} removed, {} changed",
+        modules,
+        diffs.len(),
+        added,
+        removed,
+        changed
+    )
+}
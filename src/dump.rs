@@ -0,0 +1,174 @@
+use std::ops::{ControlFlow, RangeInclusive};
+use std::path::Path;
+
+#[cfg(feature = "live")]
+use windows::Win32::System::LibraryLoader::*;
+
+use crate::message::MessageEntry;
+use crate::msgtable::{self, Decoding};
+use crate::table::{self, MessageTable, RawMessageTable};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Languages {
+    // Only the language the loader would pick for each resource.
+    Default,
+    All,
+    Only(Vec<u16>),
+}
+
+// Options for read_message_table_with. The defaults match what the dump command prints.
+#[derive(Debug, Clone)]
+pub struct DumpOptions {
+    pub(crate) languages: Languages,
+    pub(crate) id_ranges: Vec<RangeInclusive<u32>>,
+    pub(crate) decoding: Decoding,
+    pub(crate) strict: bool,
+    pub(crate) include_raw: bool,
+}
+
+impl Default for DumpOptions {
+    fn default() -> DumpOptions {
+        DumpOptions {
+            languages: Languages::Default,
+            id_ranges: Vec::new(),
+            decoding: Decoding::Lossy,
+            strict: true,
+            include_raw: false,
+        }
+    }
+}
+
+impl DumpOptions {
+    pub fn new() -> DumpOptions {
+        DumpOptions::default()
+    }
+
+    pub fn languages(mut self, languages: Languages) -> DumpOptions {
+        self.languages = languages;
+        self
+    }
+
+    // Entries are kept if they fall in any of the ranges. Without ranges, every entry is kept.
+    pub fn id_range(mut self, range: RangeInclusive<u32>) -> DumpOptions {
+        self.id_ranges.push(range);
+        self
+    }
+
+    pub fn decoding(mut self, decoding: Decoding) -> DumpOptions {
+        self.decoding = decoding;
+        self
+    }
+
+    // When not strict, tables that fail to parse are skipped instead of failing the read.
+    pub fn strict(mut self, strict: bool) -> DumpOptions {
+        self.strict = strict;
+        self
+    }
+
+    // Keeps each entry's undecoded bytes in Entry::raw. Otherwise raw is left empty.
+    pub fn include_raw(mut self, include_raw: bool) -> DumpOptions {
+        self.include_raw = include_raw;
+        self
+    }
+
+    pub(crate) fn wants_id(&self, id: u32) -> bool {
+        self.id_ranges.is_empty() || self.id_ranges.iter().any(|range| range.contains(&id))
+    }
+
+    // Whether any ID in ids is wanted.
+    pub(crate) fn wants_any(&self, ids: &RangeInclusive<u32>) -> bool {
+        self.id_ranges.is_empty()
+            || self
+                .id_ranges
+                .iter()
+                .any(|range| range.start() <= ids.end() && ids.start() <= range.end())
+    }
+}
+
+// Modules are loaded as data files, so reading never runs code from them.
+pub fn read_message_table_with(
+    path: impl AsRef<Path>,
+    options: &DumpOptions,
+) -> table::Result<Vec<MessageTable>> {
+    let mut tables = Vec::new();
+    for raw in read_raw_tables(path.as_ref(), options)? {
+        let parsed = match msgtable::parse_with(&raw.data, options.decoding) {
+            Ok(parsed) => parsed,
+            Err(_) if !options.strict => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut entries = parsed.entries;
+        entries.retain(|entry| options.wants_id(entry.id));
+        if !options.include_raw {
+            for entry in &mut entries {
+                entry.raw = Vec::new();
+            }
+        }
+        let mut blocks = parsed.blocks;
+        blocks.retain(|block| entries.iter().any(|entry| block.contains(entry.id)));
+        tables.push(MessageTable {
+            resource: raw.resource,
+            lang: raw.lang,
+            blocks,
+            entries,
+        });
+    }
+    Ok(tables)
+}
+
+pub fn for_each_entry<B>(
+    path: impl AsRef<Path>,
+    f: impl FnMut(MessageEntry) -> ControlFlow<B>,
+) -> table::Result<ControlFlow<B>> {
+    for_each_entry_with(path, &DumpOptions::default(), f)
+}
+
+// Streams entries to f as they're decoded instead of collecting them, stopping early if f
+// breaks. With lenient options, a table that fails to parse partway through may already
+// have passed some of its entries to f.
+pub fn for_each_entry_with<B>(
+    path: impl AsRef<Path>,
+    options: &DumpOptions,
+    mut f: impl FnMut(MessageEntry) -> ControlFlow<B>,
+) -> table::Result<ControlFlow<B>> {
+    for raw in read_raw_tables(path.as_ref(), options)? {
+        let result = msgtable::for_each_entry(&raw.data, options.decoding, |mut entry| {
+            if !options.wants_id(entry.id) {
+                return ControlFlow::Continue(());
+            }
+            if !options.include_raw {
+                entry.raw = Vec::new();
+            }
+            f(MessageEntry::new(entry, &raw.resource, raw.lang))
+        });
+        match result {
+            Ok(ControlFlow::Break(b)) => return Ok(ControlFlow::Break(b)),
+            Ok(ControlFlow::Continue(())) => {}
+            Err(_) if !options.strict => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(ControlFlow::Continue(()))
+}
+
+// With the live backend, modules are loaded with the loader so that resources are picked
+// the way Windows picks them. Otherwise they're parsed offline.
+pub(crate) fn read_raw_tables(
+    path: &Path,
+    options: &DumpOptions,
+) -> table::Result<Vec<RawMessageTable>> {
+    let all_langs = options.languages != Languages::Default;
+    #[cfg(feature = "live")]
+    let mut raw_tables = crate::sys::get_raw_message_tables(
+        &path.to_string_lossy(),
+        LOAD_LIBRARY_AS_DATAFILE | LOAD_LIBRARY_AS_IMAGE_RESOURCE,
+        all_langs,
+    )?;
+    #[cfg(not(feature = "live"))]
+    let mut raw_tables = crate::pe::read_raw_message_tables(path, all_langs)?;
+    if let Languages::Only(langs) = &options.languages {
+        raw_tables.retain(|raw| langs.contains(&raw.lang));
+    }
+    Ok(raw_tables)
+}
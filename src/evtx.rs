@@ -0,0 +1,307 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::c_void;
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::Globalization::GetUserDefaultUILanguage;
+use windows::Win32::System::EventLog::*;
+use windows::Win32::System::LibraryLoader::*;
+
+use crate::output::{self, LangFormat};
+use crate::policy::LoadPolicy;
+use crate::providers::{self, Provider, Registry};
+use crate::table::MessageTable;
+
+// Events are read from the log this many at a time.
+const EVENT_BATCH: usize = 64;
+
+// A distinct provider and event ID referenced by a log, and how many of its events use it.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EventRef {
+    pub channel: String,
+    pub provider: String,
+    // Set for manifest-based providers, in {...} form.
+    pub guid: Option<String>,
+    // For classic providers, the event ID with its qualifiers in the high word, which is the
+    // ID the message file defines.
+    pub id: u32,
+    pub count: usize,
+}
+
+// Whether an event's message can be formatted on this machine, and if not, what's missing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    Resolved,
+    // Manifest-based providers map event IDs to messages through their manifest, so only
+    // their message file is checked.
+    ManifestFilePresent,
+    UnknownProvider,
+    MissingFiles(Vec<PathBuf>),
+    MissingId,
+    // The ID is defined, but only in these languages.
+    MissingLanguage(Vec<u16>),
+}
+
+impl Resolution {
+    pub fn is_resolved(&self) -> bool {
+        matches!(self, Resolution::Resolved | Resolution::ManifestFilePresent)
+    }
+
+    pub fn describe(&self, lang_format: LangFormat) -> String {
+        match self {
+            Resolution::Resolved => "ok".to_string(),
+            Resolution::ManifestFilePresent => {
+                "ok (manifest-based; only the message file was checked)".to_string()
+            }
+            Resolution::UnknownProvider => "provider not registered".to_string(),
+            Resolution::MissingFiles(files) => {
+                let files: Vec<_> = files
+                    .iter()
+                    .map(|file| file.to_string_lossy().into_owned())
+                    .collect();
+                format!("missing message file {}", files.join(";"))
+            }
+            Resolution::MissingId => "ID not in the message files".to_string(),
+            Resolution::MissingLanguage(langs) => {
+                let langs: Vec<_> = langs
+                    .iter()
+                    .map(|&lang| output::format_lang(lang_format, lang))
+                    .collect();
+                format!("missing language; defined only in {}", langs.join(", "))
+            }
+        }
+    }
+}
+
+// Closes the handle on drop.
+struct EvtHandle(isize);
+
+impl Drop for EvtHandle {
+    fn drop(&mut self) {
+        unsafe { EvtClose(self.0) };
+    }
+}
+
+impl EvtHandle {
+    fn new(handle: isize, context: impl FnOnce() -> String) -> anyhow::Result<EvtHandle> {
+        if handle == 0 {
+            return Err(anyhow!("{}: {}", context(), wp::last_error()));
+        }
+        Ok(EvtHandle(handle))
+    }
+}
+
+// Every distinct provider and event ID in an .evtx file, sorted by channel and provider.
+pub fn read_events(path: &Path) -> anyhow::Result<Vec<EventRef>> {
+    let path_utf16 = wp::utf8_to_utf16(&path.to_string_lossy());
+    let query_utf16 = wp::utf8_to_utf16("*");
+    let query = EvtHandle::new(
+        unsafe {
+            EvtQuery(
+                0,
+                PCWSTR(path_utf16.as_ptr()),
+                PCWSTR(query_utf16.as_ptr()),
+                (EvtQueryFilePath.0 | EvtQueryForwardDirection.0) as u32,
+            )
+        },
+        || format!("failed to open {}", path.display()),
+    )?;
+    let context = EvtHandle::new(
+        unsafe { EvtCreateRenderContext(None, EvtRenderContextSystem.0 as u32) },
+        || "failed to create a render context".to_string(),
+    )?;
+
+    let mut counts: BTreeMap<EventRef, usize> = BTreeMap::new();
+    let mut buf: Vec<u64> = Vec::new();
+    loop {
+        let mut handles = [0isize; EVENT_BATCH];
+        let mut returned = 0;
+        let ok = unsafe { EvtNext(query.0, &mut handles, INFINITE, 0, &mut returned) };
+        if !ok.as_bool() {
+            if unsafe { GetLastError() } == ERROR_NO_MORE_ITEMS {
+                break;
+            }
+            return Err(anyhow!(
+                "failed to read {}: {}",
+                path.display(),
+                wp::last_error()
+            ));
+        }
+        // Every handle is wrapped before any is used, so none leak on error.
+        let events: Vec<EvtHandle> = handles[..returned as usize]
+            .iter()
+            .map(|&handle| EvtHandle(handle))
+            .collect();
+        for event in &events {
+            *counts
+                .entry(render_system(&context, event, &mut buf)?)
+                .or_default() += 1;
+        }
+    }
+    Ok(counts
+        .into_iter()
+        .map(|(event, count)| EventRef { count, ..event })
+        .collect())
+}
+
+// The system properties of an event that identify its message, with count left at zero.
+fn render_system(
+    context: &EvtHandle,
+    event: &EvtHandle,
+    buf: &mut Vec<u64>,
+) -> anyhow::Result<EventRef> {
+    let mut bytes_used = 0;
+    let mut property_count = 0;
+    loop {
+        let ok = unsafe {
+            EvtRender(
+                context.0,
+                event.0,
+                EvtRenderEventValues.0 as u32,
+                (buf.len() * 8) as u32,
+                Some(buf.as_mut_ptr() as *mut c_void),
+                &mut bytes_used,
+                &mut property_count,
+            )
+        };
+        if ok.as_bool() {
+            break;
+        }
+        if unsafe { GetLastError() } != ERROR_INSUFFICIENT_BUFFER {
+            return Err(anyhow!("failed to render an event: {}", wp::last_error()));
+        }
+        buf.resize((bytes_used as usize + 7) / 8, 0);
+    }
+    let values = unsafe {
+        std::slice::from_raw_parts(buf.as_ptr() as *const EVT_VARIANT, property_count as usize)
+    };
+    let value = |property: EVT_SYSTEM_PROPERTY_ID| values.get(property.0 as usize);
+
+    let string = |property| {
+        value(property)
+            .filter(|value| value.Type == EvtVarTypeString.0 as u32)
+            .map(|value| unsafe { wp::utf16_to_utf8(value.Anonymous.StringVal.0) })
+            .unwrap_or_default()
+    };
+    let uint16 = |property| {
+        value(property)
+            .filter(|value| value.Type == EvtVarTypeUInt16.0 as u32)
+            .map_or(0, |value| unsafe { value.Anonymous.UInt16Val })
+    };
+    let guid = value(EvtSystemProviderGuid)
+        .filter(|value| value.Type == EvtVarTypeGuid.0 as u32)
+        .map(|value| format_guid(unsafe { &*value.Anonymous.GuidVal }));
+    Ok(EventRef {
+        channel: string(EvtSystemChannel),
+        provider: string(EvtSystemProviderName),
+        guid,
+        id: (uint16(EvtSystemQualifiers) as u32) << 16 | uint16(EvtSystemEventID) as u32,
+        count: 0,
+    })
+}
+
+fn format_guid(guid: &GUID) -> String {
+    format!(
+        "{{{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}}}",
+        guid.data1,
+        guid.data2,
+        guid.data3,
+        guid.data4[0],
+        guid.data4[1],
+        guid.data4[2],
+        guid.data4[3],
+        guid.data4[4],
+        guid.data4[5],
+        guid.data4[6],
+        guid.data4[7]
+    )
+}
+
+// Checks each event against the providers registered on this machine, the way the event log
+// service would look its message up. Messages are wanted in the policy's fallback languages,
+// or the user's UI language; a language matches any region of it, and neutral tables match
+// every language.
+pub fn audit(policy: &LoadPolicy, events: &[EventRef]) -> anyhow::Result<Vec<Resolution>> {
+    let langs = match policy.lang_fallback() {
+        Some(fallback) => fallback.langs().to_vec(),
+        None => vec![unsafe { GetUserDefaultUILanguage() }],
+    };
+    let providers = providers::event_providers(&Registry::Live)?;
+    // Each list of message files is read once, however many events use it.
+    let mut tables: HashMap<Vec<PathBuf>, Vec<MessageTable>> = HashMap::new();
+    let mut resolutions = Vec::new();
+    for event in events {
+        if let Some(guid) = &event.guid {
+            resolutions.push(match providers::publisher_message_file(guid) {
+                None => Resolution::UnknownProvider,
+                Some(file) if !file.is_file() => Resolution::MissingFiles(vec![file]),
+                Some(_) => Resolution::ManifestFilePresent,
+            });
+            continue;
+        }
+        let provider = match find_provider(&providers, event) {
+            Some(provider) => provider,
+            None => {
+                resolutions.push(Resolution::UnknownProvider);
+                continue;
+            }
+        };
+        let (present, missing): (Vec<PathBuf>, Vec<PathBuf>) = provider
+            .message_files
+            .iter()
+            .cloned()
+            .partition(|file| file.is_file());
+        if present.is_empty() {
+            resolutions.push(Resolution::MissingFiles(missing));
+            continue;
+        }
+        let tables = tables
+            .entry(present.clone())
+            .or_insert_with(|| read_tables(policy, &present));
+        let defined_in: Vec<u16> = tables
+            .iter()
+            .filter(|table| table.entries.iter().any(|entry| entry.id == event.id))
+            .map(|table| table.lang)
+            .collect();
+        resolutions.push(if defined_in.is_empty() {
+            Resolution::MissingId
+        } else if defined_in.iter().any(|&lang| matches_lang(lang, &langs)) {
+            Resolution::Resolved
+        } else {
+            Resolution::MissingLanguage(defined_in)
+        });
+    }
+    Ok(resolutions)
+}
+
+// The source registered under the event's own log, or failing that, under any log.
+fn find_provider<'a>(providers: &'a [Provider], event: &EventRef) -> Option<&'a Provider> {
+    let named = |provider: &&Provider| provider.source.eq_ignore_ascii_case(&event.provider);
+    providers
+        .iter()
+        .filter(named)
+        .find(|provider| provider.log.eq_ignore_ascii_case(&event.channel))
+        .or_else(|| providers.iter().find(named))
+}
+
+// Every language of every file's tables. Files that can't be read are skipped.
+fn read_tables(policy: &LoadPolicy, files: &[PathBuf]) -> Vec<MessageTable> {
+    let mut tables = Vec::new();
+    for file in files {
+        match policy.get_message_tables(
+            &file.to_string_lossy(),
+            LOAD_LIBRARY_AS_DATAFILE | LOAD_LIBRARY_AS_IMAGE_RESOURCE,
+            true,
+        ) {
+            Ok(file_tables) => tables.extend(file_tables),
+            Err(e) => eprintln!("WARNING: {}: {}", file.display(), e),
+        }
+    }
+    tables
+}
+
+fn matches_lang(lang: u16, langs: &[u16]) -> bool {
+    lang & 0x3ff == 0 || langs.iter().any(|&wanted| wanted & 0x3ff == lang & 0x3ff)
+}
@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use clap::ValueEnum;
+
+use windows::Win32::System::LibraryLoader::*;
+
+use crate::decode::DecodedId;
+use crate::facility::{FacilityNames, Namespace};
+use crate::headers::{self, Headers};
+use crate::message::MessageEntry;
+use crate::policy::LoadPolicy;
+
+// System error messages moved from kernel32 to kernelbase, so both are consulted.
+const WIN32_MODULES: &[&str] = &["kernelbase.dll", "kernel32.dll"];
+const NTSTATUS_MODULES: &[&str] = &["ntdll.dll"];
+const NETMSG_MODULES: &[&str] = &["netmsg.dll"];
+
+// Error Lookup pads symbolic names to this width.
+const ERRLOOK_NAME_WIDTH: usize = 62;
+
+const FACILITY_WIN32: u16 = 7;
+const FACILITY_NTWIN32: u16 = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExplainFormat {
+    Text,
+    // Like the Microsoft Error Lookup Tool (err.exe).
+    Errlook,
+}
+
+struct Interpretation {
+    label: String,
+    id: u32,
+    modules: Vec<String>,
+    // The SDK header the symbolic names of this kind of code are defined in.
+    header: Option<&'static str>,
+    score: u32,
+}
+
+struct Candidate {
+    label: String,
+    module: String,
+    text: String,
+    score: u32,
+}
+
+// Caches each module's table so that several interpretations can share a load.
+struct Tables<'a> {
+    tables: HashMap<String, Vec<MessageEntry>>,
+    policy: &'a LoadPolicy,
+}
+
+impl Tables<'_> {
+    fn lookup(&mut self, module: &str, id: u32) -> Option<&str> {
+        let entries = self.tables.entry(module.to_string()).or_insert_with(|| {
+            self.policy
+                .get_message_table_entries(
+                    module,
+                    LOAD_LIBRARY_AS_DATAFILE | LOAD_LIBRARY_AS_IMAGE_RESOURCE,
+                )
+                .unwrap_or_else(|e| {
+                    eprintln!("WARNING: {}: {}", module, e);
+                    Vec::new()
+                })
+        });
+        entries
+            .iter()
+            .find(|entry| entry.id() == id)
+            .map(|entry| entry.text())
+    }
+}
+
+// Whether mod_name, a name or a path, is one of the modules Win32 error messages live in.
+pub fn is_win32_module(mod_name: &str) -> bool {
+    let file_name = Path::new(mod_name)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase());
+    file_name.map_or(false, |name| WIN32_MODULES.contains(&name.as_str()))
+}
+
+fn to_strings(modules: &[&str]) -> Vec<String> {
+    modules.iter().map(|module| module.to_string()).collect()
+}
+
+fn interpretations(code: u32, user_modules: &[String]) -> Vec<Interpretation> {
+    let decoded = DecodedId::new(code);
+    let mut interpretations = Vec::new();
+
+    if code <= 0xffff {
+        interpretations.push(Interpretation {
+            label: format!("Win32 error {}", code),
+            id: code,
+            modules: to_strings(WIN32_MODULES),
+            header: Some(headers::WINERROR_H),
+            score: 90,
+        });
+        // NERR_BASE..MAX_NERR
+        let netmsg_score = if (2100..3000).contains(&code) { 95 } else { 50 };
+        interpretations.push(Interpretation {
+            label: format!("network error {}", code),
+            id: code,
+            modules: to_strings(NETMSG_MODULES),
+            header: Some(headers::LMERR_H),
+            score: netmsg_score,
+        });
+    } else {
+        let is_failure = code & 0x8000_0000 != 0;
+        if is_failure && decoded.facility == FACILITY_WIN32 && code >> 28 == 0x8 {
+            interpretations.push(Interpretation {
+                label: format!("HRESULT_FROM_WIN32({})", decoded.code),
+                id: decoded.code.into(),
+                modules: to_strings(WIN32_MODULES),
+                header: Some(headers::WINERROR_H),
+                score: 100,
+            });
+        }
+        interpretations.push(Interpretation {
+            label: "HRESULT".to_string(),
+            id: code,
+            modules: to_strings(WIN32_MODULES),
+            header: Some(headers::WINERROR_H),
+            score: if is_failure { 80 } else { 40 },
+        });
+
+        if code >> 30 == 0x3 && decoded.facility == FACILITY_NTWIN32 {
+            interpretations.push(Interpretation {
+                label: format!("NTSTATUS_FROM_WIN32({})", decoded.code),
+                id: decoded.code.into(),
+                modules: to_strings(WIN32_MODULES),
+                header: Some(headers::WINERROR_H),
+                score: 85,
+            });
+        }
+        interpretations.push(Interpretation {
+            label: "NTSTATUS".to_string(),
+            id: code,
+            modules: to_strings(NTSTATUS_MODULES),
+            header: Some(headers::NTSTATUS_H),
+            score: if code >> 30 == 0x3 { 90 } else { 60 },
+        });
+    }
+
+    if !user_modules.is_empty() {
+        interpretations.push(Interpretation {
+            label: "message ID".to_string(),
+            id: code,
+            modules: user_modules.to_vec(),
+            header: None,
+            score: 70,
+        });
+    }
+    interpretations
+}
+
+// Every interpretation of code that some module has a message for, most likely first.
+fn candidates(code: u32, user_modules: &[String], tables: &mut Tables) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+    for interpretation in interpretations(code, user_modules) {
+        for module in &interpretation.modules {
+            if let Some(text) = tables.lookup(module, interpretation.id) {
+                candidates.push(Candidate {
+                    label: interpretation.label.clone(),
+                    module: module.clone(),
+                    text: text.trim_end().to_string(),
+                    score: interpretation.score,
+                });
+            }
+        }
+    }
+    // Stable, so ties keep the order the interpretations were listed in.
+    candidates.sort_by(|a, b| b.score.cmp(&a.score));
+    candidates
+}
+
+// Explains many codes in a row, reading each module only once.
+pub struct Explainer<'a> {
+    tables: Tables<'a>,
+}
+
+impl<'a> Explainer<'a> {
+    pub fn new(policy: &'a LoadPolicy) -> Explainer<'a> {
+        Explainer {
+            tables: Tables {
+                tables: HashMap::new(),
+                policy,
+            },
+        }
+    }
+
+    // The message of the most likely interpretation of code whose label contains
+    // prefer, or of the most likely interpretation overall if none does.
+    pub fn best(&mut self, code: u32, prefer: Option<&str>) -> Option<String> {
+        let candidates = candidates(code, &[], &mut self.tables);
+        prefer
+            .and_then(|prefer| {
+                candidates
+                    .iter()
+                    .find(|candidate| candidate.label.contains(prefer))
+            })
+            .or_else(|| candidates.first())
+            .map(|candidate| candidate.text.clone())
+    }
+}
+
+pub fn explain(
+    code: u32,
+    user_modules: &[String],
+    facilities: &FacilityNames,
+    policy: &LoadPolicy,
+) {
+    let mut tables = Tables {
+        tables: HashMap::new(),
+        policy,
+    };
+    let candidates = candidates(code, user_modules, &mut tables);
+
+    let decoded = DecodedId::new(code);
+    let facility = |namespace| {
+        facilities
+            .lookup(namespace, decoded.facility)
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| format!("{:#x}", decoded.facility))
+    };
+    println!(
+        "{:#010x} ({}): severity {}, facility {} / {}, code {:#06x}",
+        code,
+        code,
+        decoded.severity,
+        facility(Namespace::Hresult),
+        facility(Namespace::Ntstatus),
+        decoded.code
+    );
+    if candidates.is_empty() {
+        println!("  no interpretation found");
+    }
+    for (rank, candidate) in candidates.iter().enumerate() {
+        println!(
+            "  {}. {} [{}]: {}",
+            rank + 1,
+            candidate.label,
+            candidate.module,
+            candidate.text
+        );
+    }
+}
+
+// Lists every interpretation the way err.exe does: each symbolic name with the header it's
+// defined in, followed by the message. Interpretations without a name are listed by label
+// and module, and ones with neither a name nor a message are left out.
+pub fn explain_errlook(code: u32, user_modules: &[String], headers: &Headers, policy: &LoadPolicy) {
+    let mut tables = Tables {
+        tables: HashMap::new(),
+        policy,
+    };
+    let mut interpretations = interpretations(code, user_modules);
+    // Stable, so ties keep the order the interpretations were listed in.
+    interpretations.sort_by(|a, b| b.score.cmp(&a.score));
+
+    println!("# for hex {:#x} / decimal {}", code, code);
+    let mut matches = 0;
+    for interpretation in &interpretations {
+        let names = interpretation
+            .header
+            .map_or(&[][..], |header| headers.lookup(header, interpretation.id));
+        let found = interpretation.modules.iter().find_map(|module| {
+            tables
+                .lookup(module, interpretation.id)
+                .map(|text| (module.clone(), text.trim_end().to_string()))
+        });
+        if names.is_empty() && found.is_none() {
+            continue;
+        }
+        if let (true, Some((module, _))) = (names.is_empty(), &found) {
+            println!(
+                "  {:<width$} {}",
+                format!("({})", interpretation.label),
+                module,
+                width = ERRLOOK_NAME_WIDTH
+            );
+            matches += 1;
+        }
+        for name in names {
+            println!(
+                "  {:<width$} {}",
+                name,
+                interpretation.header.unwrap_or_default(),
+                width = ERRLOOK_NAME_WIDTH
+            );
+            matches += 1;
+        }
+        if let Some((_, text)) = &found {
+            for line in text.lines() {
+                println!("# {}", line.trim_end_matches('\r'));
+            }
+        }
+    }
+    println!("# {} matches found for \"{:#x}\"", matches, code);
+}
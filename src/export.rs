@@ -0,0 +1,278 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io::{self, Write};
+
+use crate::decode::{DecodedId, Severity};
+use crate::lint::Terminator;
+use crate::resource::StringEntry;
+use crate::table::{MessageTable, RawMessageTable, ResourceId};
+
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    // Gives every message the conventional CRLF terminator instead of reproducing the
+    // terminator it was compiled with.
+    pub fix_terminators: bool,
+    // Strips spaces and tabs from the end of every line.
+    pub trim_trailing_spaces: bool,
+    // Collapses runs of spaces within a line into one, leaving its indentation alone.
+    pub collapse_spaces: bool,
+    // Drops blank lines at the end of messages, so each ends with a single CRLF. Implies
+    // fix_terminators.
+    pub single_terminator: bool,
+    // Writes each message's severity and facility by name, declaring them in SeverityNames
+    // and FacilityNames, with these names for facilities. Facilities without one are named
+    // by number. Ignored for tables with IDs mc can't build that way.
+    pub facility_names: Option<BTreeMap<u16, String>>,
+}
+
+const SEVERITIES: [(Severity, u32); 4] = [
+    (Severity::Success, 0),
+    (Severity::Informational, 1),
+    (Severity::Warning, 2),
+    (Severity::Error, 3),
+];
+
+// Writes message compiler source that compiles back to the same message tables. IDs are
+// written as raw MessageId values, unless options has facility names to split them into
+// severity, facility and code with and every ID can be split. mc only sets the customer
+// and reserved bits for a whole file, so any ID with one set keeps raw IDs throughout.
+pub fn write_mc(
+    out: &mut impl Write,
+    tables: &[MessageTable],
+    options: &ExportOptions,
+) -> io::Result<()> {
+    let langs: BTreeSet<u16> = tables.iter().map(|table| table.lang).collect();
+    let mut messages: BTreeMap<u32, Vec<(u16, &str)>> = BTreeMap::new();
+    for table in tables {
+        for entry in &table.entries {
+            messages
+                .entry(entry.id)
+                .or_default()
+                .push((table.lang, &entry.text));
+        }
+    }
+
+    let lang_names: Vec<String> = langs
+        .iter()
+        .map(|&lang| format!("{}={:#06x}:MSG{:05x}", lang_name(lang), lang, lang))
+        .collect();
+    write!(out, "LanguageNames=({})\r\n\r\n", lang_names.join(" "))?;
+
+    let facility_names = options
+        .facility_names
+        .as_ref()
+        .filter(|_| messages.keys().all(|&id| mc_can_split(id)));
+    let facilities = match facility_names {
+        Some(names) => {
+            let facilities = mc_facility_names(messages.keys().copied(), names);
+            let severity_names: Vec<String> = SEVERITIES
+                .iter()
+                .map(|(severity, value)| format!("{}={:#x}", severity, value))
+                .collect();
+            write!(out, "SeverityNames=({})\r\n", severity_names.join(" "))?;
+            let facility_names: Vec<String> = facilities
+                .iter()
+                .map(|(facility, name)| format!("{}={:#x}", name, facility))
+                .collect();
+            write!(out, "FacilityNames=({})\r\n\r\n", facility_names.join(" "))?;
+            Some(facilities)
+        }
+        None => None,
+    };
+
+    for (id, texts) in messages {
+        match &facilities {
+            // mc carries Severity and Facility over from the previous message, so they're
+            // given for every one.
+            Some(facilities) => {
+                let decoded = DecodedId::new(id);
+                write!(out, "MessageId={:#x}\r\n", decoded.code)?;
+                write!(out, "Severity={}\r\n", decoded.severity)?;
+                write!(out, "Facility={}\r\n", facilities[&decoded.facility])?;
+            }
+            None => write!(out, "MessageId={:#x}\r\n", id)?,
+        }
+        for (lang, text) in texts {
+            write!(out, "Language={}\r\n", lang_name(lang))?;
+            write!(out, "{}\r\n.\r\n", mc_text(text, options))?;
+        }
+        write!(out, "\r\n")?;
+    }
+    Ok(())
+}
+
+// The file write_rc expects a message table's resource data in.
+pub fn bin_file_name(table: &RawMessageTable) -> String {
+    match &table.resource {
+        ResourceId::Id(id) => format!("MSG{:05}_{:04x}.bin", id, table.lang),
+        ResourceId::Name(name) => {
+            // The name comes from the module, so it mustn't be able to reach outside the
+            // directory the file's written in.
+            let name: String = name
+                .chars()
+                .map(|c| {
+                    if c.is_alphanumeric() || c == '_' || c == '-' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect();
+            format!("MSG_{}_{:04x}.bin", name, table.lang)
+        }
+    }
+}
+
+// Writes resource compiler source with a MESSAGETABLE statement for each table, naming the
+// file bin_file_name gives it, and a STRINGTABLE per language of strings. The source is
+// UTF-8, which the code_page pragma tells rc.
+pub fn write_rc(
+    out: &mut impl Write,
+    tables: &[RawMessageTable],
+    strings: &[StringEntry],
+) -> io::Result<()> {
+    write!(out, "#pragma code_page(65001)\r\n\r\n")?;
+    for table in tables {
+        write_language(out, table.lang)?;
+        write!(
+            out,
+            "{} MESSAGETABLE \"{}\"\r\n\r\n",
+            rc_resource_name(&table.resource),
+            bin_file_name(table)
+        )?;
+    }
+    let langs: BTreeSet<u16> = strings.iter().map(|entry| entry.lang).collect();
+    for lang in langs {
+        write_language(out, lang)?;
+        write!(out, "STRINGTABLE\r\nBEGIN\r\n")?;
+        for entry in strings.iter().filter(|entry| entry.lang == lang) {
+            write!(out, "    {}, \"{}\"\r\n", entry.id, rc_text(&entry.text))?;
+        }
+        write!(out, "END\r\n\r\n")?;
+    }
+    Ok(())
+}
+
+// LANGUAGE takes the primary and sublanguage of a LANGID separately.
+fn write_language(out: &mut impl Write, lang: u16) -> io::Result<()> {
+    write!(
+        out,
+        "LANGUAGE {:#04x}, {:#04x}\r\n",
+        lang & 0x3ff,
+        lang >> 10
+    )
+}
+
+// Names are quoted, so ones with spaces or that spell a keyword aren't misread.
+fn rc_resource_name(resource: &ResourceId) -> String {
+    match resource {
+        ResourceId::Id(id) => id.to_string(),
+        ResourceId::Name(name) => format!("\"{}\"", rc_text(name)),
+    }
+}
+
+// rc strings escape backslashes and control characters with a backslash, and quotes by
+// doubling them.
+fn rc_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\"\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Whether mc can build id from Severity, Facility and MessageId, i.e. whether neither the
+// customer nor the reserved bit is set.
+fn mc_can_split(id: u32) -> bool {
+    id & 0x3000_0000 == 0
+}
+
+// A unique identifier for each facility ids use, from names where possible.
+fn mc_facility_names(
+    ids: impl Iterator<Item = u32>,
+    names: &BTreeMap<u16, String>,
+) -> BTreeMap<u16, String> {
+    let facilities: BTreeSet<u16> = ids
+        .map(|id| DecodedId::new(id).facility)
+        .collect();
+    let mut used = HashSet::new();
+    let mut mc_names = BTreeMap::new();
+    for facility in facilities {
+        let name: String = match names.get(&facility) {
+            Some(name) => name
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect(),
+            None => String::new(),
+        };
+        let name = if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            format!("FACILITY_{:03X}", facility)
+        } else if used.contains(&name) {
+            // Custom facility names can cover a range of facilities.
+            format!("{}_{:03X}", name, facility)
+        } else {
+            name
+        };
+        used.insert(name.clone());
+        mc_names.insert(facility, name);
+    }
+    mc_names
+}
+
+fn lang_name(lang: u16) -> String {
+    format!("LANG_{:04X}", lang)
+}
+
+// mc ends every line of a message, including the last one, with CRLF unless the message
+// ends with %0. Messages that end without a line break get a %0 so they round-trip; bare
+// LFs can't be expressed in .mc and become CRLFs.
+fn mc_text(text: &str, options: &ExportOptions) -> String {
+    let body = text.strip_suffix('\n').unwrap_or(text);
+    let body = body.strip_suffix('\r').unwrap_or(body);
+    let mut lines: Vec<String> = body
+        .split('\n')
+        .map(|line| clean_line(line.trim_end_matches('\r'), options))
+        .collect();
+    if options.single_terminator {
+        while lines.len() > 1 && lines.last().map_or(false, |line| line.is_empty()) {
+            lines.pop();
+        }
+    }
+    let mut mc_text = lines.join("\r\n");
+    if Terminator::of(text) == Terminator::Missing
+        && !options.fix_terminators
+        && !options.single_terminator
+    {
+        mc_text.push_str("%0");
+    }
+    mc_text
+}
+
+fn clean_line(line: &str, options: &ExportOptions) -> String {
+    let line = if options.trim_trailing_spaces {
+        line.trim_end_matches([' ', '\t'])
+    } else {
+        line
+    };
+    if !options.collapse_spaces {
+        return line.to_string();
+    }
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    let mut cleaned = line[..indent].to_string();
+    let mut previous = None;
+    for c in line[indent..].chars() {
+        if c == ' ' && previous == Some(' ') {
+            continue;
+        }
+        cleaned.push(c);
+        previous = Some(c);
+    }
+    cleaned
+}
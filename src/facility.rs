@@ -0,0 +1,303 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::config::Config;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Namespace {
+    Hresult,
+    Ntstatus,
+}
+
+// From winerror.h. Facilities above 0x7ff spill into the reserved bit 27, which is why
+// facilities are always decoded as 12 bits wide.
+const HRESULT_FACILITIES: &[(u16, &str)] = &[
+    (0, "FACILITY_NULL"),
+    (1, "FACILITY_RPC"),
+    (2, "FACILITY_DISPATCH"),
+    (3, "FACILITY_STORAGE"),
+    (4, "FACILITY_ITF"),
+    (7, "FACILITY_WIN32"),
+    (8, "FACILITY_WINDOWS"),
+    (9, "FACILITY_SECURITY"),
+    (10, "FACILITY_CONTROL"),
+    (11, "FACILITY_CERT"),
+    (12, "FACILITY_INTERNET"),
+    (13, "FACILITY_MEDIASERVER"),
+    (14, "FACILITY_MSMQ"),
+    (15, "FACILITY_SETUPAPI"),
+    (16, "FACILITY_SCARD"),
+    (17, "FACILITY_COMPLUS"),
+    (18, "FACILITY_AAF"),
+    (19, "FACILITY_URT"),
+    (20, "FACILITY_ACS"),
+    (21, "FACILITY_DPLAY"),
+    (22, "FACILITY_UMI"),
+    (23, "FACILITY_SXS"),
+    (24, "FACILITY_WINDOWS_CE"),
+    (25, "FACILITY_HTTP"),
+    (26, "FACILITY_USERMODE_COMMONLOG"),
+    (27, "FACILITY_WER"),
+    (31, "FACILITY_USERMODE_FILTER_MANAGER"),
+    (32, "FACILITY_BACKGROUNDCOPY"),
+    (33, "FACILITY_CONFIGURATION"),
+    (34, "FACILITY_STATE_MANAGEMENT"),
+    (35, "FACILITY_METADIRECTORY"),
+    (36, "FACILITY_WINDOWSUPDATE"),
+    (37, "FACILITY_DIRECTORYSERVICE"),
+    (38, "FACILITY_GRAPHICS"),
+    (39, "FACILITY_SHELL"),
+    (40, "FACILITY_TPM_SERVICES"),
+    (41, "FACILITY_TPM_SOFTWARE"),
+    (42, "FACILITY_UI"),
+    (43, "FACILITY_XAML"),
+    (44, "FACILITY_ACTION_QUEUE"),
+    (48, "FACILITY_PLA"),
+    (49, "FACILITY_FVE"),
+    (50, "FACILITY_FWP"),
+    (51, "FACILITY_WINRM"),
+    (52, "FACILITY_NDIS"),
+    (53, "FACILITY_USERMODE_HYPERVISOR"),
+    (54, "FACILITY_CMI"),
+    (55, "FACILITY_USERMODE_VIRTUALIZATION"),
+    (56, "FACILITY_USERMODE_VOLMGR"),
+    (57, "FACILITY_BCD"),
+    (58, "FACILITY_USERMODE_VHD"),
+    (59, "FACILITY_USERMODE_HNS"),
+    (60, "FACILITY_SDIAG"),
+    (61, "FACILITY_WEBSERVICES"),
+    (62, "FACILITY_WPN"),
+    (63, "FACILITY_WINDOWS_STORE"),
+    (64, "FACILITY_INPUT"),
+    (66, "FACILITY_EAP"),
+    (80, "FACILITY_WINDOWS_DEFENDER"),
+    (81, "FACILITY_OPC"),
+    (82, "FACILITY_XPS"),
+    (83, "FACILITY_RAS"),
+    (84, "FACILITY_POWERSHELL"),
+    (85, "FACILITY_EAS"),
+    (98, "FACILITY_P2P_INT"),
+    (99, "FACILITY_P2P"),
+    (100, "FACILITY_DAF"),
+    (101, "FACILITY_BLUETOOTH_ATT"),
+    (102, "FACILITY_AUDIO"),
+    (103, "FACILITY_STATEREPOSITORY"),
+    (109, "FACILITY_VISUALCPP"),
+    (112, "FACILITY_SCRIPT"),
+    (113, "FACILITY_PARSE"),
+    (120, "FACILITY_BLB"),
+    (121, "FACILITY_BLB_CLI"),
+    (122, "FACILITY_WSBAPP"),
+    (128, "FACILITY_BLBUI"),
+    (129, "FACILITY_USN"),
+    (130, "FACILITY_USERMODE_VOLSNAP"),
+    (131, "FACILITY_TIERING"),
+    (133, "FACILITY_WSB_ONLINE"),
+    (134, "FACILITY_ONLINE_ID"),
+    (135, "FACILITY_DEVICE_UPDATE_AGENT"),
+    (153, "FACILITY_DLS"),
+    (160, "FACILITY_SOS"),
+    (176, "FACILITY_DEBUGGERS"),
+    (208, "FACILITY_DELIVERY_OPTIMIZATION"),
+    (231, "FACILITY_USERMODE_SPACES"),
+    (232, "FACILITY_USER_MODE_SECURITY_CORE"),
+    (234, "FACILITY_USERMODE_LICENSING"),
+    (256, "FACILITY_SPP"),
+    (257, "FACILITY_DEPLOYMENT_SERVICES_SERVER"),
+    (258, "FACILITY_DEPLOYMENT_SERVICES_IMAGING"),
+    (259, "FACILITY_DEPLOYMENT_SERVICES_MANAGEMENT"),
+    (260, "FACILITY_DEPLOYMENT_SERVICES_UTIL"),
+    (261, "FACILITY_DEPLOYMENT_SERVICES_BINLSVC"),
+    (263, "FACILITY_DEPLOYMENT_SERVICES_PXE"),
+    (264, "FACILITY_DEPLOYMENT_SERVICES_TFTP"),
+    (272, "FACILITY_DEPLOYMENT_SERVICES_TRANSPORT_MANAGEMENT"),
+    (278, "FACILITY_DEPLOYMENT_SERVICES_DRIVER_PROVISIONING"),
+    (289, "FACILITY_DEPLOYMENT_SERVICES_MULTICAST_SERVER"),
+    (290, "FACILITY_DEPLOYMENT_SERVICES_MULTICAST_CLIENT"),
+    (293, "FACILITY_DEPLOYMENT_SERVICES_CONTENT_PROVIDER"),
+    (305, "FACILITY_LINGUISTIC_SERVICES"),
+    (885, "FACILITY_WEB"),
+    (886, "FACILITY_WEB_SOCKET"),
+    (1094, "FACILITY_AUDIOSTREAMING"),
+    (1536, "FACILITY_ACCELERATOR"),
+    (1996, "FACILITY_WMAAECMA"),
+    (2168, "FACILITY_DIRECTMUSIC"),
+    (2169, "FACILITY_DIRECT3D10"),
+    (2170, "FACILITY_DXGI"),
+    (2171, "FACILITY_DXGI_DDI"),
+    (2172, "FACILITY_DIRECT3D11"),
+    (2173, "FACILITY_DIRECT3D11_DEBUG"),
+    (2174, "FACILITY_DIRECT3D12"),
+    (2175, "FACILITY_DIRECT3D12_DEBUG"),
+    (2184, "FACILITY_LEAP"),
+    (2185, "FACILITY_AUDCLNT"),
+    (2192, "FACILITY_WINML"),
+    (2200, "FACILITY_WINCODEC_DWRITE_DWM"),
+    (2201, "FACILITY_DIRECT2D"),
+    (2304, "FACILITY_DEFRAG"),
+    (2305, "FACILITY_USERMODE_SDBUS"),
+    (2306, "FACILITY_JSCRIPT"),
+    (2561, "FACILITY_PIDGENX"),
+];
+
+// From ntstatus.h.
+const NTSTATUS_FACILITIES: &[(u16, &str)] = &[
+    (0x1, "FACILITY_DEBUGGER"),
+    (0x2, "FACILITY_RPC_RUNTIME"),
+    (0x3, "FACILITY_RPC_STUBS"),
+    (0x4, "FACILITY_IO_ERROR_CODE"),
+    (0x6, "FACILITY_CODCLASS_ERROR_CODE"),
+    (0x7, "FACILITY_NTWIN32"),
+    (0x8, "FACILITY_NTCERT"),
+    (0x9, "FACILITY_NTSSPI"),
+    (0xa, "FACILITY_TERMINAL_SERVER"),
+    (0xb, "FACILITY_MUI_ERROR_CODE"),
+    (0x10, "FACILITY_USB_ERROR_CODE"),
+    (0x11, "FACILITY_HID_ERROR_CODE"),
+    (0x12, "FACILITY_FIREWIRE_ERROR_CODE"),
+    (0x13, "FACILITY_CLUSTER_ERROR_CODE"),
+    (0x14, "FACILITY_ACPI_ERROR_CODE"),
+    (0x15, "FACILITY_SXS_ERROR_CODE"),
+    (0x19, "FACILITY_TRANSACTION"),
+    (0x1a, "FACILITY_COMMONLOG"),
+    (0x1b, "FACILITY_VIDEO"),
+    (0x1c, "FACILITY_FILTER_MANAGER"),
+    (0x1d, "FACILITY_MONITOR"),
+    (0x1e, "FACILITY_GRAPHICS_KERNEL"),
+    (0x20, "FACILITY_DRIVER_FRAMEWORK"),
+    (0x21, "FACILITY_FVE_ERROR_CODE"),
+    (0x22, "FACILITY_FWP_ERROR_CODE"),
+    (0x23, "FACILITY_NDIS_ERROR_CODE"),
+    (0x24, "FACILITY_QUIC_ERROR_CODE"),
+    (0x29, "FACILITY_TPM"),
+    (0x2a, "FACILITY_RTPM"),
+    (0x35, "FACILITY_HYPERVISOR"),
+    (0x36, "FACILITY_IPSEC"),
+    (0x37, "FACILITY_VIRTUALIZATION"),
+    (0x38, "FACILITY_VOLMGR"),
+    (0x39, "FACILITY_BCD_ERROR_CODE"),
+    (0x3e, "FACILITY_WIN32K_NTUSER"),
+    (0x3f, "FACILITY_WIN32K_NTGDI"),
+    (0x40, "FACILITY_RESUME_KEY_FILTER"),
+    (0x41, "FACILITY_RDBSS"),
+    (0x42, "FACILITY_BTH_ATT"),
+    (0x43, "FACILITY_SECUREBOOT"),
+    (0x44, "FACILITY_AUDIO_KERNEL"),
+    (0x45, "FACILITY_VSM"),
+    (0x50, "FACILITY_VOLSNAP"),
+    (0x51, "FACILITY_SDBUS"),
+    (0x5c, "FACILITY_SHARED_VHDX"),
+    (0x5d, "FACILITY_SMB"),
+    (0x99, "FACILITY_INTERIX"),
+    (0xe7, "FACILITY_SPACES"),
+    (0xe8, "FACILITY_SECURITY_CORE"),
+    (0xe9, "FACILITY_SYSTEM_INTEGRITY"),
+    (0xea, "FACILITY_LICENSING"),
+    (0xeb, "FACILITY_PLATFORM_MANIFEST"),
+    (0xec, "FACILITY_APP_EXEC"),
+];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FacilityRange {
+    pub namespace: Namespace,
+    pub first: u16,
+    pub last: Option<u16>,
+    pub name: String,
+}
+
+impl FacilityRange {
+    fn contains(&self, namespace: Namespace, facility: u16) -> bool {
+        let last = self.last.unwrap_or(self.first);
+        self.namespace == namespace && (self.first..=last).contains(&facility)
+    }
+}
+
+pub struct FacilityNames {
+    custom: Vec<FacilityRange>,
+}
+
+impl FacilityNames {
+    pub fn new(config: &Config) -> FacilityNames {
+        FacilityNames {
+            custom: config.facilities.clone(),
+        }
+    }
+
+    pub fn lookup(&self, namespace: Namespace, facility: u16) -> Option<&str> {
+        // Custom ranges take precedence so in-house components can claim unused facilities.
+        if let Some(range) = self
+            .custom
+            .iter()
+            .find(|range| range.contains(namespace, facility))
+        {
+            return Some(&range.name);
+        }
+
+        let built_in = match namespace {
+            Namespace::Hresult => HRESULT_FACILITIES,
+            Namespace::Ntstatus => NTSTATUS_FACILITIES,
+        };
+        built_in
+            .iter()
+            .find(|(number, _)| *number == facility)
+            .map(|(_, name)| *name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hresult_name(facility: u16) -> Option<&'static str> {
+        HRESULT_FACILITIES
+            .iter()
+            .find(|(number, _)| *number == facility)
+            .map(|(_, name)| *name)
+    }
+
+    #[test]
+    fn hresult_facilities_match_winerror_h() {
+        let known = [
+            (7, "FACILITY_WIN32"),
+            (885, "FACILITY_WEB"),
+            (886, "FACILITY_WEB_SOCKET"),
+            (1094, "FACILITY_AUDIOSTREAMING"),
+            (1536, "FACILITY_ACCELERATOR"),
+            (1996, "FACILITY_WMAAECMA"),
+            (2168, "FACILITY_DIRECTMUSIC"),
+            (2201, "FACILITY_DIRECT2D"),
+            (2561, "FACILITY_PIDGENX"),
+        ];
+        for (facility, name) in known {
+            assert_eq!(hresult_name(facility), Some(name), "facility {}", facility);
+        }
+        assert_eq!(hresult_name(1792), None);
+        assert_eq!(hresult_name(2049), None);
+    }
+
+    #[test]
+    fn facilities_are_sorted_and_unique() {
+        for table in [HRESULT_FACILITIES, NTSTATUS_FACILITIES] {
+            assert!(table.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        }
+    }
+
+    #[test]
+    fn custom_ranges_take_precedence() {
+        let names = FacilityNames {
+            custom: vec![FacilityRange {
+                namespace: Namespace::Hresult,
+                first: 7,
+                last: Some(8),
+                name: "CONTOSO".to_string(),
+            }],
+        };
+        assert_eq!(names.lookup(Namespace::Hresult, 8), Some("CONTOSO"));
+        assert_eq!(
+            names.lookup(Namespace::Ntstatus, 7),
+            Some("FACILITY_NTWIN32")
+        );
+        assert_eq!(names.lookup(Namespace::Hresult, 885), Some("FACILITY_WEB"));
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::bail;
+use windows::core::PCWSTR;
+use windows::Win32::Globalization::*;
+
+use crate::msgtable::Entry;
+use crate::provider::ResourceProvider;
+use crate::table::{MessageTable, ResourceId, Result};
+
+const LANG_NEUTRAL: u16 = 0;
+const LANG_ENGLISH_US: u16 = 0x409;
+
+// The order languages are tried in when a module has no resources in the first one, such as
+// de-DE,de,en-US. Neutral names like de stand for every region of the language.
+#[derive(Debug, Clone, Default)]
+pub struct LangFallback {
+    names: Vec<String>,
+    langs: Vec<u16>,
+}
+
+impl LangFallback {
+    // Parses a comma-separated list of locale names, as taken by --lang-fallback.
+    pub fn parse(s: &str) -> std::result::Result<LangFallback, String> {
+        let mut fallback = LangFallback::default();
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let name_utf16 = wp::utf8_to_utf16(name);
+            let lcid = unsafe {
+                LocaleNameToLCID(PCWSTR(name_utf16.as_ptr()), LOCALE_ALLOW_NEUTRAL_NAMES)
+            };
+            if lcid == 0 {
+                return Err(format!("unknown locale name: {}", name));
+            }
+            fallback.names.push(name.to_string());
+            fallback.langs.push(lcid as u16);
+        }
+        if fallback.names.is_empty() {
+            return Err("no languages given".to_string());
+        }
+        Ok(fallback)
+    }
+
+    pub fn langs(&self) -> &[u16] {
+        &self.langs
+    }
+
+    // Makes these the process's preferred UI languages, which the loader consults before
+    // the user's and the system's when it picks a .mui satellite or a resource language.
+    // Only languages the OS lists for neither of those are overridden completely.
+    pub fn apply(&self) -> anyhow::Result<()> {
+        // A double-NUL-terminated list of NUL-terminated names.
+        let mut buffer: Vec<u16> = Vec::new();
+        for name in &self.names {
+            buffer.extend(name.encode_utf16());
+            buffer.push(0);
+        }
+        buffer.push(0);
+        let ok = unsafe {
+            SetProcessPreferredUILanguages(MUI_LANGUAGE_NAME, PCWSTR(buffer.as_ptr()), None)
+        };
+        if !ok.as_bool() {
+            bail!(
+                "failed to set the preferred UI languages: {}",
+                windows::core::Error::from_win32().message()
+            );
+        }
+        Ok(())
+    }
+
+    // The first language lang matches, if any. A neutral language matches any of its
+    // regions.
+    pub fn rank(&self, lang: u16) -> Option<usize> {
+        self.langs
+            .iter()
+            .position(|&preferred| preferred == lang || is_neutral_of(preferred, lang))
+    }
+
+    // Merges every language of each resource into the single table a user with these
+    // languages would see: each ID comes from the first language that defines it, trying
+    // each language, then its neutral form, then LANG_NEUTRAL, en-US and any other in turn.
+    // Merged tables are labelled with the first language and have no blocks.
+    pub fn overlay(&self, tables: Vec<MessageTable>) -> Vec<MessageTable> {
+        let mut resources: Vec<(ResourceId, Vec<MessageTable>)> = Vec::new();
+        for table in tables {
+            match resources
+                .iter_mut()
+                .find(|(resource, _)| *resource == table.resource)
+            {
+                Some((_, group)) => group.push(table),
+                None => resources.push((table.resource.clone(), vec![table])),
+            }
+        }
+        resources
+            .into_iter()
+            .map(|(resource, mut group)| {
+                group.sort_by_key(|table| self.overlay_rank(table.lang));
+                let mut entries: BTreeMap<u32, Entry> = BTreeMap::new();
+                for table in group {
+                    for entry in table.entries {
+                        entries.entry(entry.id).or_insert(entry);
+                    }
+                }
+                MessageTable {
+                    resource,
+                    lang: self.langs[0],
+                    blocks: Vec::new(),
+                    entries: entries.into_values().collect(),
+                }
+            })
+            .collect()
+    }
+
+    fn overlay_rank(&self, lang: u16) -> usize {
+        let count = self.langs.len();
+        self.langs
+            .iter()
+            .position(|&preferred| {
+                preferred == lang
+                    || is_neutral_of(preferred, lang)
+                    || is_neutral_of(lang, preferred)
+            })
+            .unwrap_or(match lang {
+                LANG_NEUTRAL => count,
+                LANG_ENGLISH_US => count + 1,
+                _ => count + 2,
+            })
+    }
+
+    // The .mui satellite of path the loader would pick for the first language that has one,
+    // looked for where the loader looks: a subdirectory named for the language next to it.
+    pub fn satellite(&self, path: &Path) -> Option<PathBuf> {
+        let dir = path.parent()?;
+        let mut file_name = path.file_name()?.to_os_string();
+        file_name.push(".mui");
+        let mut subdirs: Vec<String> = std::fs::read_dir(dir)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().join(&file_name).is_file())
+            .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+            .collect();
+        subdirs.sort();
+        self.names.iter().find_map(|name| {
+            subdirs
+                .iter()
+                .find(|subdir| {
+                    subdir.eq_ignore_ascii_case(name)
+                        || (!name.contains('-')
+                            && subdir
+                                .split('-')
+                                .next()
+                                .map_or(false, |language| language.eq_ignore_ascii_case(name)))
+                })
+                .map(|subdir| dir.join(subdir).join(&file_name))
+        })
+    }
+}
+
+// Reads resources through another provider, but picks each resource's default language in
+// fallback order. Resources in none of the languages keep the provider's own default.
+pub struct Preferring<'a> {
+    pub provider: &'a dyn ResourceProvider,
+    pub fallback: &'a LangFallback,
+}
+
+impl ResourceProvider for Preferring<'_> {
+    fn types(&self) -> Result<Vec<ResourceId>> {
+        self.provider.types()
+    }
+
+    fn names(&self, typ: &ResourceId) -> Result<Vec<ResourceId>> {
+        self.provider.names(typ)
+    }
+
+    fn langs(&self, typ: &ResourceId, name: &ResourceId) -> Result<Vec<u16>> {
+        self.provider.langs(typ, name)
+    }
+
+    fn data(&self, typ: &ResourceId, name: &ResourceId, lang: u16) -> Result<Vec<u8>> {
+        self.provider.data(typ, name, lang)
+    }
+
+    fn default_lang(&self, typ: &ResourceId, name: &ResourceId) -> Result<Option<u16>> {
+        let preferred = self
+            .langs(typ, name)?
+            .into_iter()
+            .filter_map(|lang| self.fallback.rank(lang).map(|rank| (rank, lang)))
+            .min();
+        match preferred {
+            Some((_, lang)) => Ok(Some(lang)),
+            None => self.provider.default_lang(typ, name),
+        }
+    }
+}
+
+// Neutral LANGIDs carry SUBLANG_NEUTRAL, which is zero.
+fn is_neutral_of(neutral: u16, lang: u16) -> bool {
+    neutral >> 10 == 0 && neutral & 0x3ff == lang & 0x3ff
+}
@@ -0,0 +1,450 @@
+use std::fmt;
+
+use regex::Regex;
+
+use crate::decode::DecodedId;
+use crate::msgtable::Entry;
+use crate::output;
+use crate::table::MessageTable;
+
+// An expression over each entry's fields, such as
+// `id >= 0x4000 && text.contains('RPC') && lang == 'en-US'`.
+//
+// Fields: id, text, lang (locale name), langid, resource, encoding, length, offset,
+// severity, customer, facility and code. Strings have contains, starts_with, ends_with,
+// matches (a regex) and to_lowercase methods.
+#[derive(Debug, Clone)]
+pub struct Filter {
+    expr: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterError(String);
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+fn error(message: impl Into<String>) -> FilterError {
+    FilterError(message.into())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Id,
+    Text,
+    Lang,
+    LangId,
+    Resource,
+    Encoding,
+    Length,
+    Offset,
+    Severity,
+    Customer,
+    Facility,
+    Code,
+}
+
+const FIELDS: &[(&str, Field)] = &[
+    ("id", Field::Id),
+    ("text", Field::Text),
+    ("lang", Field::Lang),
+    ("langid", Field::LangId),
+    ("resource", Field::Resource),
+    ("encoding", Field::Encoding),
+    ("length", Field::Length),
+    ("offset", Field::Offset),
+    ("severity", Field::Severity),
+    ("customer", Field::Customer),
+    ("facility", Field::Facility),
+    ("code", Field::Code),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitAnd,
+}
+
+#[derive(Debug, Clone)]
+enum Method {
+    Contains(Box<Expr>),
+    StartsWith(Box<Expr>),
+    EndsWith(Box<Expr>),
+    // The pattern must be a literal so it's compiled once.
+    Matches(Regex),
+    ToLowercase,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Int(i64),
+    Str(String),
+    Bool(bool),
+    Field(Field),
+    Not(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Box<Expr>, Method),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Int(i64),
+    Str(String),
+    Bool(bool),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "integer",
+            Value::Str(_) => "string",
+            Value::Bool(_) => "boolean",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+}
+
+// Longest first, so that >= isn't read as > followed by =.
+const OPERATORS: &[&str] = &[
+    "||", "&&", "==", "!=", "<=", ">=", "<", ">", "!", "&", "(", ")", ".", ",",
+];
+
+impl Filter {
+    pub fn parse(source: &str) -> Result<Filter, FilterError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+        };
+        let expr = parser.expr(0)?;
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(error(format!("unexpected {:?}", token)));
+        }
+        Ok(Filter { expr })
+    }
+
+    pub fn matches(&self, table: &MessageTable, entry: &Entry) -> Result<bool, FilterError> {
+        match eval(&self.expr, table, entry)? {
+            Value::Bool(matches) => Ok(matches),
+            value => Err(error(format!(
+                "the filter is a {}, not a boolean",
+                value.type_name()
+            ))),
+        }
+    }
+
+    // Drops the entries the filter doesn't match.
+    pub fn apply(&self, tables: &mut [MessageTable]) -> Result<(), FilterError> {
+        for table in tables {
+            let mut kept = Vec::with_capacity(table.entries.len());
+            for entry in std::mem::take(&mut table.entries) {
+                if self.matches(table, &entry)? {
+                    kept.push(entry);
+                }
+            }
+            table.entries = kept;
+        }
+        Ok(())
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, FilterError> {
+    let mut tokens = Vec::new();
+    let mut rest = source;
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c == '\'' || c == '"' {
+            let end = rest[1..]
+                .find(c)
+                .ok_or_else(|| error(format!("unterminated string {}", rest)))?;
+            tokens.push(Token::Str(rest[1..end + 1].to_string()));
+            rest = &rest[end + 2..];
+        } else if c.is_ascii_digit() {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            let literal = &rest[..end];
+            let value = match literal
+                .strip_prefix("0x")
+                .or_else(|| literal.strip_prefix("0X"))
+            {
+                Some(hex) => i64::from_str_radix(hex, 16),
+                None => literal.parse(),
+            }
+            .map_err(|_| error(format!("invalid number {}", literal)))?;
+            tokens.push(Token::Int(value));
+            rest = &rest[end..];
+        } else if c.is_alphabetic() || c == '_' {
+            let end = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            let op = OPERATORS
+                .iter()
+                .copied()
+                .find(|op| rest.starts_with(op))
+                .ok_or_else(|| error(format!("unexpected {:?}", c)))?;
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(*op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), FilterError> {
+        match self.next() {
+            Some(Token::Op(found)) if found == op => Ok(()),
+            Some(token) => Err(error(format!("expected {} but found {:?}", op, token))),
+            None => Err(error(format!("expected {} but the filter ended", op))),
+        }
+    }
+
+    // Precedence climbing: || binds loosest, then &&, then comparisons, then &.
+    fn expr(&mut self, min_precedence: u8) -> Result<Expr, FilterError> {
+        let mut lhs = self.unary()?;
+        while let Some((op, precedence)) = self.peek_op().and_then(binary_op) {
+            if precedence < min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.expr(precedence + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, FilterError> {
+        if self.peek_op() == Some("!") {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        let mut expr = self.primary()?;
+        while self.peek_op() == Some(".") {
+            self.pos += 1;
+            let name = match self.next() {
+                Some(Token::Ident(name)) => name,
+                _ => return Err(error("expected a method name after .")),
+            };
+            self.expect("(")?;
+            let method = match name.as_str() {
+                "to_lowercase" => Method::ToLowercase,
+                "contains" | "starts_with" | "ends_with" | "matches" => {
+                    let arg = self.expr(0)?;
+                    match (name.as_str(), arg) {
+                        ("contains", arg) => Method::Contains(Box::new(arg)),
+                        ("starts_with", arg) => Method::StartsWith(Box::new(arg)),
+                        ("ends_with", arg) => Method::EndsWith(Box::new(arg)),
+                        (_, Expr::Str(pattern)) => Method::Matches(
+                            Regex::new(&pattern)
+                                .map_err(|e| error(format!("invalid regex: {}", e)))?,
+                        ),
+                        _ => return Err(error("matches takes a string literal")),
+                    }
+                }
+                _ => return Err(error(format!("unknown method {}", name))),
+            };
+            self.expect(")")?;
+            expr = Expr::Call(Box::new(expr), method);
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, FilterError> {
+        match self.next() {
+            Some(Token::Int(value)) => Ok(Expr::Int(value)),
+            Some(Token::Str(value)) => Ok(Expr::Str(value)),
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" => Ok(Expr::Bool(true)),
+                "false" => Ok(Expr::Bool(false)),
+                _ => FIELDS
+                    .iter()
+                    .find(|(field_name, _)| *field_name == name)
+                    .map(|&(_, field)| Expr::Field(field))
+                    .ok_or_else(|| error(format!("unknown field {}", name))),
+            },
+            Some(Token::Op("(")) => {
+                let expr = self.expr(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(token) => Err(error(format!("unexpected {:?}", token))),
+            None => Err(error("the filter ended unexpectedly")),
+        }
+    }
+}
+
+fn binary_op(op: &str) -> Option<(BinaryOp, u8)> {
+    let op = match op {
+        "||" => (BinaryOp::Or, 1),
+        "&&" => (BinaryOp::And, 2),
+        "==" => (BinaryOp::Eq, 3),
+        "!=" => (BinaryOp::Ne, 3),
+        "<" => (BinaryOp::Lt, 3),
+        "<=" => (BinaryOp::Le, 3),
+        ">" => (BinaryOp::Gt, 3),
+        ">=" => (BinaryOp::Ge, 3),
+        "&" => (BinaryOp::BitAnd, 4),
+        _ => return None,
+    };
+    Some(op)
+}
+
+fn field(field: Field, table: &MessageTable, entry: &Entry) -> Value {
+    let decoded = DecodedId::new(entry.id);
+    match field {
+        Field::Id => Value::Int(entry.id.into()),
+        Field::Text => Value::Str(entry.text.clone()),
+        Field::Lang => Value::Str(output::locale_name(table.lang).unwrap_or_default()),
+        Field::LangId => Value::Int(table.lang.into()),
+        Field::Resource => Value::Str(table.resource.to_string()),
+        Field::Encoding => Value::Str(entry.encoding.to_string()),
+        Field::Length => Value::Int(entry.length.into()),
+        Field::Offset => Value::Int(entry.offset.into()),
+        Field::Severity => Value::Str(decoded.severity.to_string()),
+        Field::Customer => Value::Bool(decoded.customer),
+        Field::Facility => Value::Int(decoded.facility.into()),
+        Field::Code => Value::Int(decoded.code.into()),
+    }
+}
+
+fn eval(expr: &Expr, table: &MessageTable, entry: &Entry) -> Result<Value, FilterError> {
+    let value = match expr {
+        Expr::Int(value) => Value::Int(*value),
+        Expr::Str(value) => Value::Str(value.clone()),
+        Expr::Bool(value) => Value::Bool(*value),
+        Expr::Field(f) => field(*f, table, entry),
+        Expr::Not(operand) => Value::Bool(!as_bool(eval(operand, table, entry)?)?),
+        // Logical operators short-circuit.
+        Expr::Binary(BinaryOp::And, lhs, rhs) => {
+            Value::Bool(as_bool(eval(lhs, table, entry)?)? && as_bool(eval(rhs, table, entry)?)?)
+        }
+        Expr::Binary(BinaryOp::Or, lhs, rhs) => {
+            Value::Bool(as_bool(eval(lhs, table, entry)?)? || as_bool(eval(rhs, table, entry)?)?)
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let lhs = eval(lhs, table, entry)?;
+            let rhs = eval(rhs, table, entry)?;
+            binary(*op, lhs, rhs)?
+        }
+        Expr::Call(receiver, method) => {
+            let receiver = as_str(eval(receiver, table, entry)?)?;
+            let arg = |arg: &Expr| eval(arg, table, entry).and_then(as_str);
+            match method {
+                Method::Contains(needle) => Value::Bool(receiver.contains(&arg(needle)?)),
+                Method::StartsWith(prefix) => Value::Bool(receiver.starts_with(&arg(prefix)?)),
+                Method::EndsWith(suffix) => Value::Bool(receiver.ends_with(&arg(suffix)?)),
+                Method::Matches(regex) => Value::Bool(regex.is_match(&receiver)),
+                Method::ToLowercase => Value::Str(receiver.to_lowercase()),
+            }
+        }
+    };
+    Ok(value)
+}
+
+fn binary(op: BinaryOp, lhs: Value, rhs: Value) -> Result<Value, FilterError> {
+    let mismatch = |lhs: &Value, rhs: &Value| {
+        error(format!(
+            "can't compare a {} with a {}",
+            lhs.type_name(),
+            rhs.type_name()
+        ))
+    };
+    let value = match op {
+        BinaryOp::Eq | BinaryOp::Ne => {
+            if std::mem::discriminant(&lhs) != std::mem::discriminant(&rhs) {
+                return Err(mismatch(&lhs, &rhs));
+            }
+            // Languages and encodings are compared case-insensitively.
+            let equal = match (&lhs, &rhs) {
+                (Value::Str(lhs), Value::Str(rhs)) => lhs.eq_ignore_ascii_case(rhs),
+                _ => lhs == rhs,
+            };
+            Value::Bool(equal == (op == BinaryOp::Eq))
+        }
+        BinaryOp::BitAnd => Value::Int(as_int(lhs)? & as_int(rhs)?),
+        _ => {
+            let ordering = match (&lhs, &rhs) {
+                (Value::Int(lhs), Value::Int(rhs)) => lhs.cmp(rhs),
+                (Value::Str(lhs), Value::Str(rhs)) => lhs.cmp(rhs),
+                _ => return Err(mismatch(&lhs, &rhs)),
+            };
+            Value::Bool(match op {
+                BinaryOp::Lt => ordering.is_lt(),
+                BinaryOp::Le => ordering.is_le(),
+                BinaryOp::Gt => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            })
+        }
+    };
+    Ok(value)
+}
+
+fn as_bool(value: Value) -> Result<bool, FilterError> {
+    match value {
+        Value::Bool(value) => Ok(value),
+        value => Err(error(format!(
+            "expected a boolean, found a {}",
+            value.type_name()
+        ))),
+    }
+}
+
+fn as_int(value: Value) -> Result<i64, FilterError> {
+    match value {
+        Value::Int(value) => Ok(value),
+        value => Err(error(format!(
+            "expected an integer, found a {}",
+            value.type_name()
+        ))),
+    }
+}
+
+fn as_str(value: Value) -> Result<String, FilterError> {
+    match value {
+        Value::Str(value) => Ok(value),
+        value => Err(error(format!(
+            "expected a string, found a {}",
+            value.type_name()
+        ))),
+    }
+}
@@ -0,0 +1,692 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::msgtable::{
+    self, Encoding, ParseError, BLOCK_SIZE, ENTRY_HEADER_SIZE, MESSAGE_RESOURCE_ANSI,
+    MESSAGE_RESOURCE_UNICODE, MESSAGE_RESOURCE_UTF8,
+};
+use crate::table::ResourceId;
+
+const RT_MESSAGETABLE: u32 = 11;
+
+// The image is a DOS stub, the headers and a single .rsrc section.
+const FILE_ALIGNMENT: u32 = 0x200;
+const SECTION_ALIGNMENT: u32 = 0x1000;
+const PE_OFFSET: usize = 0x40;
+const SIZE_OF_OPTIONAL_HEADER: usize = 240;
+const RSRC_RVA: u32 = SECTION_ALIGNMENT;
+const IMAGE_BASE: u64 = 0x1_8000_0000;
+
+const IMAGE_FILE_MACHINE_AMD64: u16 = 0x8664;
+const IMAGE_FILE_EXECUTABLE_IMAGE: u16 = 0x2;
+const IMAGE_FILE_LARGE_ADDRESS_AWARE: u16 = 0x20;
+const IMAGE_FILE_DLL: u16 = 0x2000;
+const IMAGE_NT_OPTIONAL_HDR64_MAGIC: u16 = 0x20b;
+const IMAGE_SUBSYSTEM_WINDOWS_GUI: u16 = 2;
+const IMAGE_DLLCHARACTERISTICS_HIGH_ENTROPY_VA: u16 = 0x20;
+const IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE: u16 = 0x40;
+const IMAGE_DLLCHARACTERISTICS_NX_COMPAT: u16 = 0x100;
+const IMAGE_DIRECTORY_ENTRY_RESOURCE: usize = 2;
+const IMAGE_NUMBEROF_DIRECTORY_ENTRIES: u32 = 16;
+const IMAGE_SCN_CNT_INITIALIZED_DATA: u32 = 0x40;
+const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+
+const RESOURCE_DIRECTORY_SIZE: usize = 16;
+const RESOURCE_DIRECTORY_ENTRY_SIZE: usize = 8;
+const RESOURCE_DATA_ENTRY_SIZE: usize = 16;
+const RESOURCE_NAME_IS_STRING: u32 = 0x8000_0000;
+const RESOURCE_DATA_IS_DIRECTORY: u32 = 0x8000_0000;
+
+#[derive(Debug)]
+pub struct FixtureError {
+    err_msg: String,
+}
+
+impl fmt::Display for FixtureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid fixture: {}", self.err_msg)
+    }
+}
+
+impl std::error::Error for FixtureError {}
+
+fn invalid(err_msg: String) -> FixtureError {
+    FixtureError { err_msg }
+}
+
+pub struct FixtureEntry {
+    pub id: u32,
+    // Stored as given, so it should carry its own terminator, usually \r\n.
+    pub text: String,
+    pub encoding: Encoding,
+}
+
+impl FixtureEntry {
+    pub fn new(id: u32, text: &str, encoding: Encoding) -> FixtureEntry {
+        FixtureEntry {
+            id,
+            text: text.to_string(),
+            encoding,
+        }
+    }
+}
+
+// A single language of a single MESSAGETABLE resource to put in a fixture.
+pub struct FixtureTable {
+    pub resource: ResourceId,
+    pub lang: u16,
+    pub entries: Vec<FixtureEntry>,
+}
+
+// Encodes entries as MESSAGE_RESOURCE_DATA, with a block for every run of consecutive IDs.
+// ANSI text is stored as Latin-1.
+pub fn encode_message_table(entries: &[FixtureEntry]) -> Result<Vec<u8>, FixtureError> {
+    let mut encoded = Vec::with_capacity(entries.len());
+    for entry in entries {
+        encoded.push((entry.id, encode_entry(entry)?));
+    }
+    lay_out(encoded)
+}
+
+// Lays out encoded entries, each with its ID, as MESSAGE_RESOURCE_DATA.
+fn lay_out(mut entries: Vec<(u32, Vec<u8>)>) -> Result<Vec<u8>, FixtureError> {
+    entries.sort_by_key(|(id, _)| *id);
+    let mut runs: Vec<Vec<(u32, Vec<u8>)>> = Vec::new();
+    for (id, encoded) in entries {
+        match runs.last_mut() {
+            Some(run) if run.last().map(|(last, _)| *last) == Some(id) => {
+                return Err(invalid(format!("entry {:#x} is defined twice", id)));
+            }
+            Some(run) if run.last().map(|(last, _)| last.checked_add(1)) == Some(Some(id)) => {
+                run.push((id, encoded))
+            }
+            _ => runs.push(vec![(id, encoded)]),
+        }
+    }
+
+    let mut data = Vec::new();
+    data.extend((runs.len() as u32).to_le_bytes());
+    let mut offset = 4 + runs.len() * BLOCK_SIZE;
+    for run in &runs {
+        data.extend(run[0].0.to_le_bytes());
+        data.extend(run[run.len() - 1].0.to_le_bytes());
+        data.extend((offset as u32).to_le_bytes());
+        offset += run.iter().map(|(_, encoded)| encoded.len()).sum::<usize>();
+    }
+    for (_, encoded) in runs.into_iter().flatten() {
+        data.extend(encoded);
+    }
+    Ok(data)
+}
+
+// Text is NUL-terminated and padded out to a multiple of four bytes.
+fn encode_entry(entry: &FixtureEntry) -> Result<Vec<u8>, FixtureError> {
+    let (flags, text) = match entry.encoding {
+        Encoding::Ansi => {
+            let mut text = Vec::new();
+            for c in entry.text.chars() {
+                let byte = u8::try_from(u32::from(c)).map_err(|_| {
+                    invalid(format!(
+                        "entry {:#x} has {:?}, which isn't in Latin-1",
+                        entry.id, c
+                    ))
+                })?;
+                text.push(byte);
+            }
+            text.push(0);
+            (MESSAGE_RESOURCE_ANSI, text)
+        }
+        Encoding::Unicode => {
+            let mut text: Vec<u8> = entry
+                .text
+                .encode_utf16()
+                .flat_map(|unit| unit.to_le_bytes())
+                .collect();
+            text.extend([0, 0]);
+            (MESSAGE_RESOURCE_UNICODE, text)
+        }
+        Encoding::Utf8 => {
+            let mut text = entry.text.as_bytes().to_vec();
+            text.push(0);
+            (MESSAGE_RESOURCE_UTF8, text)
+        }
+    };
+    frame_entry(entry.id, flags, text)
+}
+
+// Puts the header on an entry's NUL-terminated text, padding it out to a multiple of four
+// bytes.
+fn frame_entry(id: u32, flags: u16, mut text: Vec<u8>) -> Result<Vec<u8>, FixtureError> {
+    while (ENTRY_HEADER_SIZE + text.len()) % 4 != 0 {
+        text.push(0);
+    }
+    let length = u16::try_from(ENTRY_HEADER_SIZE + text.len()).map_err(|_| {
+        invalid(format!(
+            "entry {:#x} is {} bytes encoded, more than an entry can hold",
+            id,
+            ENTRY_HEADER_SIZE + text.len()
+        ))
+    })?;
+    let mut encoded = Vec::with_capacity(length as usize);
+    encoded.extend(length.to_le_bytes());
+    encoded.extend(flags.to_le_bytes());
+    encoded.extend(text);
+    Ok(encoded)
+}
+
+// Where re-encoding a decoded message table doesn't reproduce the original bytes. id is None
+// for differences in the resource's layout rather than in an entry.
+pub struct RoundtripDifference {
+    pub id: Option<u32>,
+    pub message: String,
+}
+
+// Decodes a MESSAGE_RESOURCE_DATA blob, re-encodes it with encode_message_table and compares
+// the two, entry by entry and then as a whole. ANSI text is re-encoded from its stored bytes
+// rather than its decoded text, since decoding may have gone through the ANSI code page,
+// which Latin-1 doesn't reproduce.
+pub fn verify_roundtrip(data: &[u8]) -> Result<Vec<RoundtripDifference>, ParseError> {
+    let parsed = msgtable::parse(data)?;
+    let mut differences = Vec::new();
+    let mut entries = Vec::new();
+    for entry in &parsed.entries {
+        let reencoded = match entry.encoding {
+            Encoding::Ansi => {
+                let mut text: Vec<u8> = entry
+                    .raw
+                    .get(ENTRY_HEADER_SIZE..)
+                    .unwrap_or_default()
+                    .iter()
+                    .copied()
+                    .take_while(|&b| b != 0)
+                    .collect();
+                text.push(0);
+                frame_entry(entry.id, MESSAGE_RESOURCE_ANSI, text)
+            }
+            _ => encode_entry(&FixtureEntry {
+                id: entry.id,
+                text: entry.text.clone(),
+                encoding: entry.encoding,
+            }),
+        };
+        let message = match &reencoded {
+            Ok(encoded) if *encoded == entry.raw => None,
+            Ok(encoded) if encoded.len() != entry.raw.len() => Some(format!(
+                "re-encoded in {} bytes instead of {}",
+                encoded.len(),
+                entry.raw.len()
+            )),
+            Ok(encoded) => {
+                let offset = first_difference(encoded, &entry.raw);
+                Some(format!(
+                    "differs at byte {:#x} of the entry: {:#04x} instead of {:#04x}",
+                    offset, encoded[offset], entry.raw[offset]
+                ))
+            }
+            Err(e) => Some(e.err_msg.clone()),
+        };
+        if let Some(message) = message {
+            differences.push(RoundtripDifference {
+                id: Some(entry.id),
+                message,
+            });
+        }
+        if let Ok(encoded) = reencoded {
+            entries.push((entry.id, encoded));
+        }
+    }
+
+    // Layout differences are only worth reporting when no entry explains them.
+    let layout = match lay_out(entries) {
+        Ok(encoded) if encoded == data => None,
+        Ok(_) if !differences.is_empty() => None,
+        Ok(encoded) => {
+            let blocks = msgtable::read_blocks(&encoded)?;
+            if blocks.len() != parsed.blocks.len() {
+                Some(format!(
+                    "re-encoded with {} blocks instead of {}",
+                    blocks.len(),
+                    parsed.blocks.len()
+                ))
+            } else if encoded.len() != data.len() {
+                Some(format!(
+                    "re-encoded in {} bytes instead of {}",
+                    encoded.len(),
+                    data.len()
+                ))
+            } else {
+                Some(format!(
+                    "differs at offset {:#x}",
+                    first_difference(&encoded, data)
+                ))
+            }
+        }
+        Err(e) => Some(e.err_msg),
+    };
+    if let Some(message) = layout {
+        differences.push(RoundtripDifference { id: None, message });
+    }
+    Ok(differences)
+}
+
+fn first_difference(a: &[u8], b: &[u8]) -> usize {
+    a.iter()
+        .zip(b)
+        .position(|(a, b)| a != b)
+        .unwrap_or_else(|| a.len().min(b.len()))
+}
+
+// Builds a resource-only x64 DLL holding tables, which the loader and the offline parser both
+// read like any other module. The output depends only on tables, so fixtures can be
+// regenerated byte for byte.
+pub fn build_dll(tables: &[FixtureTable]) -> Result<Vec<u8>, FixtureError> {
+    let rsrc = build_resource_section(tables)?;
+    let raw_size = align(rsrc.len() as u32, FILE_ALIGNMENT);
+    let size_of_image = RSRC_RVA + align(rsrc.len() as u32, SECTION_ALIGNMENT);
+
+    let mut image = vec![0u8; FILE_ALIGNMENT as usize];
+    image[0..2].copy_from_slice(b"MZ");
+    put_u32(&mut image, 0x3c, PE_OFFSET as u32);
+    image[PE_OFFSET..PE_OFFSET + 4].copy_from_slice(b"PE\0\0");
+
+    let coff = PE_OFFSET + 4;
+    put_u16(&mut image, coff, IMAGE_FILE_MACHINE_AMD64);
+    put_u16(&mut image, coff + 2, 1);
+    put_u16(&mut image, coff + 16, SIZE_OF_OPTIONAL_HEADER as u16);
+    put_u16(
+        &mut image,
+        coff + 18,
+        IMAGE_FILE_EXECUTABLE_IMAGE | IMAGE_FILE_LARGE_ADDRESS_AWARE | IMAGE_FILE_DLL,
+    );
+
+    // The entry point is left at zero, so the loader never runs anything from the image.
+    let optional = coff + 20;
+    put_u16(&mut image, optional, IMAGE_NT_OPTIONAL_HDR64_MAGIC);
+    put_u32(&mut image, optional + 8, raw_size);
+    put_u64(&mut image, optional + 24, IMAGE_BASE);
+    put_u32(&mut image, optional + 32, SECTION_ALIGNMENT);
+    put_u32(&mut image, optional + 36, FILE_ALIGNMENT);
+    put_u16(&mut image, optional + 40, 6);
+    put_u16(&mut image, optional + 48, 6);
+    put_u32(&mut image, optional + 56, size_of_image);
+    put_u32(&mut image, optional + 60, FILE_ALIGNMENT);
+    put_u16(&mut image, optional + 68, IMAGE_SUBSYSTEM_WINDOWS_GUI);
+    put_u16(
+        &mut image,
+        optional + 70,
+        IMAGE_DLLCHARACTERISTICS_HIGH_ENTROPY_VA
+            | IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE
+            | IMAGE_DLLCHARACTERISTICS_NX_COMPAT,
+    );
+    put_u64(&mut image, optional + 72, 0x10_0000);
+    put_u64(&mut image, optional + 80, 0x1000);
+    put_u64(&mut image, optional + 88, 0x10_0000);
+    put_u64(&mut image, optional + 96, 0x1000);
+    put_u32(&mut image, optional + 108, IMAGE_NUMBEROF_DIRECTORY_ENTRIES);
+    let resource_directory = optional + 112 + IMAGE_DIRECTORY_ENTRY_RESOURCE * 8;
+    put_u32(&mut image, resource_directory, RSRC_RVA);
+    put_u32(&mut image, resource_directory + 4, rsrc.len() as u32);
+
+    let section = optional + SIZE_OF_OPTIONAL_HEADER;
+    image[section..section + 5].copy_from_slice(b".rsrc");
+    put_u32(&mut image, section + 8, rsrc.len() as u32);
+    put_u32(&mut image, section + 12, RSRC_RVA);
+    put_u32(&mut image, section + 16, raw_size);
+    put_u32(&mut image, section + 20, FILE_ALIGNMENT);
+    put_u32(
+        &mut image,
+        section + 36,
+        IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ,
+    );
+
+    image.extend(rsrc);
+    image.resize((FILE_ALIGNMENT + raw_size) as usize, 0);
+    Ok(image)
+}
+
+// The resource directory has a single type, RT_MESSAGETABLE, with a directory of languages
+// for each resource. Directories come first, then resource names, data entries and data.
+fn build_resource_section(tables: &[FixtureTable]) -> Result<Vec<u8>, FixtureError> {
+    let mut resources: BTreeMap<&ResourceId, BTreeMap<u16, Vec<u8>>> = BTreeMap::new();
+    for table in tables {
+        let data = encode_message_table(&table.entries)?;
+        if resources
+            .entry(&table.resource)
+            .or_default()
+            .insert(table.lang, data)
+            .is_some()
+        {
+            return Err(invalid(format!(
+                "resource {} has language {:#x} twice",
+                table.resource, table.lang
+            )));
+        }
+    }
+    // Named entries go before ID entries; names are sorted case-insensitively, as rc
+    // uppercases them.
+    let mut names: Vec<(&ResourceId, BTreeMap<u16, Vec<u8>>)> = resources.into_iter().collect();
+    names.sort_by_key(|(resource, _)| match resource {
+        ResourceId::Name(name) => (0, name.to_uppercase(), 0),
+        ResourceId::Id(id) => (1, String::new(), *id),
+    });
+    let named_count = names
+        .iter()
+        .filter(|(resource, _)| matches!(resource, ResourceId::Name(_)))
+        .count();
+
+    let directory_size =
+        |entries: usize| RESOURCE_DIRECTORY_SIZE + entries * RESOURCE_DIRECTORY_ENTRY_SIZE;
+    let type_directory = directory_size(1);
+    let mut lang_directory = type_directory + directory_size(names.len());
+    let mut strings = lang_directory
+        + names
+            .iter()
+            .map(|(_, langs)| directory_size(langs.len()))
+            .sum::<usize>();
+    let string_size = |name: &str| 2 + 2 * name.encode_utf16().count();
+    let mut data_entries = strings
+        + names
+            .iter()
+            .map(|(resource, _)| match resource {
+                ResourceId::Name(name) => string_size(&name.to_uppercase()),
+                ResourceId::Id(_) => 0,
+            })
+            .sum::<usize>();
+    data_entries = align(data_entries as u32, 4) as usize;
+    let data_entry_count: usize = names.iter().map(|(_, langs)| langs.len()).sum();
+    let mut data = align(
+        (data_entries + data_entry_count * RESOURCE_DATA_ENTRY_SIZE) as u32,
+        8,
+    ) as usize;
+    let total = data
+        + names
+            .iter()
+            .flat_map(|(_, langs)| langs.values())
+            .map(|blob| align(blob.len() as u32, 8) as usize)
+            .sum::<usize>();
+    let mut rsrc = vec![0u8; total];
+
+    put_directory(&mut rsrc, 0, 0, 1);
+    put_u32(&mut rsrc, RESOURCE_DIRECTORY_SIZE, RT_MESSAGETABLE);
+    put_u32(
+        &mut rsrc,
+        RESOURCE_DIRECTORY_SIZE + 4,
+        RESOURCE_DATA_IS_DIRECTORY | type_directory as u32,
+    );
+    put_directory(
+        &mut rsrc,
+        type_directory,
+        named_count,
+        names.len() - named_count,
+    );
+    for (i, (resource, langs)) in names.iter().enumerate() {
+        let entry = type_directory + directory_size(i);
+        let name = match resource {
+            ResourceId::Name(name) => {
+                let units: Vec<u16> = name.to_uppercase().encode_utf16().collect();
+                put_u16(&mut rsrc, strings, units.len() as u16);
+                for (j, unit) in units.iter().enumerate() {
+                    put_u16(&mut rsrc, strings + 2 + 2 * j, *unit);
+                }
+                let offset = strings;
+                strings += 2 + 2 * units.len();
+                RESOURCE_NAME_IS_STRING | offset as u32
+            }
+            ResourceId::Id(id) => *id as u32,
+        };
+        put_u32(&mut rsrc, entry, name);
+        put_u32(
+            &mut rsrc,
+            entry + 4,
+            RESOURCE_DATA_IS_DIRECTORY | lang_directory as u32,
+        );
+
+        put_directory(&mut rsrc, lang_directory, 0, langs.len());
+        for (j, (lang, blob)) in langs.iter().enumerate() {
+            let entry = lang_directory + directory_size(j);
+            put_u32(&mut rsrc, entry, *lang as u32);
+            put_u32(&mut rsrc, entry + 4, data_entries as u32);
+            put_u32(&mut rsrc, data_entries, RSRC_RVA + data as u32);
+            put_u32(&mut rsrc, data_entries + 4, blob.len() as u32);
+            rsrc[data..data + blob.len()].copy_from_slice(blob);
+            data_entries += RESOURCE_DATA_ENTRY_SIZE;
+            data += align(blob.len() as u32, 8) as usize;
+        }
+        lang_directory += directory_size(langs.len());
+    }
+    Ok(rsrc)
+}
+
+fn put_directory(rsrc: &mut [u8], offset: usize, named: usize, ids: usize) {
+    put_u16(rsrc, offset + 12, named as u16);
+    put_u16(rsrc, offset + 14, ids as u16);
+}
+
+fn put_u16(buf: &mut [u8], offset: usize, value: u16) {
+    buf[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(buf: &mut [u8], offset: usize, value: u32) {
+    buf[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buf: &mut [u8], offset: usize, value: u64) {
+    buf[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+}
+
+fn align(value: u32, alignment: u32) -> u32 {
+    (value + alignment - 1) / alignment * alignment
+}
+
+// The longest Unicode text whose entry, terminator and padding included, fits its 16-bit
+// length.
+const HUGE_TEXT_UNITS: usize = (u16::MAX as usize / 4 * 4 - ENTRY_HEADER_SIZE) / 2 - 1;
+
+// A fixture as described in TOML, with a [[table]] per language of each resource:
+//
+//   [[table]]
+//   resource = 1          # or a name, such as "EXTRA"
+//   lang = 0x409
+//   entries = [{ id = 0x1, text = "Hello\r\n", encoding = "utf8" }]
+//
+// encoding is ansi, unicode or utf8, and defaults to unicode.
+#[cfg(feature = "cli")]
+pub fn parse_spec(text: &str) -> Result<Vec<FixtureTable>, FixtureError> {
+    let spec: Spec = toml::from_str(text).map_err(|e| invalid(e.to_string()))?;
+    Ok(spec
+        .tables
+        .into_iter()
+        .map(|table| FixtureTable {
+            resource: match table.resource {
+                SpecResource::Id(id) => ResourceId::Id(id),
+                SpecResource::Name(name) => ResourceId::Name(name),
+            },
+            lang: table.lang,
+            entries: table
+                .entries
+                .into_iter()
+                .map(|entry| FixtureEntry {
+                    id: entry.id,
+                    text: entry.text,
+                    encoding: match entry.encoding {
+                        SpecEncoding::Ansi => Encoding::Ansi,
+                        SpecEncoding::Unicode => Encoding::Unicode,
+                        SpecEncoding::Utf8 => Encoding::Utf8,
+                    },
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+#[cfg(feature = "cli")]
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Spec {
+    #[serde(rename = "table")]
+    tables: Vec<SpecTable>,
+}
+
+#[cfg(feature = "cli")]
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpecTable {
+    resource: SpecResource,
+    lang: u16,
+    entries: Vec<SpecEntry>,
+}
+
+#[cfg(feature = "cli")]
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum SpecResource {
+    Id(u16),
+    Name(String),
+}
+
+#[cfg(feature = "cli")]
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpecEntry {
+    id: u32,
+    text: String,
+    #[serde(default)]
+    encoding: SpecEncoding,
+}
+
+#[cfg(feature = "cli")]
+#[derive(Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SpecEncoding {
+    Ansi,
+    #[default]
+    Unicode,
+    Utf8,
+}
+
+// Tables covering the cases parsers get wrong: every encoding side by side, text outside
+// the BMP, inserts, a suppressed terminator, empty and maximum-length entries, sparse IDs
+// spread over many blocks, several languages, and an ID a second, named resource defines
+// again with different text.
+pub fn edge_cases() -> Vec<FixtureTable> {
+    let huge = format!("{}\r\n", "x".repeat(HUGE_TEXT_UNITS - 2));
+    vec![
+        FixtureTable {
+            resource: ResourceId::Id(1),
+            lang: 0x409,
+            entries: vec![
+                FixtureEntry::new(0x1, "ANSI message\r\n", Encoding::Ansi),
+                FixtureEntry::new(
+                    0x2,
+                    "Unicode message: \u{e9} \u{2713} \u{1f600}\r\n",
+                    Encoding::Unicode,
+                ),
+                FixtureEntry::new(
+                    0x3,
+                    "UTF-8 message: \u{e9} \u{2713} \u{1f600}\r\n",
+                    Encoding::Utf8,
+                ),
+                FixtureEntry::new(0x4, "Inserts %1 and %2!d!.\r\n", Encoding::Unicode),
+                FixtureEntry::new(0x5, "First line\r\nSecond line\r\n", Encoding::Unicode),
+                FixtureEntry::new(0x6, "Suppressed terminator%0", Encoding::Unicode),
+                FixtureEntry::new(0x7, "", Encoding::Unicode),
+                FixtureEntry::new(0x100, "Sparse block\r\n", Encoding::Unicode),
+                FixtureEntry::new(0x200, &huge, Encoding::Unicode),
+                FixtureEntry::new(0x1_0000, "Customer-range block\r\n", Encoding::Ansi),
+                FixtureEntry::new(0xc000_1000, "Error severity\r\n", Encoding::Unicode),
+                FixtureEntry::new(0xffff_ffff, "Highest ID\r\n", Encoding::Utf8),
+            ],
+        },
+        FixtureTable {
+            resource: ResourceId::Id(1),
+            lang: 0x407,
+            entries: vec![
+                FixtureEntry::new(0x1, "ANSI-Meldung\r\n", Encoding::Ansi),
+                FixtureEntry::new(0x2, "Unicode-Meldung: \u{fc}\r\n", Encoding::Unicode),
+            ],
+        },
+        FixtureTable {
+            resource: ResourceId::Id(1),
+            lang: 0,
+            entries: vec![FixtureEntry::new(
+                0x300,
+                "Neutral message\r\n",
+                Encoding::Unicode,
+            )],
+        },
+        FixtureTable {
+            resource: ResourceId::Name("EXTRA".to_string()),
+            lang: 0x409,
+            entries: vec![FixtureEntry::new(
+                0x1,
+                "Second resource\r\n",
+                Encoding::Unicode,
+            )],
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edge_cases_parse_back() {
+        for table in edge_cases() {
+            let data = encode_message_table(&table.entries).unwrap();
+            let parsed = msgtable::parse(&data).unwrap();
+            let mut expected: Vec<&FixtureEntry> = table.entries.iter().collect();
+            expected.sort_by_key(|entry| entry.id);
+            assert_eq!(parsed.entries.len(), expected.len());
+            for (entry, expected) in parsed.entries.iter().zip(expected) {
+                assert_eq!(entry.id, expected.id);
+                assert_eq!(entry.text, expected.text);
+                assert_eq!(entry.encoding, expected.encoding);
+            }
+            assert!(verify_roundtrip(&data).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn runs_of_ids_share_a_block() {
+        let entries = [
+            FixtureEntry::new(0x3, "c\r\n", Encoding::Unicode),
+            FixtureEntry::new(0x1, "a\r\n", Encoding::Unicode),
+            FixtureEntry::new(0x2, "b\r\n", Encoding::Utf8),
+            FixtureEntry::new(0x10, "d\r\n", Encoding::Ansi),
+        ];
+        let parsed = msgtable::parse(&encode_message_table(&entries).unwrap()).unwrap();
+        let blocks: Vec<(u32, u32)> = parsed
+            .blocks
+            .iter()
+            .map(|block| (block.low_id, block.high_id))
+            .collect();
+        assert_eq!(blocks, [(0x1, 0x3), (0x10, 0x10)]);
+    }
+
+    #[test]
+    fn duplicate_ids_are_rejected() {
+        let entries = [
+            FixtureEntry::new(0x1, "a\r\n", Encoding::Unicode),
+            FixtureEntry::new(0x1, "b\r\n", Encoding::Unicode),
+        ];
+        assert!(encode_message_table(&entries).is_err());
+    }
+
+    #[cfg(feature = "offline")]
+    #[test]
+    fn dll_holds_every_table() {
+        let tables = edge_cases();
+        let dll = build_dll(&tables).unwrap();
+        let resources = crate::pe::PeResources::parse(&dll).unwrap();
+        let raws = crate::provider::read_raw_message_tables(&resources, true).unwrap();
+        assert_eq!(raws.len(), tables.len());
+        for table in &tables {
+            let raw = raws
+                .iter()
+                .find(|raw| raw.resource == table.resource && raw.lang == table.lang)
+                .unwrap();
+            assert_eq!(raw.data, encode_message_table(&table.entries).unwrap());
+        }
+    }
+}
@@ -0,0 +1,608 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::junit;
+use crate::msgtable::{Block, Entry};
+use crate::output::{self, JsonDocument, JsonModule, Printer, Summary};
+use crate::proto::Encoder;
+use crate::signature::Signature;
+use crate::symbols::Symbols;
+use crate::table::{MessageTable, ResourceId};
+use crate::ui::{Msg, Ui};
+
+// A module as handed to formatters, with everything that was read about it.
+pub struct ModuleOutput<'a> {
+    // The name or path the module was given as.
+    pub name: &'a str,
+    // The absolute path it was resolved to.
+    pub path: &'a Path,
+    pub tables: &'a [MessageTable],
+    pub symbols: Option<&'a Symbols>,
+    pub signature: Option<&'a Signature>,
+    // 64 or 32 when the module was dumped together with its counterpart of the other
+    // bitness.
+    pub bitness: Option<u32>,
+    // None when the module's file couldn't be found or read to classify it.
+    pub resource_only: Option<bool>,
+}
+
+pub struct EntryOutput<'a> {
+    pub table: &'a MessageTable,
+    // Only set when entries are grouped by block.
+    pub block: Option<&'a Block>,
+    pub entry: &'a Entry,
+    // The last ID of a run of identical entries collapsed into this one, or the entry's
+    // own ID.
+    pub last_id: u32,
+}
+
+// Receives every module's entries, in order, and renders them in some output format to the
+// writer it was created with.
+pub trait OutputFormatter {
+    fn begin_module(&mut self, module: &ModuleOutput) -> io::Result<()>;
+    fn entry(&mut self, module: &ModuleOutput, entry: &EntryOutput) -> io::Result<()>;
+    fn end_module(&mut self, module: &ModuleOutput) -> io::Result<()>;
+    // Called once after the last module.
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+pub type Output<'a> = Box<dyn Write + 'a>;
+
+type Factory<'a> = Box<dyn Fn(Output<'a>) -> Box<dyn OutputFormatter + 'a> + 'a>;
+
+// Formatters by name. Library consumers can register their own alongside the built-in ones.
+#[derive(Default)]
+pub struct Formatters<'a> {
+    factories: BTreeMap<String, Factory<'a>>,
+}
+
+impl<'a> Formatters<'a> {
+    pub fn new() -> Formatters<'a> {
+        Formatters::default()
+    }
+
+    // The formats the command-line tool offers, rendered with printer's options.
+    pub fn builtin(printer: &'a Printer, ui: &'a Ui) -> Formatters<'a> {
+        let mut formatters = Formatters::new();
+        formatters.register("text", move |out| {
+            Box::new(TextFormatter::new(printer, ui, out))
+        });
+        formatters.register("json", move |out| {
+            Box::new(JsonFormatter::new(printer, out))
+        });
+        formatters.register("reverse-index", move |out| {
+            Box::new(ReverseIndexFormatter::new(printer, out))
+        });
+        formatters.register("resx", move |out| {
+            Box::new(ResxFormatter::new(printer, out))
+        });
+        formatters.register("proto", move |out| {
+            Box::new(ProtoFormatter::new(printer, out))
+        });
+        formatters
+    }
+
+    // Replaces any formatter already registered under name.
+    pub fn register(
+        &mut self,
+        name: &str,
+        factory: impl Fn(Output<'a>) -> Box<dyn OutputFormatter + 'a> + 'a,
+    ) {
+        self.factories.insert(name.to_string(), Box::new(factory));
+    }
+
+    pub fn create(&self, name: &str, out: Output<'a>) -> Option<Box<dyn OutputFormatter + 'a>> {
+        self.factories.get(name).map(|factory| factory(out))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.factories.keys().map(String::as_str)
+    }
+}
+
+// Feeds a module's entries to formatter, grouped by block and collapsed into runs as
+// printer's options say.
+pub fn write_module(
+    formatter: &mut dyn OutputFormatter,
+    printer: &Printer,
+    module: &ModuleOutput,
+) -> io::Result<()> {
+    formatter.begin_module(module)?;
+    for table in module.tables {
+        if !printer.show_blocks {
+            for (entry, last_id) in printer.runs(table.entries.iter()) {
+                let entry = EntryOutput {
+                    table,
+                    block: None,
+                    entry,
+                    last_id,
+                };
+                formatter.entry(module, &entry)?;
+            }
+            continue;
+        }
+        for block in &table.blocks {
+            let entries = table
+                .entries
+                .iter()
+                .filter(|entry| block.contains(entry.id));
+            for (entry, last_id) in printer.runs(entries) {
+                let entry = EntryOutput {
+                    table,
+                    block: Some(block),
+                    entry,
+                    last_id,
+                };
+                formatter.entry(module, &entry)?;
+            }
+        }
+    }
+    formatter.end_module(module)
+}
+
+pub struct TextFormatter<'a> {
+    printer: &'a Printer,
+    ui: &'a Ui,
+    out: Output<'a>,
+    // The table and block of the previous entry, so headers are printed when they change.
+    table: Option<(ResourceId, u16)>,
+    block: Option<u32>,
+}
+
+impl<'a> TextFormatter<'a> {
+    pub fn new(printer: &'a Printer, ui: &'a Ui, out: Output<'a>) -> TextFormatter<'a> {
+        TextFormatter {
+            printer,
+            ui,
+            out,
+            table: None,
+            block: None,
+        }
+    }
+}
+
+impl OutputFormatter for TextFormatter<'_> {
+    fn begin_module(&mut self, module: &ModuleOutput) -> io::Result<()> {
+        self.table = None;
+        self.block = None;
+        if self.printer.module_headers && self.printer.only.is_none() {
+            let header = match module.bitness {
+                Some(bitness) => self.ui.text(
+                    Msg::ModuleHeaderBitness,
+                    &[module.name, &bitness.to_string()],
+                ),
+                None => self.ui.text(Msg::ModuleHeader, &[module.name]),
+            };
+            writeln!(self.out, "{}", header)?;
+        }
+        if self.printer.show_paths {
+            writeln!(
+                self.out,
+                "{}",
+                self.ui
+                    .text(Msg::ResolvedPath, &[&module.path.display().to_string()])
+            )?;
+        }
+        if module.resource_only == Some(true) {
+            writeln!(self.out, "{}", self.ui.text(Msg::ResourceOnly, &[]))?;
+        }
+        if let Some(signature) = module.signature {
+            writeln!(
+                self.out,
+                "{}",
+                self.ui.text(Msg::Signature, &[&signature.to_string()])
+            )?;
+        }
+        Ok(())
+    }
+
+    fn entry(&mut self, module: &ModuleOutput, entry: &EntryOutput) -> io::Result<()> {
+        if self.printer.top_size.is_some() || self.printer.gaps {
+            return Ok(());
+        }
+        // Entries grouped by block are listed under headers describing the resource and
+        // each block they came from.
+        if let Some(block) = entry.block {
+            let table = (entry.table.resource.clone(), entry.table.lang);
+            if self.table.as_ref() != Some(&table) {
+                writeln!(
+                    self.out,
+                    "resource {} [{}]:",
+                    entry.table.resource,
+                    output::format_lang(self.printer.lang_format, entry.table.lang)
+                )?;
+                self.table = Some(table);
+                self.block = None;
+            }
+            if self.block != Some(block.low_id) {
+                let count = entry
+                    .table
+                    .entries
+                    .iter()
+                    .filter(|entry| block.contains(entry.id))
+                    .count();
+                writeln!(
+                    self.out,
+                    "  block {:#x}-{:#x} at offset {:#x} ({} entries):",
+                    block.low_id, block.high_id, block.offset_to_entries, count
+                )?;
+                self.block = Some(block.low_id);
+            }
+        }
+        self.printer.print_entry(
+            &mut self.out,
+            entry.entry.id,
+            entry.last_id,
+            &entry.entry.text,
+            Some(entry.entry),
+            module.symbols,
+            Some(module.name),
+        )
+    }
+
+    fn end_module(&mut self, module: &ModuleOutput) -> io::Result<()> {
+        match self.printer.top_size {
+            Some(count) => self
+                .printer
+                .print_top_size(&mut self.out, module.tables, count),
+            None if self.printer.gaps => self.printer.print_gaps(&mut self.out, module.tables),
+            None => self.printer.print_summary(&mut self.out, module.tables),
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// Collects every module and prints them as a single JSON document at the end.
+pub struct JsonFormatter<'a> {
+    printer: &'a Printer,
+    out: Output<'a>,
+    modules: Vec<JsonModule>,
+}
+
+impl<'a> JsonFormatter<'a> {
+    pub fn new(printer: &'a Printer, out: Output<'a>) -> JsonFormatter<'a> {
+        JsonFormatter {
+            printer,
+            out,
+            modules: Vec::new(),
+        }
+    }
+}
+
+impl OutputFormatter for JsonFormatter<'_> {
+    fn begin_module(&mut self, module: &ModuleOutput) -> io::Result<()> {
+        self.modules.push(JsonModule {
+            module: module.name.to_string(),
+            path: module.path.display().to_string(),
+            bitness: module.bitness,
+            resource_only: module.resource_only,
+            signature: module.signature.cloned(),
+            summary: self.printer.summary.then(|| Summary::new(module.tables)),
+            entries: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn entry(&mut self, module: &ModuleOutput, entry: &EntryOutput) -> io::Result<()> {
+        let json_entry =
+            self.printer
+                .json_entry(entry.entry, entry.last_id, entry.table, module.symbols);
+        if let Some(json_module) = self.modules.last_mut() {
+            json_module.entries.push(json_entry);
+        }
+        Ok(())
+    }
+
+    fn end_module(&mut self, _module: &ModuleOutput) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let document = JsonDocument::new(std::mem::take(&mut self.modules));
+        serde_json::to_writer_pretty(&mut self.out, &document)?;
+        writeln!(self.out)?;
+        self.out.flush()
+    }
+}
+
+#[derive(Serialize)]
+struct Occurrence {
+    module: String,
+    id: u32,
+    lang: u16,
+}
+
+// Maps each distinct message text, without its trailing line break, to every module, ID and
+// language it occurs under, so equivalent events from different sources can be collapsed.
+pub struct ReverseIndexFormatter<'a> {
+    printer: &'a Printer,
+    out: Output<'a>,
+    index: BTreeMap<String, Vec<Occurrence>>,
+}
+
+impl<'a> ReverseIndexFormatter<'a> {
+    pub fn new(printer: &'a Printer, out: Output<'a>) -> ReverseIndexFormatter<'a> {
+        ReverseIndexFormatter {
+            printer,
+            out,
+            index: BTreeMap::new(),
+        }
+    }
+}
+
+impl OutputFormatter for ReverseIndexFormatter<'_> {
+    fn begin_module(&mut self, _module: &ModuleOutput) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn entry(&mut self, module: &ModuleOutput, entry: &EntryOutput) -> io::Result<()> {
+        let text = self.printer.normalize(&entry.entry.text);
+        let text = text.trim_end_matches(['\r', '\n']).to_string();
+        let occurrences = self.index.entry(text).or_default();
+        // A collapsed run stands for every ID in it.
+        for id in entry.entry.id..=entry.last_id {
+            occurrences.push(Occurrence {
+                module: module.name.to_string(),
+                id,
+                lang: entry.table.lang,
+            });
+        }
+        Ok(())
+    }
+
+    fn end_module(&mut self, _module: &ModuleOutput) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut self.out, &self.index)?;
+        writeln!(self.out)?;
+        self.out.flush()
+    }
+}
+
+// How --format resx names each message's resource: a template in which {id} is the ID as
+// eight hex digits, {decimal} the ID in decimal and {symbol} its name from the module's PDB.
+// IDs without a symbol are named by the default template instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResxNames {
+    template: String,
+}
+
+impl Default for ResxNames {
+    fn default() -> ResxNames {
+        ResxNames {
+            template: ResxNames::DEFAULT.to_string(),
+        }
+    }
+}
+
+impl ResxNames {
+    pub const DEFAULT: &'static str = "MSG_{id}";
+    const PLACEHOLDERS: [&'static str; 3] = ["{id}", "{decimal}", "{symbol}"];
+
+    pub fn parse(template: &str) -> Result<ResxNames, String> {
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let placeholder = rest[start..]
+                .find('}')
+                .map(|end| &rest[start..start + end + 1])
+                .ok_or_else(|| format!("unterminated placeholder in {:?}", template))?;
+            if !ResxNames::PLACEHOLDERS.contains(&placeholder) {
+                return Err(format!(
+                    "unknown placeholder {}; expected one of {}",
+                    placeholder,
+                    ResxNames::PLACEHOLDERS.join(", ")
+                ));
+            }
+            rest = &rest[start + placeholder.len()..];
+        }
+        Ok(ResxNames {
+            template: template.to_string(),
+        })
+    }
+
+    pub fn name(&self, id: u32, symbol: Option<&str>) -> String {
+        let template = match symbol {
+            None if self.template.contains("{symbol}") => ResxNames::DEFAULT,
+            _ => &self.template,
+        };
+        template
+            .replace("{id}", &format!("{:08X}", id))
+            .replace("{decimal}", &id.to_string())
+            .replace("{symbol}", symbol.unwrap_or_default())
+    }
+}
+
+// Writes a single .resx document for ResourceManager, with a string per message ID named by
+// printer's resx_names. Each message loses its final line break, and a name that's already
+// been written, such as the same ID in another module, is skipped. A .resx holds one
+// language, so entries in a language other than the first one's are an error.
+pub struct ResxFormatter<'a> {
+    printer: &'a Printer,
+    out: Output<'a>,
+    names: HashSet<String>,
+    // The language of the first entry written.
+    lang: Option<u16>,
+    started: bool,
+}
+
+impl<'a> ResxFormatter<'a> {
+    pub fn new(printer: &'a Printer, out: Output<'a>) -> ResxFormatter<'a> {
+        ResxFormatter {
+            printer,
+            out,
+            names: HashSet::new(),
+            lang: None,
+            started: false,
+        }
+    }
+
+    // The header goes out before the first module, so a run without modules still writes
+    // a valid document.
+    fn start(&mut self) -> io::Result<()> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+        writeln!(self.out, r#"<?xml version="1.0" encoding="utf-8"?>"#)?;
+        writeln!(self.out, "<root>")?;
+        for (name, value) in RESX_HEADERS {
+            writeln!(
+                self.out,
+                r#"  <resheader name="{}"><value>{}</value></resheader>"#,
+                name, value
+            )?;
+        }
+        Ok(())
+    }
+}
+
+const RESX_HEADERS: [(&str, &str); 4] = [
+    ("resmimetype", "text/microsoft-resx"),
+    ("version", "2.0"),
+    (
+        "reader",
+        "System.Resources.ResXResourceReader, System.Windows.Forms, Version=4.0.0.0, \
+         Culture=neutral, PublicKeyToken=b77a5c561934e089",
+    ),
+    (
+        "writer",
+        "System.Resources.ResXResourceWriter, System.Windows.Forms, Version=4.0.0.0, \
+         Culture=neutral, PublicKeyToken=b77a5c561934e089",
+    ),
+];
+
+impl OutputFormatter for ResxFormatter<'_> {
+    fn begin_module(&mut self, _module: &ModuleOutput) -> io::Result<()> {
+        self.start()
+    }
+
+    fn entry(&mut self, module: &ModuleOutput, entry: &EntryOutput) -> io::Result<()> {
+        match self.lang {
+            None => self.lang = Some(entry.table.lang),
+            Some(lang) if lang != entry.table.lang => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "a .resx holds a single language, but {} has entries in [{}] after \
+                         ones in [{}]; use --effective-lang to merge them into one",
+                        module.name,
+                        output::format_lang(self.printer.lang_format, entry.table.lang),
+                        output::format_lang(self.printer.lang_format, lang)
+                    ),
+                ));
+            }
+            Some(_) => {}
+        }
+        let text = self.printer.normalize(&entry.entry.text);
+        let text = text.strip_suffix('\n').unwrap_or(&text);
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        // XML parsers turn CRs into LFs unless they're escaped.
+        let value = junit::escape(text).replace('\r', "&#13;");
+        // A collapsed run stands for every ID in it.
+        for id in entry.entry.id..=entry.last_id {
+            let symbol = module.symbols.and_then(|symbols| symbols.lookup(id));
+            let name = self.printer.resx_names.name(id, symbol);
+            if !self.names.insert(name.clone()) {
+                continue;
+            }
+            writeln!(
+                self.out,
+                r#"  <data name="{}" xml:space="preserve">"#,
+                junit::escape(&name)
+            )?;
+            writeln!(self.out, "    <value>{}</value>", value)?;
+            writeln!(
+                self.out,
+                "    <comment>{} {:#010x} [{}]</comment>",
+                junit::escape(module.name),
+                id,
+                output::format_lang(self.printer.lang_format, entry.table.lang)
+            )?;
+            writeln!(self.out, "  </data>")?;
+        }
+        Ok(())
+    }
+
+    fn end_module(&mut self, _module: &ModuleOutput) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.start()?;
+        writeln!(self.out, "</root>")?;
+        self.out.flush()
+    }
+}
+
+// Writes the Catalog message of catalog.proto a module at a time, so nothing but the
+// current module is held in memory.
+pub struct ProtoFormatter<'a> {
+    printer: &'a Printer,
+    out: Output<'a>,
+    module: Encoder,
+}
+
+impl<'a> ProtoFormatter<'a> {
+    pub fn new(printer: &'a Printer, out: Output<'a>) -> ProtoFormatter<'a> {
+        ProtoFormatter {
+            printer,
+            out,
+            module: Encoder::new(),
+        }
+    }
+}
+
+impl OutputFormatter for ProtoFormatter<'_> {
+    fn begin_module(&mut self, module: &ModuleOutput) -> io::Result<()> {
+        self.module = Encoder::new();
+        self.module.string(1, module.name);
+        self.module.string(2, &module.path.display().to_string());
+        self.module.optional_uint32(3, module.bitness);
+        self.module.optional_bool(4, module.resource_only);
+        Ok(())
+    }
+
+    fn entry(&mut self, module: &ModuleOutput, entry: &EntryOutput) -> io::Result<()> {
+        let json_entry =
+            self.printer
+                .json_entry(entry.entry, entry.last_id, entry.table, module.symbols);
+        let mut message = Encoder::new();
+        message.uint32(1, json_entry.id);
+        message.optional_uint32(2, json_entry.range.map(|[_, last_id]| last_id));
+        message.string(3, &json_entry.resource);
+        message.uint32(4, json_entry.lang.into());
+        message.optional_string(5, json_entry.locale.as_deref());
+        message.string(6, &json_entry.text);
+        message.optional_string(7, json_entry.severity.as_deref());
+        message.optional_bool(8, json_entry.customer);
+        message.optional_string(9, json_entry.facility.as_deref());
+        message.optional_uint32(10, json_entry.code.map(u32::from));
+        message.string(11, &json_entry.encoding);
+        message.optional_string(12, json_entry.component.as_deref());
+        message.optional_string(13, json_entry.symbol.as_deref());
+        message.uint32(14, json_entry.offset);
+        message.uint32(15, json_entry.length.into());
+        // Unlike JSON, the bytes go in as they are.
+        message.optional_bytes(16, self.printer.include_raw.then_some(&entry.entry.raw[..]));
+        self.module.message(5, message);
+        Ok(())
+    }
+
+    fn end_module(&mut self, _module: &ModuleOutput) -> io::Result<()> {
+        let mut catalog = Encoder::new();
+        catalog.message(1, std::mem::take(&mut self.module));
+        self.out.write_all(&catalog.into_bytes())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::table::MessageTable;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GapKind {
+    // IDs a block covers whose entries are empty in every language.
+    WithinBlock,
+    BeforeFirstBlock,
+    BetweenBlocks,
+    AfterLastBlock,
+}
+
+// A run of IDs nothing uses. IDs right after a block extend it rather than starting a new
+// one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gap {
+    pub first: u32,
+    pub last: u32,
+    pub kind: GapKind,
+}
+
+impl Gap {
+    pub fn size(&self) -> u32 {
+        self.last - self.first + 1
+    }
+}
+
+// The unused IDs among those sharing a severity, customer bit and facility, i.e. the top
+// 16 bits of the ID.
+#[derive(Debug, Clone)]
+pub struct FacilityGaps {
+    pub first_id: u32,
+    pub gaps: Vec<Gap>,
+}
+
+// The gaps in the ID space of every facility tables use, in every language at once, so an
+// ID free in one is free in all of them.
+pub fn find_gaps(tables: &[MessageTable]) -> Vec<FacilityGaps> {
+    let used: BTreeSet<u32> = tables
+        .iter()
+        .flat_map(|table| &table.entries)
+        .filter(|entry| !entry.text.is_empty())
+        .map(|entry| entry.id)
+        .collect();
+
+    // The ranges blocks cover, merged across languages and split at facility boundaries.
+    let mut ranges: Vec<(u32, u32)> = tables
+        .iter()
+        .flat_map(|table| &table.blocks)
+        .map(|block| (block.low_id, block.high_id))
+        .collect();
+    ranges.sort_unstable();
+    let mut by_facility: BTreeMap<u32, Vec<(u32, u32)>> = BTreeMap::new();
+    for (low, high) in ranges {
+        let mut low = low;
+        loop {
+            let facility_last = low | 0xffff;
+            let spans = by_facility.entry(low & 0xffff_0000).or_default();
+            let end = high.min(facility_last);
+            match spans.last_mut() {
+                Some((_, last)) if low <= last.saturating_add(1) => *last = (*last).max(end),
+                _ => spans.push((low, end)),
+            }
+            if high <= facility_last {
+                break;
+            }
+            low = facility_last + 1;
+        }
+    }
+
+    by_facility
+        .into_iter()
+        .map(|(first_id, spans)| {
+            let last_id = first_id | 0xffff;
+            let mut gaps = Vec::new();
+            let mut next = first_id;
+            for (i, &(low, high)) in spans.iter().enumerate() {
+                if low > next {
+                    gaps.push(Gap {
+                        first: next,
+                        last: low - 1,
+                        kind: if i == 0 {
+                            GapKind::BeforeFirstBlock
+                        } else {
+                            GapKind::BetweenBlocks
+                        },
+                    });
+                }
+                let mut unused: Option<u32> = None;
+                for id in low..=high {
+                    match (used.contains(&id), unused) {
+                        (false, None) => unused = Some(id),
+                        (true, Some(first)) => {
+                            gaps.push(Gap {
+                                first,
+                                last: id - 1,
+                                kind: GapKind::WithinBlock,
+                            });
+                            unused = None;
+                        }
+                        _ => {}
+                    }
+                }
+                if let Some(first) = unused {
+                    gaps.push(Gap {
+                        first,
+                        last: high,
+                        kind: GapKind::WithinBlock,
+                    });
+                }
+                next = high.saturating_add(1);
+            }
+            if spans.last().map_or(false, |&(_, high)| high < last_id) {
+                gaps.push(Gap {
+                    first: next,
+                    last: last_id,
+                    kind: GapKind::AfterLastBlock,
+                });
+            }
+            FacilityGaps { first_id, gaps }
+        })
+        .collect()
+}
@@ -0,0 +1,20 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use windows::Win32::Security::Cryptography::*;
+
+use crate::paths;
+
+const SHA256_LEN: usize = 32;
+
+// The SHA-256 of a file's contents, as lowercase hex, computed with CNG so no crypto crate
+// is needed.
+pub fn sha256_file(path: &Path) -> anyhow::Result<String> {
+    let data =
+        paths::read_file(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let mut digest = [0u8; SHA256_LEN];
+    unsafe { BCryptHash(BCRYPT_SHA256_ALG_HANDLE, None, &data, &mut digest) }
+        .ok()
+        .map_err(|e| anyhow!("failed to hash {}: {}", path.display(), e.message()))?;
+    Ok(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
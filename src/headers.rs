@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+// The SDK headers error codes are defined in, as Error Lookup names them.
+pub const WINERROR_H: &str = "winerror.h";
+pub const NTSTATUS_H: &str = "ntstatus.h";
+pub const LMERR_H: &str = "lmerr.h";
+const HEADERS: &[&str] = &[WINERROR_H, NTSTATUS_H, LMERR_H];
+
+// Casts and typedef macros wrapped around values, which don't change them.
+const WRAPPERS: &[&str] = &[
+    "_HRESULT_TYPEDEF_",
+    "_NDIS_ERROR_TYPEDEF_",
+    "NTSTATUS",
+    "HRESULT",
+    "SCODE",
+    "DWORD",
+    "ULONG",
+    "LONG",
+];
+
+// Symbolic names of error codes, parsed from the #defines in the Windows SDK's headers.
+#[derive(Default)]
+pub struct Headers {
+    names: HashMap<(&'static str, u32), Vec<String>>,
+}
+
+impl Headers {
+    // include_dir is either a directory holding the headers or an SDK version's Include
+    // directory, whose shared and um subdirectories hold them. Headers that can't be found
+    // or read are left out; without an SDK, nothing has a name.
+    pub fn load(include_dir: Option<&Path>) -> Headers {
+        let mut headers = Headers::default();
+        let include_dir = match include_dir.map(Path::to_path_buf).or_else(sdk_include_dir) {
+            Some(include_dir) => include_dir,
+            None => return headers,
+        };
+        let dirs = [
+            include_dir.clone(),
+            include_dir.join("shared"),
+            include_dir.join("um"),
+        ];
+        for &header in HEADERS {
+            let text = dirs
+                .iter()
+                .find_map(|dir| fs::read_to_string(dir.join(header)).ok());
+            if let Some(text) = text {
+                headers.parse(header, &text);
+            }
+        }
+        headers
+    }
+
+    pub fn lookup(&self, header: &'static str, value: u32) -> &[String] {
+        self.names
+            .get(&(header, value))
+            .map_or(&[], |names| names.as_slice())
+    }
+
+    fn parse(&mut self, header: &'static str, text: &str) {
+        let define =
+            Regex::new(r"^\s*#\s*define\s+([A-Z][A-Z0-9_]*)\s+(.+?)\s*(?://.*|/\*.*)?$").unwrap();
+        // lmerr.h defines most codes relative to NERR_BASE and friends.
+        let mut values: HashMap<String, u32> = HashMap::new();
+        for line in text.lines() {
+            let captures = match define.captures(line) {
+                Some(captures) => captures,
+                None => continue,
+            };
+            let name = &captures[1];
+            let value = match evaluate(&captures[2], &values) {
+                Some(value) => value,
+                None => continue,
+            };
+            values.insert(name.to_string(), value);
+            if is_error_name(name) {
+                self.names
+                    .entry((header, value))
+                    .or_default()
+                    .push(name.to_string());
+            }
+        }
+    }
+}
+
+// Facility and severity constants and the bases of code ranges share values with codes but
+// aren't codes themselves.
+fn is_error_name(name: &str) -> bool {
+    name.contains('_')
+        && !name.starts_with("FACILITY_")
+        && !name.starts_with("SEVERITY_")
+        && !name.ends_with("_BASE")
+}
+
+// Handles literals and sums of literals and earlier definitions, which is all the error
+// headers use.
+fn evaluate(expression: &str, values: &HashMap<String, u32>) -> Option<u32> {
+    let mut expression = expression.replace(['(', ')'], " ");
+    for wrapper in WRAPPERS {
+        expression = expression.replace(wrapper, " ");
+    }
+    let mut sum: u32 = 0;
+    for term in expression.split('+') {
+        let term = term.trim();
+        let value = match values.get(term) {
+            Some(&value) => value,
+            None => literal(term)?,
+        };
+        sum = sum.wrapping_add(value);
+    }
+    Some(sum)
+}
+
+fn literal(term: &str) -> Option<u32> {
+    let term = term.trim_end_matches(['L', 'l', 'U', 'u']);
+    match term.strip_prefix("0x").or_else(|| term.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => term
+            .parse::<u32>()
+            .ok()
+            .or_else(|| term.parse::<i32>().ok().map(|n| n as u32)),
+    }
+}
+
+// The include directory of the newest Windows 10+ SDK installed.
+fn sdk_include_dir() -> Option<PathBuf> {
+    let kits = env::var_os("WindowsSdkDir")
+        .map(PathBuf::from)
+        .or_else(|| {
+            env::var_os("ProgramFiles(x86)")
+                .map(|program_files| Path::new(&program_files).join("Windows Kits").join("10"))
+        })?;
+    let mut versions: Vec<PathBuf> = fs::read_dir(kits.join("Include"))
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|version| version.join("shared").join(WINERROR_H).is_file())
+        .collect();
+    // Version directories are named like 10.0.22621.0.
+    versions.sort();
+    versions.pop()
+}
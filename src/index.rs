@@ -0,0 +1,304 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::{anyhow, Context};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::policy::LoadPolicy;
+use crate::scan::{self, CancellationToken, NameFilter, ScanProgress};
+
+// Bump whenever SCHEMA changes. The index is only a cache, so an outdated one is simply
+// dropped and rebuilt.
+const SCHEMA_VERSION: i64 = 3;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS modules (
+        id INTEGER PRIMARY KEY,
+        path TEXT NOT NULL UNIQUE,
+        size INTEGER NOT NULL,
+        modified INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS messages (
+        entry_id INTEGER PRIMARY KEY,
+        module_id INTEGER NOT NULL,
+        id INTEGER NOT NULL,
+        lang INTEGER NOT NULL,
+        text TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS messages_id ON messages (id);
+    CREATE INDEX IF NOT EXISTS messages_module_id ON messages (module_id);
+    CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5 (
+        text,
+        content = 'messages',
+        content_rowid = 'entry_id'
+    );
+    CREATE VIRTUAL TABLE IF NOT EXISTS messages_trigram USING fts5 (
+        text,
+        content = 'messages',
+        content_rowid = 'entry_id',
+        tokenize = 'trigram'
+    );
+    CREATE TRIGGER IF NOT EXISTS messages_insert AFTER INSERT ON messages BEGIN
+        INSERT INTO messages_fts (rowid, text) VALUES (new.entry_id, new.text);
+        INSERT INTO messages_trigram (rowid, text) VALUES (new.entry_id, new.text);
+    END;
+    CREATE TRIGGER IF NOT EXISTS messages_delete AFTER DELETE ON messages BEGIN
+        INSERT INTO messages_fts (messages_fts, rowid, text)
+            VALUES ('delete', old.entry_id, old.text);
+        INSERT INTO messages_trigram (messages_trigram, rowid, text)
+            VALUES ('delete', old.entry_id, old.text);
+    END;
+";
+
+// Fuzzy searches rerank this many candidates per requested hit.
+const FUZZY_CANDIDATES_PER_HIT: usize = 10;
+
+const DROP_SCHEMA: &str = "
+    DROP TABLE IF EXISTS modules;
+    DROP TABLE IF EXISTS messages;
+    DROP TABLE IF EXISTS messages_fts;
+    DROP TABLE IF EXISTS messages_trigram;
+";
+
+pub struct SearchHit {
+    pub path: String,
+    pub id: u32,
+    pub lang: u16,
+    pub text: String,
+    // Fraction of the query's trigrams found in the text; only set by fuzzy searches.
+    pub similarity: Option<f64>,
+}
+
+// An on-disk cache of the message tables of every module scanned so far. Modules are
+// only rescanned when their size or modification time changes.
+pub struct Index {
+    conn: Connection,
+}
+
+impl Index {
+    pub fn open_default() -> anyhow::Result<Index> {
+        let local_app_data =
+            env::var_os("LOCALAPPDATA").ok_or_else(|| anyhow!("LOCALAPPDATA is not set"))?;
+        let dir = Path::new(&local_app_data).join("dump_msg_tables");
+        fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        Index::open(&dir.join("index.sqlite"))
+    }
+
+    pub fn open(path: &Path) -> anyhow::Result<Index> {
+        let conn = Connection::open(path)
+            .with_context(|| format!("failed to open the index {}", path.display()))?;
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version != SCHEMA_VERSION {
+            conn.execute_batch(DROP_SCHEMA)?;
+            conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
+        }
+        conn.execute_batch(SCHEMA)?;
+        Ok(Index { conn })
+    }
+
+    pub fn is_empty(&self) -> anyhow::Result<bool> {
+        let count: i64 = self
+            .conn
+            .query_row("SELECT COUNT(*) FROM modules", [], |row| row.get(0))?;
+        Ok(count == 0)
+    }
+
+    // Brings the index up to date with the modules directly inside dir that filter admits;
+    // what's indexed of the others is left alone. If cancel is signalled, the index is left
+    // as it was. Only modules that changed since the last refresh are reported to progress.
+    pub fn refresh(
+        &mut self,
+        dir: &Path,
+        policy: &LoadPolicy,
+        filter: &NameFilter,
+        cancel: &CancellationToken,
+        progress: &dyn ScanProgress,
+    ) -> anyhow::Result<()> {
+        let paths = scan::module_files(dir, filter)?;
+
+        let mut stale = Vec::new();
+        for path in &paths {
+            let (size, modified) = match file_stamp(path) {
+                Ok(stamp) => stamp,
+                Err(_) => continue,
+            };
+            let known: Option<(i64, i64)> = self
+                .conn
+                .query_row(
+                    "SELECT size, modified FROM modules WHERE path = ?1",
+                    params![path.to_string_lossy()],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .optional()?;
+            if known != Some((size, modified)) {
+                stale.push((path.clone(), size, modified));
+            }
+        }
+
+        let stale_paths: Vec<PathBuf> = stale.iter().map(|(path, _, _)| path.clone()).collect();
+        let results = scan::scan_modules(&stale_paths, policy, cancel, progress)?;
+
+        let present: HashSet<String> = paths
+            .iter()
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        let tx = self.conn.transaction()?;
+        {
+            // Forget modules that have been removed from dir since the last refresh.
+            let mut known_paths = tx.prepare("SELECT path FROM modules")?;
+            let removed: Vec<String> = known_paths
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?
+                .into_iter()
+                .filter(|path| {
+                    let known = Path::new(path);
+                    known.parent() == Some(dir)
+                        && filter.admits(known)
+                        && !present.contains(path)
+                })
+                .collect();
+            for path in removed {
+                remove_module(&tx, &path)?;
+            }
+
+            for ((path, size, modified), result) in stale.iter().zip(results) {
+                let path = path.to_string_lossy();
+                remove_module(&tx, &path)?;
+                tx.execute(
+                    "INSERT INTO modules (path, size, modified) VALUES (?1, ?2, ?3)",
+                    params![path, size, modified],
+                )?;
+                let module_id = tx.last_insert_rowid();
+
+                // Modules that fail to load are still recorded so they aren't retried
+                // on every refresh.
+                let tables = result.unwrap_or_default();
+                let mut insert = tx.prepare_cached(
+                    "INSERT INTO messages (module_id, id, lang, text) VALUES (?1, ?2, ?3, ?4)",
+                )?;
+                for table in tables {
+                    for entry in table.entries {
+                        insert.execute(params![module_id, entry.id, table.lang, entry.text])?;
+                    }
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn who_defines(&self, id: u32) -> anyhow::Result<Vec<SearchHit>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT modules.path, messages.id, messages.lang, messages.text FROM messages
+             JOIN modules ON modules.id = messages.module_id
+             WHERE messages.id = ?1
+             ORDER BY modules.path, messages.lang",
+        )?;
+        let hits = stmt
+            .query_map(params![id], search_hit)?
+            .collect::<rusqlite::Result<Vec<SearchHit>>>()?;
+        Ok(hits)
+    }
+
+    // Every word of text must appear in a message for it to match. Hits are ordered by
+    // FTS5's bm25 rank, best first.
+    pub fn search(&self, text: &str, limit: usize) -> anyhow::Result<Vec<SearchHit>> {
+        let query = text
+            .split_whitespace()
+            .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+            .collect::<Vec<String>>()
+            .join(" ");
+        let mut stmt = self.conn.prepare(
+            "SELECT modules.path, messages.id, messages.lang, messages.text FROM messages_fts
+             JOIN messages ON messages.entry_id = messages_fts.rowid
+             JOIN modules ON modules.id = messages.module_id
+             WHERE messages_fts MATCH ?1
+             ORDER BY messages_fts.rank
+             LIMIT ?2",
+        )?;
+        let hits = stmt
+            .query_map(params![query, limit as i64], search_hit)?
+            .collect::<rusqlite::Result<Vec<SearchHit>>>()?;
+        Ok(hits)
+    }
+
+    // Tolerates typos and OCR errors: candidates sharing any trigram with text are fetched
+    // from the trigram index, then reranked by how many of text's trigrams they contain.
+    pub fn search_fuzzy(&self, text: &str, limit: usize) -> anyhow::Result<Vec<SearchHit>> {
+        let query_trigrams = trigrams(text);
+        if query_trigrams.is_empty() {
+            return self.search(text, limit);
+        }
+        let query = query_trigrams
+            .iter()
+            .map(|trigram| format!("\"{}\"", trigram.replace('"', "\"\"")))
+            .collect::<Vec<String>>()
+            .join(" OR ");
+        let mut stmt = self.conn.prepare(
+            "SELECT modules.path, messages.id, messages.lang, messages.text
+             FROM messages_trigram
+             JOIN messages ON messages.entry_id = messages_trigram.rowid
+             JOIN modules ON modules.id = messages.module_id
+             WHERE messages_trigram MATCH ?1
+             ORDER BY messages_trigram.rank
+             LIMIT ?2",
+        )?;
+        let candidates = (limit * FUZZY_CANDIDATES_PER_HIT) as i64;
+        let mut hits = stmt
+            .query_map(params![query, candidates], search_hit)?
+            .collect::<rusqlite::Result<Vec<SearchHit>>>()?;
+
+        for hit in &mut hits {
+            let text_trigrams = trigrams(&hit.text);
+            let shared = query_trigrams.intersection(&text_trigrams).count();
+            hit.similarity = Some(shared as f64 / query_trigrams.len() as f64);
+        }
+        hits.sort_by(|a, b| b.similarity.partial_cmp(&a.similarity).unwrap());
+        hits.truncate(limit);
+        Ok(hits)
+    }
+}
+
+fn search_hit(row: &rusqlite::Row) -> rusqlite::Result<SearchHit> {
+    Ok(SearchHit {
+        path: row.get(0)?,
+        id: row.get(1)?,
+        lang: row.get(2)?,
+        text: row.get(3)?,
+        similarity: None,
+    })
+}
+
+// Case-insensitive, with runs of whitespace collapsed so line breaks in the catalog don't
+// count against a match.
+fn trigrams(text: &str) -> HashSet<String> {
+    let normalized: Vec<char> = text
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .to_lowercase()
+        .chars()
+        .collect();
+    normalized
+        .windows(3)
+        .map(|window| window.iter().collect())
+        .collect()
+}
+
+fn remove_module(conn: &Connection, path: &str) -> anyhow::Result<()> {
+    conn.execute(
+        "DELETE FROM messages WHERE module_id IN (SELECT id FROM modules WHERE path = ?1)",
+        params![path],
+    )?;
+    conn.execute("DELETE FROM modules WHERE path = ?1", params![path])?;
+    Ok(())
+}
+
+fn file_stamp(path: &Path) -> anyhow::Result<(i64, i64)> {
+    let metadata = fs::metadata(path)?;
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs();
+    Ok((metadata.len() as i64, modified as i64))
+}
@@ -0,0 +1,91 @@
+use std::io::{self, Write};
+
+use crate::lint::{Check, Problem};
+use crate::output::{self, LangFormat};
+
+// Writes lint results as JUnit XML: a test suite per module with a test case per check, so
+// CI dashboards show each check that found problems as a failing test.
+pub fn write_lint_results(
+    out: &mut dyn Write,
+    results: &[(String, Vec<Problem>)],
+    lang_format: LangFormat,
+) -> io::Result<()> {
+    let total_failures: usize = results
+        .iter()
+        .map(|(_, problems)| failed_checks(problems))
+        .sum();
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        out,
+        r#"<testsuites name="lint" tests="{}" failures="{}">"#,
+        results.len() * Check::ALL.len(),
+        total_failures
+    )?;
+    for (module, problems) in results {
+        writeln!(
+            out,
+            r#"  <testsuite name="{}" tests="{}" failures="{}">"#,
+            escape(module),
+            Check::ALL.len(),
+            failed_checks(problems)
+        )?;
+        for check in Check::ALL {
+            let found: Vec<&Problem> = problems
+                .iter()
+                .filter(|problem| problem.check == check)
+                .collect();
+            let name = escape(check.name());
+            if found.is_empty() {
+                writeln!(
+                    out,
+                    r#"    <testcase classname="{}" name="{}"/>"#,
+                    escape(module),
+                    name
+                )?;
+                continue;
+            }
+            writeln!(
+                out,
+                r#"    <testcase classname="{}" name="{}">"#,
+                escape(module),
+                name
+            )?;
+            writeln!(out, r#"      <failure message="{} problems">"#, found.len())?;
+            for problem in found {
+                writeln!(
+                    out,
+                    "{:#010x} [{}]: {}",
+                    problem.id,
+                    escape(&output::format_lang(lang_format, problem.lang)),
+                    escape(&problem.message)
+                )?;
+            }
+            writeln!(out, "      </failure>")?;
+            writeln!(out, "    </testcase>")?;
+        }
+        writeln!(out, "  </testsuite>")?;
+    }
+    writeln!(out, "</testsuites>")
+}
+
+fn failed_checks(problems: &[Problem]) -> usize {
+    Check::ALL
+        .iter()
+        .filter(|&&check| problems.iter().any(|problem| problem.check == check))
+        .count()
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
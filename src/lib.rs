@@ -0,0 +1,109 @@
+// Without a backend, only the parser is built: MessageTable::from_bytes and msgtable.
+// live reads modules through the Win32 loader, offline parses PE files on any platform,
+// cli adds everything the command-line tool needs, com its COM automation server, and tokio
+// async variants of the scanning APIs.
+
+#[cfg(feature = "cli")]
+pub mod annotate;
+#[cfg(any(feature = "live", feature = "offline"))]
+pub mod catalog;
+#[cfg(feature = "com")]
+pub mod com;
+#[cfg(feature = "cli")]
+pub mod compress;
+#[cfg(feature = "cli")]
+pub mod config;
+pub mod decode;
+#[cfg(feature = "cli")]
+pub mod diff;
+#[cfg(any(feature = "live", feature = "offline"))]
+pub mod dump;
+#[cfg(feature = "cli")]
+pub mod evtx;
+#[cfg(feature = "cli")]
+pub mod explain;
+pub mod export;
+#[cfg(feature = "cli")]
+pub mod facility;
+#[cfg(feature = "cli")]
+pub mod fallback;
+#[cfg(feature = "cli")]
+pub mod filter;
+pub mod fixture;
+#[cfg(feature = "cli")]
+pub mod formatter;
+pub mod gaps;
+#[cfg(feature = "cli")]
+pub mod hash;
+#[cfg(feature = "cli")]
+pub mod headers;
+#[cfg(feature = "cli")]
+pub mod index;
+#[cfg(feature = "cli")]
+pub mod junit;
+pub mod lint;
+#[cfg(feature = "cli")]
+pub mod manifest;
+pub mod message;
+pub mod msgtable;
+#[cfg(feature = "tokio")]
+pub mod nonblocking;
+#[cfg(feature = "cli")]
+pub mod output;
+pub mod paths;
+#[cfg(feature = "offline")]
+pub mod pe;
+#[cfg(feature = "cli")]
+pub mod policy;
+#[cfg(feature = "cli")]
+pub mod privilege;
+#[cfg(feature = "cli")]
+pub mod proto;
+pub mod provider;
+#[cfg(feature = "cli")]
+pub mod providers;
+#[cfg(feature = "cli")]
+pub mod reference;
+#[cfg(feature = "cli")]
+pub mod resolve;
+pub mod resource;
+#[cfg(feature = "cli")]
+pub mod sandbox;
+#[cfg(feature = "cli")]
+pub mod scan;
+#[cfg(feature = "cli")]
+pub mod services;
+#[cfg(feature = "cli")]
+pub mod signature;
+#[cfg(feature = "cli")]
+pub mod strings;
+#[cfg(feature = "cli")]
+pub mod symbols;
+#[cfg(feature = "live")]
+pub mod sys;
+pub mod table;
+#[cfg(feature = "cli")]
+pub mod ui;
+#[cfg(feature = "live")]
+pub mod verify;
+#[cfg(feature = "cli")]
+pub mod vss;
+#[cfg(feature = "cli")]
+pub mod winsxs;
+
+#[cfg(any(feature = "live", feature = "offline"))]
+pub use catalog::MessageCatalog;
+#[cfg(any(feature = "live", feature = "offline"))]
+pub use dump::{
+    for_each_entry, for_each_entry_with, read_message_table_with, DumpOptions, Languages,
+};
+pub use message::MessageEntry;
+pub use msgtable::{Decoding, Encoding, Entry};
+pub use provider::{MemoryResources, ResourceProvider, ResourceRef};
+pub use resource::{
+    Accelerator, AcceleratorTable, Dialog, DialogControl, ExtractedFile, Menu, MenuItem,
+    StringEntry, VersionInfo,
+};
+#[cfg(feature = "live")]
+pub use sys::Module;
+pub use table::{MessageTable, RawMessageTable, ResourceId};
@@ -0,0 +1,704 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::decode::DecodedId;
+use crate::msgtable::{Encoding, Entry, BLOCK_SIZE, ENTRY_HEADER_SIZE};
+use crate::table::{MessageTable, ResourceId};
+
+// MESSAGE_RESOURCE_DATA's NumberOfBlocks, which precedes the blocks.
+const BLOCK_COUNT_SIZE: usize = 4;
+
+// printf conversions and the length modifiers that may precede them. n is left out
+// because %n is FormatMessage's line break.
+const PRINTF_CONVERSIONS: &str = "cCdiouxXeEfgGpsSZ";
+const PRINTF_LENGTH_MODIFIERS: &str = "hlwI";
+
+// How a message ends. mc terminates every message with CRLF unless it ends with %0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Terminator {
+    Crlf,
+    Suppressed,
+    BareLf,
+    Missing,
+}
+
+impl Terminator {
+    pub fn of(text: &str) -> Terminator {
+        let trimmed = text.strip_suffix("\r\n").unwrap_or(text);
+        if trimmed.ends_with("%0") {
+            Terminator::Suppressed
+        } else if text.ends_with("\r\n") {
+            Terminator::Crlf
+        } else if text.ends_with('\n') {
+            Terminator::BareLf
+        } else {
+            Terminator::Missing
+        }
+    }
+}
+
+// The checks lint runs, each of which can flag an entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    Inserts,
+    Terminator,
+    Utf16,
+    Untranslated,
+    TranslatedInserts,
+    Spelling,
+    BannedTerms,
+    EntryBudget,
+    SizeBudget,
+}
+
+impl Check {
+    pub const ALL: [Check; 9] = [
+        Check::Inserts,
+        Check::Terminator,
+        Check::Utf16,
+        Check::Untranslated,
+        Check::TranslatedInserts,
+        Check::Spelling,
+        Check::BannedTerms,
+        Check::EntryBudget,
+        Check::SizeBudget,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Check::Inserts => "inserts",
+            Check::Terminator => "terminator",
+            Check::Utf16 => "utf16",
+            Check::Untranslated => "untranslated",
+            Check::TranslatedInserts => "translated-inserts",
+            Check::Spelling => "spelling",
+            Check::BannedTerms => "banned-terms",
+            Check::EntryBudget => "entry-budget",
+            Check::SizeBudget => "size-budget",
+        }
+    }
+}
+
+pub struct Problem {
+    pub id: u32,
+    pub lang: u16,
+    pub check: Check,
+    pub message: String,
+}
+
+// An escape sequence as FormatMessage would see it, or a printf-style conversion that
+// FormatMessage would print literally.
+enum Escape {
+    Insert(u32),
+    Printf(String),
+}
+
+// How much of a localized language differs from the base language.
+pub struct Coverage {
+    pub lang: u16,
+    pub total: usize,
+    pub untranslated: usize,
+}
+
+// Word lists for the optional dictionary checks. Lists are plain text with an entry per line;
+// blank lines and lines starting with # are skipped.
+#[derive(Debug, Clone, Default)]
+pub struct Dictionary {
+    // Lowercased.
+    words: HashSet<String>,
+    // Lowercased terms, which may be several words long, and what to use instead.
+    banned: Vec<(String, Option<String>)>,
+}
+
+impl Dictionary {
+    pub fn new() -> Dictionary {
+        Dictionary::default()
+    }
+
+    // Adds a list of correctly spelled words. Words are matched case-insensitively.
+    pub fn add_words(&mut self, list: &str) {
+        self.words
+            .extend(list_lines(list).map(|word| word.to_lowercase()));
+    }
+
+    // Adds a list of terms that mustn't appear, such as deprecated product names, each
+    // optionally followed by = and its replacement.
+    pub fn add_banned_terms(&mut self, list: &str) {
+        for line in list_lines(list) {
+            let (term, replacement) = match line.split_once('=') {
+                Some((term, replacement)) => (term.trim(), Some(replacement.trim().to_string())),
+                None => (line, None),
+            };
+            if !term.is_empty() {
+                self.banned.push((term.to_lowercase(), replacement));
+            }
+        }
+    }
+}
+
+fn list_lines(list: &str) -> impl Iterator<Item = &str> {
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+// Entries of other languages are compared against base_lang. With a dictionary, spelling is
+// checked in base_lang, which the word lists are taken to be in, and banned terms in every
+// language.
+pub fn lint(
+    tables: &[MessageTable],
+    base_lang: u16,
+    dictionary: Option<&Dictionary>,
+) -> Vec<Problem> {
+    let base = base_entries(tables, base_lang);
+    let mut problems = Vec::new();
+    for table in tables {
+        // Entries are only flagged for ending differently from the rest of their table.
+        let crlf = table
+            .entries
+            .iter()
+            .filter(|entry| Terminator::of(&entry.text) == Terminator::Crlf)
+            .count();
+        let suppressed = table
+            .entries
+            .iter()
+            .filter(|entry| Terminator::of(&entry.text) == Terminator::Suppressed)
+            .count();
+        let usual = if suppressed > crlf {
+            Terminator::Suppressed
+        } else {
+            Terminator::Crlf
+        };
+
+        for entry in &table.entries {
+            let mut messages: Vec<(Check, String)> = Vec::new();
+            let mut add = |check, found: Vec<String>| {
+                messages.extend(found.into_iter().map(|message| (check, message)));
+            };
+            add(Check::Inserts, check_inserts(&entry.text));
+            add(
+                Check::Terminator,
+                check_terminator(&entry.text, usual).into_iter().collect(),
+            );
+            add(Check::Utf16, check_utf16(entry));
+            if table.lang != base_lang {
+                if let Some(base_entry) = base.get(&(&table.resource, entry.id)) {
+                    if is_untranslated(entry, base_entry) {
+                        add(
+                            Check::Untranslated,
+                            vec!["is identical to the base language".to_string()],
+                        );
+                    }
+                    add(
+                        Check::TranslatedInserts,
+                        check_translated_inserts(&entry.text, &base_entry.text)
+                            .into_iter()
+                            .collect(),
+                    );
+                }
+            }
+            if let Some(dictionary) = dictionary {
+                if table.lang == base_lang && !dictionary.words.is_empty() {
+                    add(Check::Spelling, check_spelling(&entry.text, dictionary));
+                }
+                add(
+                    Check::BannedTerms,
+                    check_banned_terms(&entry.text, dictionary),
+                );
+            }
+            for (check, message) in messages {
+                problems.push(Problem {
+                    id: entry.id,
+                    lang: table.lang,
+                    check,
+                    message,
+                });
+            }
+        }
+    }
+    problems
+}
+
+// Limits on how big a module's message tables may grow.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Budget {
+    // Entries each facility may have in each language. As with gaps::find_gaps, IDs that
+    // share a facility but not a severity or customer bit count separately, since they're
+    // separate ranges of 64K.
+    pub max_entries_per_facility: Option<usize>,
+    // Bytes the MESSAGETABLE resources may take up altogether, in every language.
+    pub max_resource_size: Option<usize>,
+}
+
+// The size of table's resource data: the block count, the blocks and their entries.
+pub fn resource_size(table: &MessageTable) -> usize {
+    BLOCK_COUNT_SIZE
+        + table.blocks.len() * BLOCK_SIZE
+        + table
+            .entries
+            .iter()
+            .map(|entry| usize::from(entry.length))
+            .sum::<usize>()
+}
+
+// Flags each facility with more entries in a language than budget allows, as a problem with
+// the facility's first ID, and the module if its tables are bigger than budget allows, as a
+// problem with ID 0 in the first table's language.
+pub fn check_budget(tables: &[MessageTable], budget: &Budget) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    if let Some(max) = budget.max_entries_per_facility {
+        // Keyed by the top 16 bits of the ID and the language.
+        let mut counts: BTreeMap<(u32, u16), usize> = BTreeMap::new();
+        for table in tables {
+            for entry in &table.entries {
+                *counts
+                    .entry((entry.id & 0xffff_0000, table.lang))
+                    .or_default() += 1;
+            }
+        }
+        for ((first_id, lang), count) in counts.into_iter().filter(|&(_, count)| count > max) {
+            problems.push(Problem {
+                id: first_id,
+                lang,
+                check: Check::EntryBudget,
+                message: format!(
+                    "facility {:#x}, IDs {:#x}-{:#x}, has {} entries, over the budget of {}",
+                    DecodedId::new(first_id).facility,
+                    first_id,
+                    first_id | 0xffff,
+                    count,
+                    max
+                ),
+            });
+        }
+    }
+    if let Some(max) = budget.max_resource_size {
+        let size: usize = tables.iter().map(resource_size).sum();
+        if size > max {
+            problems.push(Problem {
+                id: 0,
+                lang: tables.first().map_or(0, |table| table.lang),
+                check: Check::SizeBudget,
+                message: format!(
+                    "the message tables take up {} bytes, over the budget of {}",
+                    size, max
+                ),
+            });
+        }
+    }
+    problems
+}
+
+// A Win32 error code and its HRESULT_FROM_WIN32 form, 0x8007xxxx, both with a message in the
+// same language, in one module or two.
+pub struct HresultDuplicate {
+    pub code: u32,
+    pub code_module: String,
+    pub hresult_module: String,
+    pub lang: u16,
+    // Compared without trailing line breaks and spaces.
+    pub texts_match: bool,
+}
+
+impl HresultDuplicate {
+    pub fn hresult(&self) -> u32 {
+        HRESULT_WIN32_BASE | self.code
+    }
+}
+
+const HRESULT_WIN32_BASE: u32 = 0x8007_0000;
+
+// Finds every code defined in both forms across modules, given as (name, tables) pairs. A
+// code defined by several modules is paired with each of them.
+pub fn hresult_duplicates(modules: &[(String, Vec<MessageTable>)]) -> Vec<HresultDuplicate> {
+    // (lang, id) to every module that defines it and its text there.
+    let mut defined: BTreeMap<(u16, u32), Vec<(&str, &str)>> = BTreeMap::new();
+    for (module, tables) in modules {
+        for table in tables {
+            for entry in &table.entries {
+                defined
+                    .entry((table.lang, entry.id))
+                    .or_default()
+                    .push((module, &entry.text));
+            }
+        }
+    }
+    let mut duplicates = Vec::new();
+    for (&(lang, id), hresult_defs) in &defined {
+        if id & 0xffff_0000 != HRESULT_WIN32_BASE || id == HRESULT_WIN32_BASE {
+            continue;
+        }
+        let code = id & 0xffff;
+        let code_defs = match defined.get(&(lang, code)) {
+            Some(code_defs) => code_defs,
+            None => continue,
+        };
+        for &(code_module, code_text) in code_defs {
+            for &(hresult_module, hresult_text) in hresult_defs {
+                duplicates.push(HresultDuplicate {
+                    code,
+                    code_module: code_module.to_string(),
+                    hresult_module: hresult_module.to_string(),
+                    lang,
+                    texts_match: code_text.trim_end() == hresult_text.trim_end(),
+                });
+            }
+        }
+    }
+    duplicates
+}
+
+// An ID a module defines more than once with different text, where FormatMessage could
+// return any of the definitions: in several resources of the same language, or in a neutral
+// language as well as another.
+pub struct IdConflict {
+    pub id: u32,
+    // Every definition of the ID, as (resource, language) pairs.
+    pub definitions: Vec<(ResourceId, u16)>,
+}
+
+pub fn id_conflicts(tables: &[MessageTable]) -> Vec<IdConflict> {
+    let mut definitions: BTreeMap<u32, Vec<(&MessageTable, &str)>> = BTreeMap::new();
+    for table in tables {
+        for entry in &table.entries {
+            definitions
+                .entry(entry.id)
+                .or_default()
+                .push((table, &entry.text));
+        }
+    }
+    let is_neutral = |lang: u16| lang & 0x3ff == 0;
+    let mut conflicts = Vec::new();
+    for (id, defs) in definitions {
+        let conflicting = defs.iter().enumerate().any(|(i, (a, a_text))| {
+            defs[i + 1..].iter().any(|(b, b_text)| {
+                a_text != b_text && (a.lang == b.lang || is_neutral(a.lang) || is_neutral(b.lang))
+            })
+        });
+        if conflicting {
+            conflicts.push(IdConflict {
+                id,
+                definitions: defs
+                    .iter()
+                    .map(|(table, _)| (table.resource.clone(), table.lang))
+                    .collect(),
+            });
+        }
+    }
+    conflicts
+}
+
+pub fn coverage(tables: &[MessageTable], base_lang: u16) -> Vec<Coverage> {
+    let base = base_entries(tables, base_lang);
+    let mut coverage: Vec<Coverage> = Vec::new();
+    for table in tables.iter().filter(|table| table.lang != base_lang) {
+        let index = match coverage.iter().position(|c| c.lang == table.lang) {
+            Some(index) => index,
+            None => {
+                coverage.push(Coverage {
+                    lang: table.lang,
+                    total: 0,
+                    untranslated: 0,
+                });
+                coverage.len() - 1
+            }
+        };
+        for entry in &table.entries {
+            if let Some(base_entry) = base.get(&(&table.resource, entry.id)) {
+                coverage[index].total += 1;
+                if is_untranslated(entry, base_entry) {
+                    coverage[index].untranslated += 1;
+                }
+            }
+        }
+    }
+    coverage
+}
+
+fn base_entries(tables: &[MessageTable], base_lang: u16) -> HashMap<(&ResourceId, u32), &Entry> {
+    tables
+        .iter()
+        .filter(|table| table.lang == base_lang)
+        .flat_map(|table| {
+            table
+                .entries
+                .iter()
+                .map(move |entry| ((&table.resource, entry.id), entry))
+        })
+        .collect()
+}
+
+// Messages made up only of inserts and punctuation, such as "%1\r\n", read the same in
+// every language.
+fn is_untranslated(entry: &Entry, base_entry: &Entry) -> bool {
+    entry.text == base_entry.text && entry.text.chars().any(|c| c.is_alphabetic())
+}
+
+// FormatMessage reads past the supplied arguments when a translation references an insert
+// the base language doesn't, and silently drops the ones it leaves out.
+fn check_translated_inserts(text: &str, base_text: &str) -> Option<String> {
+    let translated = inserts(text);
+    let base = inserts(base_text);
+    if translated == base {
+        return None;
+    }
+    let list = |indexes: BTreeSet<&u32>| {
+        indexes
+            .into_iter()
+            .map(|index| format!("%{}", index))
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+    let missing: BTreeSet<&u32> = base.difference(&translated).collect();
+    let extra: BTreeSet<&u32> = translated.difference(&base).collect();
+    let mut differences = Vec::new();
+    if !missing.is_empty() {
+        differences.push(format!("lacks {}", list(missing)));
+    }
+    if !extra.is_empty() {
+        differences.push(format!("adds {}", list(extra)));
+    }
+    Some(format!(
+        "inserts differ from the base language: {}",
+        differences.join(" and ")
+    ))
+}
+
+// Words with a capital after their first letter are taken to be acronyms or identifiers and
+// aren't checked.
+fn check_spelling(text: &str, dictionary: &Dictionary) -> Vec<String> {
+    let mut unknown: Vec<String> = Vec::new();
+    for word in words(&strip_escapes(text)) {
+        let word = word.strip_suffix("'s").unwrap_or(word);
+        if word.chars().count() < 2 || word.chars().skip(1).any(char::is_uppercase) {
+            continue;
+        }
+        let lowercase = word.to_lowercase();
+        if !dictionary.words.contains(&lowercase) && !unknown.contains(&lowercase) {
+            unknown.push(lowercase);
+        }
+    }
+    unknown
+        .into_iter()
+        .map(|word| format!("unknown word \"{}\"", word))
+        .collect()
+}
+
+fn check_banned_terms(text: &str, dictionary: &Dictionary) -> Vec<String> {
+    // Terms are matched against whole words, with any run of spaces between them.
+    let text = format!(" {} ", words(&strip_escapes(text)).join(" ").to_lowercase());
+    dictionary
+        .banned
+        .iter()
+        .filter(|(term, _)| {
+            let term = words(term).join(" ");
+            !term.is_empty() && text.contains(&format!(" {} ", term))
+        })
+        .map(|(term, replacement)| match replacement {
+            Some(replacement) => format!("uses \"{}\"; use \"{}\" instead", term, replacement),
+            None => format!("uses banned term \"{}\"", term),
+        })
+        .collect()
+}
+
+// Runs of letters and apostrophes, without leading or trailing apostrophes.
+fn words(text: &str) -> Vec<&str> {
+    text.split(|c: char| !c.is_alphabetic() && c != '\'')
+        .map(|word| word.trim_matches('\''))
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+// Replaces every escape sequence, insert and printf-style specifier with a space, so they
+// neither count as words nor run into the words next to them.
+fn strip_escapes(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            stripped.push(c);
+            continue;
+        }
+        stripped.push(' ');
+        match chars.next() {
+            // An insert may carry a printf format between exclamation marks, as in %1!s!.
+            Some(c) if c.is_ascii_digit() => {
+                while chars.peek().map_or(false, char::is_ascii_digit) {
+                    chars.next();
+                }
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    for c in chars.by_ref() {
+                        if c == '!' {
+                            break;
+                        }
+                    }
+                }
+            }
+            Some(c) if PRINTF_LENGTH_MODIFIERS.contains(c) => {
+                while chars
+                    .peek()
+                    .map_or(false, |&c| PRINTF_LENGTH_MODIFIERS.contains(c))
+                {
+                    chars.next();
+                }
+                if chars
+                    .peek()
+                    .map_or(false, |&c| PRINTF_CONVERSIONS.contains(c))
+                {
+                    chars.next();
+                }
+            }
+            _ => {}
+        }
+    }
+    stripped
+}
+
+fn check_terminator(text: &str, usual: Terminator) -> Option<String> {
+    match Terminator::of(text) {
+        Terminator::BareLf => Some("ends with LF instead of CRLF".to_string()),
+        Terminator::Missing => Some("ends without CRLF or %0".to_string()),
+        Terminator::Crlf if usual == Terminator::Suppressed => {
+            Some("ends with CRLF while the rest of its table ends with %0".to_string())
+        }
+        Terminator::Suppressed if usual == Terminator::Crlf => {
+            Some("ends with %0 while the rest of its table ends with CRLF".to_string())
+        }
+        _ => None,
+    }
+}
+
+// Decoding replaces these with U+FFFD or cuts the text short, so they're checked for in the
+// raw entry. Offsets are relative to the start of the resource.
+fn check_utf16(entry: &Entry) -> Vec<String> {
+    let mut messages = Vec::new();
+    if entry.encoding != Encoding::Unicode {
+        return messages;
+    }
+    // Tables read without their raw bytes have nothing to check.
+    let text = match entry.raw.get(ENTRY_HEADER_SIZE..) {
+        Some(text) => text,
+        None => return messages,
+    };
+    let units: Vec<u16> = text
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .collect();
+    let unit_offset = |i: usize| entry.offset as usize + ENTRY_HEADER_SIZE + i * 2;
+
+    // Trailing NULs are the terminator and padding; any before the last character aren't.
+    let end = units
+        .iter()
+        .rposition(|&unit| unit != 0)
+        .map_or(0, |last| last + 1);
+    let mut i = 0;
+    while i < end {
+        let unit = units[i];
+        match unit {
+            0 => messages.push(format!("embedded NUL at offset {:#x}", unit_offset(i))),
+            0xd800..=0xdbff if matches!(units.get(i + 1), Some(0xdc00..=0xdfff)) => i += 1,
+            0xd800..=0xdfff => messages.push(format!(
+                "unpaired surrogate {:#06x} at offset {:#x}",
+                unit,
+                unit_offset(i)
+            )),
+            _ => {}
+        }
+        i += 1;
+    }
+    messages
+}
+
+fn escapes(text: &str) -> Vec<Escape> {
+    let mut escapes = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            continue;
+        }
+        match chars.peek().copied() {
+            // Inserts are %1 through %99. %0 suppresses the trailing line break.
+            Some(c) if c.is_ascii_digit() => {
+                let mut index = 0;
+                let mut digits = 0;
+                while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                    if digits == 2 {
+                        break;
+                    }
+                    index = index * 10 + digit;
+                    digits += 1;
+                    chars.next();
+                }
+                escapes.push(Escape::Insert(index));
+            }
+            Some(c) if PRINTF_CONVERSIONS.contains(c) || PRINTF_LENGTH_MODIFIERS.contains(c) => {
+                let mut spec = String::from("%");
+                while let Some(c) = chars.peek().copied() {
+                    if !PRINTF_LENGTH_MODIFIERS.contains(c) {
+                        break;
+                    }
+                    spec.push(c);
+                    chars.next();
+                }
+                if let Some(c) = chars.peek().copied() {
+                    if PRINTF_CONVERSIONS.contains(c) {
+                        spec.push(c);
+                        chars.next();
+                        escapes.push(Escape::Printf(spec));
+                    }
+                }
+            }
+            // %%, %n, %r, %t, %b, %. and friends.
+            Some(_) => {
+                chars.next();
+            }
+            None => {}
+        }
+    }
+    escapes
+}
+
+// Returns the indexes of the inserts text references, ignoring %0.
+fn inserts(text: &str) -> BTreeSet<u32> {
+    escapes(text)
+        .into_iter()
+        .filter_map(|escape| match escape {
+            Escape::Insert(index) if index > 0 => Some(index),
+            _ => None,
+        })
+        .collect()
+}
+
+fn check_inserts(text: &str) -> Vec<String> {
+    let mut messages = Vec::new();
+    let inserts = inserts(text);
+    let printf_specs: Vec<String> = escapes(text)
+        .into_iter()
+        .filter_map(|escape| match escape {
+            Escape::Printf(spec) => Some(spec),
+            _ => None,
+        })
+        .collect();
+    if !inserts.is_empty() && !printf_specs.is_empty() {
+        messages.push(format!(
+            "mixes FormatMessage inserts with printf-style specifiers ({})",
+            printf_specs.join(", ")
+        ));
+    }
+
+    // FormatMessage needs an argument for every index up to the highest one.
+    if let Some(&highest) = inserts.iter().next_back() {
+        let missing: Vec<String> = (1..highest)
+            .filter(|index| !inserts.contains(index))
+            .map(|index| format!("%{}", index))
+            .collect();
+        if !missing.is_empty() {
+            messages.push(format!(
+                "uses %{} but never {}",
+                highest,
+                missing.join(", ")
+            ));
+        }
+    }
+    messages
+}
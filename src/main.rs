@@ -1,146 +1,1655 @@
-use std::ffi::c_void;
-use std::fmt;
-use std::mem;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use windows::core::*;
-use windows::Win32::Foundation::*;
+use anyhow::{bail, Context};
+use clap::{Parser, Subcommand, ValueEnum};
 use windows::Win32::System::LibraryLoader::*;
-use windows::Win32::UI::WindowsAndMessaging::*;
+
+#[cfg(feature = "com")]
+use dump_message_tables::com;
+use dump_message_tables::compress::{self, Compression, SharedWriter};
+use dump_message_tables::config::Config;
+use dump_message_tables::decode::DecodedId;
+use dump_message_tables::diff::DiffFormat;
+use dump_message_tables::explain::ExplainFormat;
+use dump_message_tables::facility::{FacilityNames, Namespace};
+use dump_message_tables::fallback::LangFallback;
+use dump_message_tables::filter::Filter;
+use dump_message_tables::formatter::{
+    write_module, EntryOutput, Formatters, ModuleOutput, OutputFormatter, ResxNames,
+};
+use dump_message_tables::headers::Headers;
+use dump_message_tables::index::Index;
+use dump_message_tables::manifest::{Journal, Manifest, ManifestModule};
+use dump_message_tables::output::{
+    self, Hyperlinks, IdFormat, LangFormat, Normalization, Only, OutputFormat, Printer,
+};
+use dump_message_tables::policy::LoadPolicy;
+use dump_message_tables::providers::Registry;
+use dump_message_tables::reference::Reference;
+use dump_message_tables::scan::{CancellationToken, NameFilter, NamePattern};
+use dump_message_tables::strings::ModuleStrings;
+use dump_message_tables::symbols::{self, Symbols};
+use dump_message_tables::sys::Module;
+use dump_message_tables::ui::{self, Msg, Ui};
+use dump_message_tables::vss::ShadowCopies;
+use dump_message_tables::{
+    annotate, diff, evtx, explain, export, fixture, hash, junit, lint, pe, privilege, proto,
+    provider, providers, resolve, resource, sandbox, scan, services, signature, strings, verify,
+    winsxs,
+};
+use dump_message_tables::{MessageTable, ResourceId};
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Dump the message tables contained in Windows modules",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Modules to dump, either paths or names resolved by the loader
+    #[arg(required_unless_present_any = ["winsxs", "services", "emit_schema", "emit_ui_catalog"])]
+    modules: Vec<String>,
+
+    /// Scan the WinSxS component store, grouping tables by component and version
+    #[arg(long, conflicts_with = "modules")]
+    winsxs: bool,
+
+    /// Dump the binary of every installed service and driver, as registered with the SCM
+    #[arg(long, conflicts_with_all = ["modules", "winsxs"])]
+    services: bool,
+
+    /// Also render about N entries of each module through FormatMessageW and report
+    /// any that differ from what was decoded, or every entry with 0
+    #[arg(
+        long,
+        value_name = "N",
+        num_args = 0..=1,
+        default_missing_value = "100",
+        conflicts_with = "no_loadlibrary"
+    )]
+    verify_against_os: Option<usize>,
+
+    /// Parse modules in a low-integrity, job-restricted child process without loading
+    /// them, for modules that may have been crafted to attack the parser
+    #[arg(long, conflicts_with_all = ["winsxs", "services", "verify_against_os"])]
+    sandbox: bool,
+
+    /// Give up on a module whose message tables take longer than SECS to read, failing it
+    /// like any module that can't be read, so a pathological file can't stall a long run.
+    /// Since a load that stalls in the loader can't be given up on, modules are all parsed
+    /// offline, as with --no-loadlibrary; with --sandbox, the parser is killed instead
+    #[arg(long, value_name = "SECS")]
+    timeout_per_module: Option<u64>,
+
+    /// Enable SeBackupPrivilege and parse every module offline, opening files for backup,
+    /// so ACL-restricted directories such as WindowsApps can be read without changing
+    /// their ACLs. Needs administrator rights
+    #[arg(long, conflicts_with_all = ["sandbox", "verify_against_os"])]
+    backup_privilege: bool,
+
+    /// Read modules from a shadow copy of their volume, so files that are locked or
+    /// protected can still be parsed offline. Needs administrator rights
+    #[arg(
+        long,
+        conflicts_with_all = ["winsxs", "services", "sandbox", "verify_against_os"]
+    )]
+    vss: bool,
+
+    /// Also dump the SysWOW64 copy of each System32 module, labelling each with its bitness
+    #[arg(long, conflicts_with_all = ["winsxs", "services", "verify_against_os"])]
+    both_arch: bool,
+
+    /// Report each module's Authenticode signature: whether it's signed, embedded or by
+    /// catalog, the signer and the timestamp
+    #[arg(long)]
+    signature: bool,
+
+    /// Report IDs a module defines more than once with different text, in several
+    /// MESSAGETABLE resources or in a neutral language as well as another, as errors
+    #[arg(long, conflicts_with_all = ["winsxs", "services"])]
+    unique_ids: bool,
+
+    /// Report the IDs each module defines that are missing from FILE, a JSON list of codes
+    /// or an object keyed by code, such as a list of documented error codes
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["winsxs", "services"])]
+    reference: Option<PathBuf>,
+
+    /// Re-encode every language of each module's message tables with the built-in encoder
+    /// and report any byte that differs from the original resource
+    #[arg(long, conflicts_with_all = ["winsxs", "services", "sandbox"])]
+    verify_roundtrip: bool,
+
+    /// Merge every language of each module into the single table a user with these
+    /// languages would see, filling in untranslated entries by Windows' fallback rules,
+    /// e.g. fr-FR
+    #[arg(
+        long,
+        value_name = "LOCALES",
+        value_parser = LangFallback::parse,
+        conflicts_with_all = ["winsxs", "services", "show_blocks"]
+    )]
+    effective_lang: Option<LangFallback>,
+
+    /// Also write each module's icons, cursors and bitmaps to DIR as .ico, .cur and .bmp
+    /// files, named <module>-<resource>-<LANGID>
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["winsxs", "services", "sandbox"])]
+    extract: Option<PathBuf>,
+
+    /// The kinds of resource --extract writes [default: all]
+    #[arg(long, value_enum, value_delimiter = ',', requires = "extract")]
+    extract_kinds: Vec<ExtractKind>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text, conflicts_with_all = ["winsxs", "services"])]
+    format: OutputFormat,
+
+    /// How --format resx names each message: {id} is the ID as eight hex digits, {decimal}
+    /// the ID in decimal and {symbol} its name from --pdb, falling back to the default
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        value_parser = ResxNames::parse,
+        default_value = ResxNames::DEFAULT
+    )]
+    resx_names: ResxNames,
+
+    /// File to write the dump to [default: stdout]
+    #[arg(short, long, conflicts_with_all = ["winsxs", "services"])]
+    output: Option<PathBuf>,
+
+    /// Write each module to its own file in DIR, named <hash>-<name> after the module's
+    /// SHA-256 and file name
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["winsxs", "services", "output"])]
+    out_dir: Option<PathBuf>,
+
+    /// Skip the modules an interrupted --out-dir run already finished, as recorded in the
+    /// journal it keeps there
+    #[arg(long, requires = "out_dir")]
+    resume: bool,
+
+    /// Print the schema of the output format, the JSON Schema for --format json or the
+    /// protobuf schema for --format proto, and exit
+    #[arg(long, conflicts_with_all = ["modules", "winsxs"])]
+    emit_schema: bool,
+
+    /// Instead of dumping message tables, write a JSON catalog of each module's strings
+    #[arg(
+        long,
+        value_enum,
+        value_name = "KIND",
+        conflicts_with_all = ["winsxs", "services", "out_dir", "sandbox", "format"]
+    )]
+    catalog: Option<CatalogKind>,
+
+    /// Print the tool's own messages as message compiler source for translators and exit
+    #[arg(long, conflicts_with_all = ["modules", "winsxs", "emit_schema"])]
+    emit_ui_catalog: bool,
+
+    /// Make output byte-identical across runs on identical inputs: modules, tables and
+    /// entries are sorted, and lines aren't truncated to the console width
+    #[arg(long)]
+    deterministic: bool,
+
+    /// Only include entries matching an expression over id, text, lang, langid, resource,
+    /// encoding, length, offset, severity, customer, facility and code, such as
+    /// "id >= 0x4000 && text.contains('RPC') && lang == 'en-US'"
+    #[arg(
+        long = "where",
+        value_name = "EXPR",
+        value_parser = Filter::parse,
+        conflicts_with_all = ["winsxs", "services"]
+    )]
+    filter: Option<Filter>,
+
+    /// Print only the IDs or only the text of each entry, without any decoration
+    #[arg(long, value_enum, conflicts_with_all = ["summary", "show_blocks"])]
+    only: Option<Only>,
+
+    /// Instead of dumping every entry, list the N largest messages of each module
+    #[arg(long, value_name = "N", conflicts_with_all = ["format", "winsxs", "only"])]
+    top_size: Option<usize>,
+
+    /// Instead of dumping every entry, list the IDs each facility leaves unused: empty
+    /// entries within blocks, and the ranges before, between and after them
+    #[arg(long, conflicts_with_all = ["format", "winsxs", "only", "top_size"])]
+    gaps: bool,
+
+    /// Label each entry with the encoding it is stored in (ANSI, Unicode or UTF-8)
+    #[arg(long)]
+    show_encoding: bool,
+
+    /// Mark the line breaks embedded in multi-line messages with ¶
+    #[arg(long)]
+    show_line_breaks: bool,
+
+    /// Print runs of consecutive IDs with identical text once, as a range
+    #[arg(long)]
+    collapse_runs: bool,
+
+    /// Print entry, block and encoding counts for each module
+    #[arg(long)]
+    summary: bool,
+
+    /// Include each entry's offset within the resource and its encoded length
+    #[arg(short, long)]
+    verbose: bool,
+
+    /// Include each entry's undecoded bytes, base64-encoded, in JSON output
+    #[arg(long)]
+    include_raw: bool,
+
+    /// Group entries under the MESSAGE_RESOURCE_BLOCK they are stored in
+    #[arg(long)]
+    show_blocks: bool,
+
+    /// Link Win32 error codes and HRESULTs to their documentation, in terminals that
+    /// support OSC 8 hyperlinks
+    #[arg(long, value_enum, default_value_t = Hyperlinks::Auto)]
+    hyperlinks: Hyperlinks,
+
+    /// Radix used to print IDs
+    #[arg(long, value_enum, default_value_t = IdFormat::Hex)]
+    id_format: IdFormat,
+
+    /// Zero-pad IDs to this many digits
+    #[arg(long, value_name = "DIGITS")]
+    id_width: Option<usize>,
+
+    /// Unicode normalization form applied to message text
+    #[arg(long, value_enum, default_value_t = Normalization::None)]
+    normalize: Normalization,
+
+    /// Truncate lines longer than this many characters in text output, or 0 to never
+    /// truncate [default: the width of the console]
+    #[arg(long, value_name = "CHARS")]
+    max_width: Option<usize>,
+
+    /// Decode each ID into its customer bit, facility and code
+    #[arg(long)]
+    decode: bool,
+
+    /// Label each ID with the severity encoded in bits 30-31
+    #[arg(long)]
+    decode_severity: bool,
+
+    /// Namespace used to name facilities when decoding
+    #[arg(long, value_enum, default_value_t = Namespace::Hresult)]
+    namespace: Namespace,
+
+    /// PDB used to name IDs after their symbolic constants, or "symsrv" to find each
+    /// module's PDB in the symbol stores listed in _NT_SYMBOL_PATH
+    #[arg(long, value_name = "PATH|symsrv", conflicts_with = "winsxs")]
+    pdb: Option<String>,
+
+    /// How languages are printed: as a LANGID, a BCP-47 locale name, or both
+    #[arg(long, value_enum, default_value_t = LangFormat::Bcp47, global = true)]
+    lang_format: LangFormat,
+
+    /// Never hand modules outside the config's trusted_dirs to the loader; parse them
+    /// offline instead
+    #[arg(long, global = true)]
+    no_loadlibrary: bool,
+
+    /// Directories to look for bare module names in, before the current directory and
+    /// System32
+    #[arg(long, value_name = "DIRS", value_delimiter = ';', global = true)]
+    search_path: Vec<PathBuf>,
+
+    /// Only look for bare module names on --search-path, never in the current directory,
+    /// System32 or the loader's search order
+    #[arg(long, global = true)]
+    no_default_search: bool,
+
+    /// Languages to try, in order, when picking .mui satellites and resource languages,
+    /// ahead of the OS's preferred UI languages, e.g. de-DE,de,en-US
+    #[arg(long, value_name = "LOCALES", value_parser = LangFallback::parse, global = true)]
+    lang_fallback: Option<LangFallback>,
+
+    /// Run at background CPU and I/O priority on a quarter of the CPUs, for long scans on
+    /// machines that are in use
+    #[arg(long, global = true)]
+    background: bool,
+
+    /// Compress files written with --output, --out-dir or --junit as they're written
+    #[arg(long, value_enum, global = true)]
+    compress: Option<Compression>,
+
+    /// Language of the tool's own messages [default: the user's UI language]
+    #[arg(long, value_name = "LANGID", value_parser = parse_lang, global = true)]
+    ui_lang: Option<u16>,
+
+    /// Configuration file [default: %APPDATA%\dump_msg_tables\config.toml]
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Only take modules whose file names match PATTERN when scanning directories with
+    /// --winsxs, diff-dirs or who-defines: a glob such as net*.dll, or a regex prefixed
+    /// with re:; may be given more than once
+    #[arg(long, value_name = "PATTERN", value_parser = NamePattern::parse, global = true)]
+    include: Vec<NamePattern>,
+
+    /// Skip modules whose file names match PATTERN when scanning directories, as for
+    /// --include, such as *.mui; may be given more than once
+    #[arg(long, value_name = "PATTERN", value_parser = NamePattern::parse, global = true)]
+    exclude: Vec<NamePattern>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ResourceKind {
+    /// Key combinations and the command IDs they send
+    Accelerators,
+    /// Dialogs' captions and their controls' classes, IDs, positions and text
+    Dialogs,
+    /// Menu hierarchies with each item's ID and text
+    Menus,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CatalogKind {
+    /// Every language of the message tables, string tables, dialog captions and control
+    /// text, and menu items, each with the kind of resource it came from
+    AllStrings,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ExtractKind {
+    Icons,
+    Cursors,
+    Bitmaps,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Explain a status code by trying every plausible interpretation of it
+    Explain {
+        /// Code to explain, in hex (0x...) or decimal
+        #[arg(value_parser = parse_id, allow_negative_numbers = true)]
+        code: u32,
+
+        /// Additional modules to look the code up in
+        #[arg(long = "module", value_name = "MODULE")]
+        modules: Vec<String>,
+
+        /// Output format; errlook mimics the Microsoft Error Lookup Tool (err.exe), naming
+        /// each interpretation after its #define in the Windows SDK headers
+        #[arg(long, value_enum, default_value_t = ExplainFormat::Text)]
+        format: ExplainFormat,
+    },
+
+    /// Compare the message tables of every module in two build output trees, pairing modules
+    /// by their path within the tree, or by name when they've moved, in a single report
+    DiffDirs {
+        /// The older tree
+        old: PathBuf,
+
+        /// The newer tree
+        new: PathBuf,
+
+        #[arg(long, value_enum, default_value_t = DiffFormat::Text)]
+        format: DiffFormat,
+
+        /// File to write the report to [default: stdout]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// List every module in a directory whose message tables define an ID
+    WhoDefines {
+        /// ID to look for, in hex (0x...) or decimal
+        #[arg(value_parser = parse_id, allow_negative_numbers = true)]
+        id: u32,
+
+        /// Directory to scan [default: System32]
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+
+    /// Export a module's message tables, in every language, as message compiler source
+    Export {
+        /// Module to export
+        module: String,
+
+        /// File to write [default: stdout]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// End every message with CRLF, even ones that were compiled without a line break
+        #[arg(long)]
+        fix_terminators: bool,
+
+        /// Strip trailing spaces and tabs from every line of every message
+        #[arg(long)]
+        trim_trailing_spaces: bool,
+
+        /// Collapse runs of spaces within lines into one, keeping indentation
+        #[arg(long)]
+        collapse_spaces: bool,
+
+        /// End every message with exactly one CRLF, dropping trailing blank lines; implies
+        /// --fix-terminators
+        #[arg(long)]
+        single_terminator: bool,
+
+        /// Instead of .mc source, write each message table's resource data to a .bin file in
+        /// DIR, and <module>.rc with a MESSAGETABLE statement for each, for a Visual Studio
+        /// project to build back in
+        #[arg(
+            long,
+            value_name = "DIR",
+            conflicts_with_all = [
+                "output",
+                "fix_terminators",
+                "trim_trailing_spaces",
+                "collapse_spaces",
+                "single_terminator"
+            ]
+        )]
+        emit_rc: Option<PathBuf>,
+
+        /// Also write the module's string tables to the .rc as STRINGTABLE statements
+        #[arg(long, requires = "emit_rc")]
+        string_tables: bool,
+
+        /// Write each message's severity and facility by name and its MessageId as just its
+        /// code, instead of raw 32-bit MessageIds. Modules with IDs that have the customer
+        /// or reserved bit set keep raw IDs
+        #[arg(long, conflicts_with = "emit_rc")]
+        symbolic_ids: bool,
+    },
+
+    /// Check every language of each module's message tables for common mistakes
+    Lint {
+        /// Modules to check
+        #[arg(required = true)]
+        modules: Vec<String>,
+
+        /// Language other languages are compared against to find untranslated entries
+        #[arg(long, value_parser = parse_lang, default_value = "0x409")]
+        base_lang: u16,
+
+        /// Also write the results as JUnit XML, with a test case per check per module
+        #[arg(long, value_name = "PATH")]
+        junit: Option<PathBuf>,
+
+        /// Spell-check the base language against a word list, with a word per line; may be
+        /// given more than once
+        #[arg(long = "words", value_name = "PATH")]
+        word_lists: Vec<PathBuf>,
+
+        /// Flag every language's messages that use a term from a list, with a term per line,
+        /// optionally followed by =REPLACEMENT; may be given more than once
+        #[arg(long = "banned-terms", value_name = "PATH")]
+        banned_term_lists: Vec<PathBuf>,
+
+        /// Also find Win32 codes that are defined, within or across the modules, both as
+        /// themselves and as their 0x8007xxxx HRESULT, and flag the ones whose texts differ
+        #[arg(long)]
+        hresult_duplicates: bool,
+
+        /// Flag each facility with more than N entries in a language [default: the config's
+        /// lint.max_entries_per_facility, if any]
+        #[arg(long, value_name = "N")]
+        max_entries_per_facility: Option<usize>,
+
+        /// Flag each module whose message tables take up more than BYTES altogether, in
+        /// every language [default: the config's lint.max_resource_size, if any]
+        #[arg(long, value_name = "BYTES")]
+        max_resource_size: Option<usize>,
+    },
+
+    /// Look up the text of each module and ID listed in a CSV file, writing CSV
+    Resolve {
+        /// CSV file of module,id[,lang] rows, or - for stdin
+        #[arg(long)]
+        input: PathBuf,
+    },
+
+    /// Echo stdin with the message of every error code found in it appended to its line
+    Annotate,
+
+    /// Dump the message file of every event source registered with the event log
+    Providers {
+        /// Keep running, dumping the message files of event sources as they're registered
+        #[arg(long)]
+        watch: bool,
+
+        /// Read the providers of an offline Windows image whose system drive is mounted at
+        /// DIR, from its SYSTEM hive, instead of this machine's
+        #[arg(long, value_name = "DIR", conflicts_with = "watch")]
+        image_root: Option<PathBuf>,
+    },
+
+    /// List every provider and event ID an .evtx file references, and report the ones whose
+    /// message this machine can't format: a missing message file, a missing ID, or the ID
+    /// missing in the UI language (or --lang-fallback's)
+    EvtxAudit {
+        /// Event log file to audit
+        file: PathBuf,
+    },
+
+    /// Decode a module's UI resources into readable listings
+    Resources {
+        /// Module to read
+        module: String,
+
+        /// Kind of resource to list
+        #[arg(long, value_enum)]
+        kind: ResourceKind,
+    },
+
+    /// Write a small resource-only DLL with the message tables a TOML spec describes, for
+    /// use as a test input
+    MakeFixture {
+        /// DLL to write
+        output: PathBuf,
+
+        /// Spec listing each table's resource, language and entries [default: a built-in
+        /// set of edge cases: mixed encodings, sparse blocks, huge entries and more]
+        #[arg(long)]
+        spec: Option<PathBuf>,
+    },
+
+    /// Serve message lookups to scripts and other COM clients as the automation class
+    /// DumpMessageTables.Catalog, with Lookup(module, id[, lang]) and Dump(module) methods
+    #[cfg(feature = "com")]
+    ComServer {
+        /// Register this executable as the class's server, then exit
+        #[arg(long)]
+        register: bool,
+
+        /// Remove the registration, then exit
+        #[arg(long, conflicts_with = "register")]
+        unregister: bool,
+
+        /// Register for every user rather than the current one; needs administrator rights
+        #[arg(long)]
+        all_users: bool,
+
+        // COM starts the server with -Embedding, which is accepted and ignored.
+        #[arg(hide = true, allow_hyphen_values = true)]
+        launch_args: Vec<String>,
+    },
+
+    /// Parses a module for --sandbox in the restricted child process
+    #[command(name = "sandbox-worker", hide = true)]
+    SandboxWorker {
+        path: PathBuf,
+
+        #[arg(long)]
+        all_langs: bool,
+    },
+
+    /// Full-text search over every indexed message, building the index on first use
+    Search {
+        /// Words that must all appear in the message
+        text: String,
+
+        /// Maximum number of hits to print
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+
+        /// Match approximately, tolerating typos and OCR errors
+        #[arg(long)]
+        fuzzy: bool,
+    },
+}
 
 fn main() {
-    if let Err(e) = try_main() {
-        println!("ERROR: {}", e);
+    let args = Args::parse();
+    let ui = Ui::load(args.ui_lang);
+    if let Err(e) = try_main(&args, &ui) {
+        println!("{}", ui.text(Msg::Error, &[&e.to_string()]));
         std::process::exit(1);
     }
 }
 
-fn try_main() -> anyhow::Result<()> {
-    // TODO: Pass module path on the command line.
-    let entries = get_message_table_entries("ping.exe")?;
-    for entry in entries {
-        println!("{:>8x}: {}", entry.0, entry.1);
+fn try_main(args: &Args, ui: &Ui) -> anyhow::Result<()> {
+    let config = Config::load(args.config.as_deref())?;
+    let scans_dirs = args.winsxs
+        || matches!(
+            args.command,
+            Some(Command::DiffDirs { .. } | Command::WhoDefines { .. })
+        );
+    if !scans_dirs && (!args.include.is_empty() || !args.exclude.is_empty()) {
+        bail!("--include and --exclude only apply to --winsxs, diff-dirs and who-defines");
+    }
+    let name_filter = NameFilter::new(args.include.clone(), args.exclude.clone());
+    if args.background {
+        scan::enter_background_mode()?;
+    }
+    if let Some(lang_fallback) = &args.lang_fallback {
+        lang_fallback.apply()?;
+    }
+    if args.backup_privilege {
+        privilege::enable_backup_privilege()?;
+    }
+    // The loader can't open files for backup, so with the privilege everything's parsed,
+    // trusted directories included.
+    let trusted_dirs: &[PathBuf] = if args.backup_privilege {
+        &[]
+    } else {
+        &config.trusted_dirs
+    };
+    let policy = LoadPolicy::new(args.no_loadlibrary || args.backup_privilege, trusted_dirs)
+        .with_lang_fallback(args.lang_fallback.clone())
+        .with_search_path(&args.search_path, args.no_default_search)
+        .with_timeout(args.timeout_per_module.map(Duration::from_secs));
+    let printer = Printer {
+        id_format: args.id_format,
+        id_width: args.id_width,
+        show_blocks: args.show_blocks,
+        show_encoding: args.show_encoding,
+        show_line_breaks: args.show_line_breaks,
+        collapse_runs: args.collapse_runs,
+        only: args.only,
+        lang_format: args.lang_format,
+        summary: args.summary,
+        verbose: args.verbose,
+        include_raw: args.include_raw,
+        normalize: args.normalize,
+        max_width: match args.max_width {
+            Some(0) => None,
+            Some(width) => Some(width),
+            None if args.deterministic || writes_files(args) => None,
+            None => output::terminal_width(),
+        },
+        decode: args.decode,
+        decode_severity: args.decode_severity,
+        namespace: args.namespace,
+        facilities: FacilityNames::new(&config),
+        components: config.components.clone(),
+        hyperlinks: match args.hyperlinks {
+            Hyperlinks::Always => true,
+            Hyperlinks::Never => false,
+            Hyperlinks::Auto => {
+                !args.deterministic && !writes_files(args) && output::supports_hyperlinks()
+            }
+        },
+        module_headers: args.out_dir.is_none() && (args.modules.len() > 1 || args.both_arch),
+        top_size: args.top_size,
+        gaps: args.gaps,
+        show_paths: !args.search_path.is_empty() || args.no_default_search,
+        resx_names: args.resx_names.clone(),
+    };
+    match &args.command {
+        Some(Command::Explain {
+            code,
+            modules,
+            format,
+        }) => {
+            match format {
+                ExplainFormat::Text => {
+                    explain::explain(*code, modules, &FacilityNames::new(&config), &policy)
+                }
+                ExplainFormat::Errlook => {
+                    let headers = Headers::load(config.sdk_include_dir.as_deref());
+                    explain::explain_errlook(*code, modules, &headers, &policy)
+                }
+            }
+            return Ok(());
+        }
+        Some(Command::DiffDirs {
+            old,
+            new,
+            format,
+            output,
+        }) => {
+            let diffs = diff::diff_trees(old, new, &policy, &name_filter)?;
+            let mut out = match output {
+                Some(path) => compress::create(path, args.compress)
+                    .with_context(|| ui.text(Msg::CreateFailed, &[&path.display().to_string()]))?,
+                None => compress::Writer::stdout(),
+            };
+            match format {
+                DiffFormat::Text => diff::write_text_report(&mut out, &diffs, args.lang_format)?,
+                DiffFormat::Json => {
+                    serde_json::to_writer_pretty(&mut out, &diffs)?;
+                    writeln!(out)?;
+                }
+            }
+            out.finish()?;
+            return Ok(());
+        }
+        Some(Command::WhoDefines { id, dir }) => {
+            let dir = dir.clone().unwrap_or_else(scan::system32_dir);
+            let mut index = Index::open_default()?;
+            index.refresh(&dir, &policy, &name_filter, &CancellationToken::new(), &())?;
+            // The index also holds modules earlier refreshes took that the filter doesn't.
+            let hits = index.who_defines(*id)?;
+            for hit in hits
+                .iter()
+                .filter(|hit| name_filter.admits(Path::new(&hit.path)))
+            {
+                println!(
+                    "{} [{}]: {}",
+                    hit.path,
+                    output::format_lang(args.lang_format, hit.lang),
+                    hit.text.trim_end()
+                );
+            }
+            return Ok(());
+        }
+        Some(Command::Export {
+            module,
+            emit_rc: Some(dir),
+            string_tables,
+            ..
+        }) => {
+            let (tables, strings) = policy.with_resources(module, |resources| {
+                let strings = if *string_tables {
+                    resource::string_tables(resources)?
+                } else {
+                    Vec::new()
+                };
+                Ok((provider::read_raw_message_tables(resources, true)?, strings))
+            })?;
+            fs::create_dir_all(dir)
+                .with_context(|| ui.text(Msg::CreateFailed, &[&dir.display().to_string()]))?;
+            for table in &tables {
+                let path = dir.join(export::bin_file_name(table));
+                fs::write(&path, &table.data)
+                    .with_context(|| ui.text(Msg::CreateFailed, &[&path.display().to_string()]))?;
+            }
+            let stem = Path::new(module).file_stem().map_or_else(
+                || module.clone(),
+                |stem| stem.to_string_lossy().into_owned(),
+            );
+            let path = dir.join(format!("{}.rc", stem));
+            let mut out = File::create(&path)
+                .with_context(|| ui.text(Msg::CreateFailed, &[&path.display().to_string()]))?;
+            export::write_rc(&mut out, &tables, &strings)?;
+            return Ok(());
+        }
+        Some(Command::Export {
+            module,
+            output,
+            fix_terminators,
+            trim_trailing_spaces,
+            collapse_spaces,
+            single_terminator,
+            symbolic_ids,
+            ..
+        }) => {
+            let tables = policy.get_message_tables(
+                module,
+                LOAD_LIBRARY_AS_DATAFILE | LOAD_LIBRARY_AS_IMAGE_RESOURCE,
+                true,
+            )?;
+            // Every facility the tables use, named as the built-in database and the config
+            // name them.
+            let facility_names = symbolic_ids.then(|| {
+                let names = FacilityNames::new(&config);
+                tables
+                    .iter()
+                    .flat_map(|table| &table.entries)
+                    .map(|entry| DecodedId::new(entry.id).facility)
+                    .filter_map(|facility| {
+                        let name = names.lookup(args.namespace, facility)?;
+                        Some((facility, name.to_string()))
+                    })
+                    .collect()
+            });
+            let options = export::ExportOptions {
+                fix_terminators: *fix_terminators,
+                trim_trailing_spaces: *trim_trailing_spaces,
+                collapse_spaces: *collapse_spaces,
+                single_terminator: *single_terminator,
+                facility_names,
+            };
+            let mut out = match output {
+                Some(path) => compress::create(path, args.compress)
+                    .with_context(|| ui.text(Msg::CreateFailed, &[&path.display().to_string()]))?,
+                None => compress::Writer::stdout(),
+            };
+            export::write_mc(&mut out, &tables, &options)?;
+            out.finish()?;
+            return Ok(());
+        }
+        Some(Command::Lint {
+            modules,
+            base_lang,
+            junit,
+            word_lists,
+            banned_term_lists,
+            hresult_duplicates,
+            max_entries_per_facility,
+            max_resource_size,
+        }) => {
+            let budget = lint::Budget {
+                max_entries_per_facility: max_entries_per_facility
+                    .or(config.lint.max_entries_per_facility),
+                max_resource_size: max_resource_size.or(config.lint.max_resource_size),
+            };
+            let dictionary = if word_lists.is_empty() && banned_term_lists.is_empty() {
+                None
+            } else {
+                let mut dictionary = lint::Dictionary::new();
+                let read = |path: &PathBuf| {
+                    fs::read_to_string(path)
+                        .with_context(|| ui.text(Msg::OpenFailed, &[&path.display().to_string()]))
+                };
+                for path in word_lists {
+                    dictionary.add_words(&read(path)?);
+                }
+                for path in banned_term_lists {
+                    dictionary.add_banned_terms(&read(path)?);
+                }
+                Some(dictionary)
+            };
+            let mut count = 0;
+            let mut results = Vec::new();
+            let mut all_tables = Vec::new();
+            for mod_name in modules {
+                let tables = policy.get_message_tables(
+                    mod_name,
+                    LOAD_LIBRARY_AS_DATAFILE | LOAD_LIBRARY_AS_IMAGE_RESOURCE,
+                    true,
+                )?;
+                for coverage in lint::coverage(&tables, *base_lang) {
+                    println!(
+                        "{}",
+                        ui.text(
+                            Msg::Untranslated,
+                            &[
+                                mod_name,
+                                &output::format_lang(args.lang_format, coverage.lang),
+                                &coverage.untranslated.to_string(),
+                                &coverage.total.to_string(),
+                            ]
+                        )
+                    );
+                }
+                let mut problems = lint::lint(&tables, *base_lang, dictionary.as_ref());
+                problems.extend(lint::check_budget(&tables, &budget));
+                for problem in &problems {
+                    println!(
+                        "{} {:>8x} [{}]: {}",
+                        mod_name,
+                        problem.id,
+                        output::format_lang(args.lang_format, problem.lang),
+                        problem.message
+                    );
+                    count += 1;
+                }
+                results.push((mod_name.clone(), problems));
+                if *hresult_duplicates {
+                    all_tables.push((mod_name.clone(), tables));
+                }
+            }
+            for duplicate in lint::hresult_duplicates(&all_tables) {
+                println!(
+                    "{} {:>8x} [{}]: also defined as {:#010x} in {}{}",
+                    duplicate.code_module,
+                    duplicate.code,
+                    output::format_lang(args.lang_format, duplicate.lang),
+                    duplicate.hresult(),
+                    duplicate.hresult_module,
+                    if duplicate.texts_match {
+                        ""
+                    } else {
+                        ", with different text"
+                    }
+                );
+                if !duplicate.texts_match {
+                    count += 1;
+                }
+            }
+            if let Some(path) = junit {
+                let mut out = compress::create(path, args.compress)
+                    .with_context(|| ui.text(Msg::CreateFailed, &[&path.display().to_string()]))?;
+                junit::write_lint_results(&mut out, &results, args.lang_format)?;
+                out.finish()?;
+            }
+            if count > 0 {
+                bail!(ui.text(Msg::ProblemsFound, &[&count.to_string()]));
+            }
+            return Ok(());
+        }
+        Some(Command::Search { text, limit, fuzzy }) => {
+            let mut index = Index::open_default()?;
+            if index.is_empty()? {
+                index.refresh(
+                    &scan::system32_dir(),
+                    &policy,
+                    &NameFilter::default(),
+                    &CancellationToken::new(),
+                    &(),
+                )?;
+            }
+            let hits = if *fuzzy {
+                index.search_fuzzy(text, *limit)?
+            } else {
+                index.search(text, *limit)?
+            };
+            for hit in hits {
+                let similarity = match hit.similarity {
+                    Some(similarity) => format!(" ({:.0}%)", similarity * 100.0),
+                    None => String::new(),
+                };
+                println!(
+                    "{} {:>8x} [{}]{}: {}",
+                    hit.path,
+                    hit.id,
+                    output::format_lang(args.lang_format, hit.lang),
+                    similarity,
+                    hit.text.trim_end()
+                );
+            }
+            return Ok(());
+        }
+        Some(Command::Resolve { input }) => {
+            let reader: Box<dyn BufRead> = if input == Path::new("-") {
+                Box::new(io::stdin().lock())
+            } else {
+                Box::new(BufReader::new(File::open(input).with_context(|| {
+                    ui.text(Msg::OpenFailed, &[&input.display().to_string()])
+                })?))
+            };
+            let failures =
+                resolve::resolve_pairs(reader, io::stdout().lock(), &policy, args.lang_format)?;
+            if failures > 0 {
+                bail!(ui.text(Msg::RowsUnresolved, &[&failures.to_string()]));
+            }
+            return Ok(());
+        }
+        Some(Command::Annotate) => {
+            let mut explainer = explain::Explainer::new(&policy);
+            annotate::annotate(io::stdin().lock(), io::stdout().lock(), &mut explainer)?;
+            return Ok(());
+        }
+        Some(Command::Providers { watch, image_root }) => {
+            let registry = match image_root {
+                Some(root) => Registry::Image(root.clone()),
+                None => Registry::Live,
+            };
+            return providers::dump_providers(&printer, &policy, &registry, *watch);
+        }
+        Some(Command::EvtxAudit { file }) => {
+            let events = evtx::read_events(file)?;
+            let resolutions = evtx::audit(&policy, &events)?;
+            let mut unresolved = 0;
+            for (event, resolution) in events.iter().zip(&resolutions) {
+                println!(
+                    "{}/{} {:>8x} ({} events): {}",
+                    event.channel,
+                    event.provider,
+                    event.id,
+                    event.count,
+                    resolution.describe(args.lang_format)
+                );
+                if !resolution.is_resolved() {
+                    unresolved += 1;
+                }
+            }
+            if unresolved > 0 {
+                bail!(ui.text(
+                    Msg::EventsUnresolved,
+                    &[&unresolved.to_string(), &events.len().to_string()]
+                ));
+            }
+            return Ok(());
+        }
+        Some(Command::Resources { module, kind }) => {
+            match kind {
+                ResourceKind::Accelerators => {
+                    for table in policy.with_resources(module, resource::accelerator_tables)? {
+                        println!(
+                            "accelerators {} [{}]:",
+                            table.name,
+                            output::format_lang(args.lang_format, table.lang)
+                        );
+                        for accelerator in &table.accelerators {
+                            println!(
+                                "  {:<20} {:#06x}{}",
+                                accelerator.to_string(),
+                                accelerator.command,
+                                if accelerator.no_invert {
+                                    " NOINVERT"
+                                } else {
+                                    ""
+                                }
+                            );
+                        }
+                    }
+                }
+                ResourceKind::Dialogs => {
+                    for dialog in policy.with_resources(module, resource::dialogs)? {
+                        let font = match &dialog.font {
+                            Some((size, face)) => format!(", font {:?} {}pt", face, size),
+                            None => String::new(),
+                        };
+                        println!(
+                            "dialog {} [{}]: {:?} at {:?}{}",
+                            dialog.name,
+                            output::format_lang(args.lang_format, dialog.lang),
+                            dialog.caption,
+                            dialog.rect,
+                            font
+                        );
+                        for control in &dialog.controls {
+                            let text = match &control.text {
+                                Some(ResourceId::Name(text)) => format!("{:?}", text),
+                                Some(ResourceId::Id(id)) => format!("resource {}", id),
+                                None => String::new(),
+                            };
+                            let rect = format!("{:?}", control.rect);
+                            println!(
+                                "  {:<16} {:>6} at {:<22} {}",
+                                control.class.to_string().trim_matches('"'),
+                                control.id,
+                                rect,
+                                text
+                            );
+                        }
+                    }
+                }
+                ResourceKind::Menus => {
+                    for menu in policy.with_resources(module, resource::menus)? {
+                        println!(
+                            "menu {} [{}]:",
+                            menu.name,
+                            output::format_lang(args.lang_format, menu.lang)
+                        );
+                        print_menu_items(&menu.items, 1);
+                    }
+                }
+            }
+            return Ok(());
+        }
+        Some(Command::MakeFixture { output, spec }) => {
+            let tables = match spec {
+                Some(path) => {
+                    fixture::parse_spec(&fs::read_to_string(path).with_context(|| {
+                        ui.text(Msg::OpenFailed, &[&path.display().to_string()])
+                    })?)?
+                }
+                None => fixture::edge_cases(),
+            };
+            fs::write(output, fixture::build_dll(&tables)?)
+                .with_context(|| ui.text(Msg::CreateFailed, &[&output.display().to_string()]))?;
+            return Ok(());
+        }
+        #[cfg(feature = "com")]
+        Some(Command::ComServer {
+            register,
+            unregister,
+            all_users,
+            ..
+        }) => {
+            if *register {
+                return com::register(&std::env::current_exe()?, *all_users);
+            }
+            if *unregister {
+                return com::unregister(*all_users);
+            }
+            return com::serve(policy);
+        }
+        Some(Command::SandboxWorker { path, all_langs }) => {
+            return sandbox::run_worker(path, *all_langs);
+        }
+        None => {}
+    }
+
+    if args.emit_schema {
+        match args.format {
+            OutputFormat::Proto => print!("{}", proto::PROTO_SCHEMA),
+            _ => print!("{}", output::JSON_SCHEMA),
+        }
+        return Ok(());
+    }
+    if args.emit_ui_catalog {
+        let options = export::ExportOptions::default();
+        let mut out = io::stdout().lock();
+        export::write_mc(&mut out, &[ui::english_table()], &options)?;
+        return Ok(());
+    }
+    if args.services {
+        return services::dump_services(&printer, &policy);
+    }
+    if args.winsxs {
+        return winsxs::dump_component_store(
+            &printer,
+            &policy,
+            &name_filter,
+            &CancellationToken::new(),
+        );
+    }
+
+    let mut modules = args.modules.clone();
+    if args.deterministic {
+        modules.sort();
+    }
+    // Modules with a SysWOW64 copy are followed by it when both architectures are wanted.
+    let mut targets: Vec<(String, Option<u32>)> = Vec::new();
+    for mod_name in &modules {
+        let counterpart = if args.both_arch {
+            scan::wow64_counterpart(&policy.resolve(mod_name)?)
+        } else {
+            None
+        };
+        match counterpart {
+            Some(counterpart) => {
+                targets.push((mod_name.clone(), Some(64)));
+                targets.push((counterpart.to_string_lossy().into_owned(), Some(32)));
+            }
+            None => targets.push((mod_name.clone(), None)),
+        }
+    }
+    if let Some(CatalogKind::AllStrings) = args.catalog {
+        let mut catalogs = Vec::new();
+        for (mod_name, _) in &targets {
+            let tables = policy.get_message_tables(
+                mod_name,
+                LOAD_LIBRARY_AS_DATAFILE | LOAD_LIBRARY_AS_IMAGE_RESOURCE,
+                true,
+            )?;
+            catalogs.push(ModuleStrings {
+                module: mod_name.clone(),
+                strings: policy
+                    .with_resources(mod_name, |provider| strings::all_strings(&tables, provider))?,
+            });
+        }
+        let mut out = match &args.output {
+            Some(path) => compress::create(path, args.compress)
+                .with_context(|| ui.text(Msg::CreateFailed, &[&path.display().to_string()]))?,
+            None => compress::Writer::stdout(),
+        };
+        serde_json::to_writer_pretty(&mut out, &catalogs)?;
+        writeln!(out)?;
+        out.finish()?;
+        return Ok(());
+    }
+    // Kept until every module has been read; dropping it deletes the shadow copies.
+    let shadow_copies = if args.vss {
+        let paths = targets
+            .iter()
+            .map(|(mod_name, _)| policy.resolve(mod_name))
+            .collect::<Result<Vec<_>, _>>()?;
+        Some(ShadowCopies::create(&paths)?)
+    } else {
+        None
+    };
+    let policy = match &shadow_copies {
+        Some(shadow_copies) => policy.with_snapshots(shadow_copies.snapshots()),
+        None => policy,
+    };
+    let reference = args.reference.as_deref().map(Reference::load).transpose()?;
+    let dump = Dump {
+        args,
+        reference: reference.as_ref(),
+        policy: &policy,
+        printer: &printer,
+        ui,
+        formatters: Formatters::builtin(&printer, ui),
+    };
+    if let Some(dir) = &args.extract {
+        fs::create_dir_all(dir)
+            .with_context(|| ui.text(Msg::CreateFailed, &[&dir.display().to_string()]))?;
+    }
+    // With --out-dir, each module gets a formatter of its own instead, and the run is
+    // recorded in a journal as it goes and a manifest at the end.
+    let (mut formatter, mut manifest, mut journal) = match &args.out_dir {
+        Some(dir) => {
+            fs::create_dir_all(dir)
+                .with_context(|| ui.text(Msg::CreateFailed, &[&dir.display().to_string()]))?;
+            let (journal, done) = Journal::open(dir, args.resume)?;
+            let mut manifest = Manifest::new();
+            manifest.modules = done;
+            (None, Some(manifest), Some(journal))
+        }
+        None => (
+            Some(dump.create_formatter(args.output.as_deref())?),
+            None,
+            None,
+        ),
+    };
+    let mut problems = Problems::default();
+    let mut failures = 0;
+    for (mod_name, bitness) in &targets {
+        let done = manifest.as_ref().map_or(false, |manifest| {
+            manifest
+                .modules
+                .iter()
+                .any(|record| record.module == *mod_name && record.bitness == *bitness)
+        });
+        if done {
+            continue;
+        }
+        let started = Instant::now();
+        let mut record = ManifestModule {
+            module: mod_name.clone(),
+            bitness: *bitness,
+            ..Default::default()
+        };
+        let result = dump.module(
+            mod_name,
+            *bitness,
+            formatter.as_deref_mut(),
+            &mut record,
+            &mut problems,
+        );
+        record.elapsed_ms = started.elapsed().as_millis() as u64;
+        let manifest = match &mut manifest {
+            Some(manifest) => manifest,
+            None => {
+                result?;
+                continue;
+            }
+        };
+        // A batch carries on past modules that fail, recording why.
+        if let Err(e) = result {
+            eprintln!(
+                "{}",
+                ui.text(Msg::ModuleFailed, &[mod_name, &format!("{:#}", e)])
+            );
+            record.error = Some(format!("{:#}", e));
+            failures += 1;
+        }
+        if let Some(journal) = &mut journal {
+            journal.record(&record)?;
+        }
+        manifest.modules.push(record);
+    }
+    if let Some(formatter) = &mut formatter {
+        formatter.finish()?;
+    }
+    if let (Some(manifest), Some(dir)) = (&mut manifest, &args.out_dir) {
+        manifest.finish(dir)?;
+    }
+    if problems.undocumented > 0 {
+        eprintln!(
+            "{}",
+            ui.text(
+                Msg::UndocumentedFound,
+                &[&problems.undocumented.to_string()]
+            )
+        );
+    }
+    if problems.discrepancies > 0 {
+        bail!(ui.text(Msg::EntriesDiffer, &[&problems.discrepancies.to_string()]));
+    }
+    if problems.conflicts > 0 {
+        bail!(ui.text(Msg::IdsConflict, &[&problems.conflicts.to_string()]));
+    }
+    if problems.roundtrip > 0 {
+        bail!(ui.text(Msg::RoundtripFailed, &[&problems.roundtrip.to_string()]));
+    }
+    if failures > 0 {
+        bail!(ui.text(Msg::ModulesFailed, &[&failures.to_string()]));
     }
     Ok(())
 }
 
-#[derive(Debug)]
-struct Error {
-    err_msg: String,
-    win_err: wp::Error,
+// Prints a menu's items indented by depth, with each popup's items under it.
+fn print_menu_items(items: &[resource::MenuItem], depth: usize) {
+    let indent = "  ".repeat(depth);
+    for item in items {
+        if item.separator {
+            println!("{}-----", indent);
+            continue;
+        }
+        let mut states = String::new();
+        if item.grayed {
+            states.push_str(" GRAYED");
+        }
+        if item.checked {
+            states.push_str(" CHECKED");
+        }
+        if item.popup {
+            println!("{}{:?}{}", indent, item.text, states);
+            print_menu_items(&item.children, depth + 1);
+        } else {
+            println!("{}{:#06x} {:?}{}", indent, item.id, item.text, states);
+        }
+    }
 }
 
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "failed to get message table entries{}: {}",
-            self.err_msg, self.win_err
-        )
-    }
+// What the checks run on each module found, to fail the run with once every module is done.
+#[derive(Default)]
+struct Problems {
+    // Entries that differ from FormatMessageW.
+    discrepancies: usize,
+    // IDs defined more than once with different text.
+    conflicts: usize,
+    // Differences re-encoding message tables.
+    roundtrip: usize,
+    // IDs missing from the reference, which are reported but don't fail the run.
+    undocumented: usize,
 }
 
-impl std::error::Error for Error {}
+// What dumping each module needs that doesn't change from one to the next.
+struct Dump<'a> {
+    args: &'a Args,
+    reference: Option<&'a Reference>,
+    policy: &'a LoadPolicy,
+    printer: &'a Printer,
+    ui: &'a Ui,
+    formatters: Formatters<'a>,
+}
 
-type Result<T> = std::result::Result<T, Error>;
+impl<'a> Dump<'a> {
+    // Reads mod_name's message tables in the sandbox or the policy's way. The policy gives
+    // up after --timeout-per-module itself.
+    fn read_tables(
+        &self,
+        mod_name: &str,
+        path: &Path,
+        flags: LOAD_LIBRARY_FLAGS,
+        all_langs: bool,
+    ) -> anyhow::Result<Vec<MessageTable>> {
+        if self.args.sandbox {
+            let timeout = self.args.timeout_per_module.map(Duration::from_secs);
+            return sandbox::read_message_tables(path, all_langs, timeout);
+        }
+        Ok(self.policy.get_message_tables(mod_name, flags, all_langs)?)
+    }
 
-unsafe extern "system" fn enum_res_names(
-    _module: HINSTANCE,
-    _typ: PCWSTR,
-    name: PCWSTR,
-    param: isize,
-) -> BOOL {
-    let names = mem::transmute::<isize, &mut Vec<PCWSTR>>(param);
-    names.push(name);
-    true.into()
+    // Reads mod_name and writes it to formatter, or to a file of its own without one,
+    // filling in what the manifest records about it along the way.
+    fn module(
+        &self,
+        mod_name: &str,
+        bitness: Option<u32>,
+        formatter: Option<&mut (dyn OutputFormatter + 'a)>,
+        record: &mut ManifestModule,
+        problems: &mut Problems,
+    ) -> anyhow::Result<()> {
+        let path = self.policy.resolve(mod_name)?;
+        record.path = path.display().to_string();
+        // Where the file's bytes come from, which with --vss is a shadow copy.
+        let read_path = self.policy.read_path(&path);
+        // Hashed first, so the manifest identifies even modules that fail to dump.
+        let hash = match self.args.out_dir {
+            Some(_) => Some(hash::sha256_file(&read_path)?),
+            None => None,
+        };
+        record.sha256 = hash.clone();
+        let symbols = match self.args.pdb.as_deref() {
+            Some("symsrv") => Some(Symbols::load(&symbols::locate_pdb(&path)?)?),
+            Some(pdb_path) => Some(Symbols::load(Path::new(pdb_path))?),
+            None => None,
+        };
+        // Resource-only DLLs have no code worth running, so they're loaded as data files.
+        let resource_only = pe::is_resource_only(&read_path).ok();
+        let flags = if resource_only == Some(true) {
+            LOAD_LIBRARY_AS_DATAFILE | LOAD_LIBRARY_AS_IMAGE_RESOURCE
+        } else {
+            LOAD_LIBRARY_FLAGS::default()
+        };
+        let all_langs = self.args.effective_lang.is_some();
+        let mut tables = self.read_tables(mod_name, &path, flags, all_langs)?;
+        if let Some(effective_lang) = &self.args.effective_lang {
+            tables = effective_lang.overlay(tables);
+        }
+        if self.args.deterministic {
+            output::sort_tables(&mut tables);
+        }
+        if let Some(filter) = &self.args.filter {
+            filter.apply(&mut tables)?;
+        }
+        if let Some(sample) = self.args.verify_against_os {
+            let module = Module::load(&self.policy.loader_name(mod_name)?, flags)?;
+            for discrepancy in verify::verify_against_os(&module, &tables, sample) {
+                let os = match &discrepancy.os {
+                    Some(text) => format!("{:?}", text),
+                    None => self.ui.text(Msg::NotFound, &[]),
+                };
+                eprintln!(
+                    "{}",
+                    self.ui.text(
+                        Msg::Mismatch,
+                        &[
+                            mod_name,
+                            &format!("{:>8x}", discrepancy.id),
+                            &output::format_lang(self.args.lang_format, discrepancy.lang),
+                            &format!("{:?}", discrepancy.ours),
+                            &os,
+                        ]
+                    )
+                );
+                problems.discrepancies += 1;
+            }
+        }
+        if self.args.unique_ids {
+            // Every language is read, since a neutral one can conflict with any other.
+            let all_tables = self.read_tables(mod_name, &path, flags, true)?;
+            for conflict in lint::id_conflicts(&all_tables) {
+                let definitions: Vec<String> = conflict
+                    .definitions
+                    .iter()
+                    .map(|(resource, lang)| {
+                        format!(
+                            "{} [{}]",
+                            resource,
+                            output::format_lang(self.args.lang_format, *lang)
+                        )
+                    })
+                    .collect();
+                eprintln!(
+                    "{}",
+                    self.ui.text(
+                        Msg::IdConflict,
+                        &[
+                            mod_name,
+                            &format!("{:>8x}", conflict.id),
+                            &definitions.join(", ")
+                        ]
+                    )
+                );
+                problems.conflicts += 1;
+            }
+        }
+        if let Some(reference) = self.reference {
+            for id in reference.undocumented(&tables) {
+                // The text of the first language that has it, to say what the ID is.
+                let text = tables
+                    .iter()
+                    .flat_map(|table| &table.entries)
+                    .find(|entry| entry.id == id)
+                    .map_or("", |entry| entry.text.trim_end());
+                eprintln!(
+                    "{}",
+                    self.ui.text(
+                        Msg::Undocumented,
+                        &[mod_name, &format!("{:>8x}", id), &format!("{:?}", text)]
+                    )
+                );
+                problems.undocumented += 1;
+            }
+        }
+        if self.args.verify_roundtrip {
+            for raw in pe::read_raw_message_tables(&read_path, true)? {
+                for difference in fixture::verify_roundtrip(&raw.data)? {
+                    let message = match difference.id {
+                        Some(id) => format!("{:>8x}: {}", id, difference.message),
+                        None => difference.message,
+                    };
+                    eprintln!(
+                        "{}",
+                        self.ui.text(
+                            Msg::RoundtripDifference,
+                            &[
+                                mod_name,
+                                &raw.resource.to_string(),
+                                &output::format_lang(self.args.lang_format, raw.lang),
+                                &message,
+                            ]
+                        )
+                    );
+                    problems.roundtrip += 1;
+                }
+            }
+        }
+        if let Some(dir) = &self.args.extract {
+            let kinds = &self.args.extract_kinds;
+            let wanted = |kind| kinds.is_empty() || kinds.contains(&kind);
+            let files = self.policy.with_resources(mod_name, |provider| {
+                let mut files = Vec::new();
+                if wanted(ExtractKind::Icons) {
+                    files.extend(resource::icon_files(provider)?);
+                }
+                if wanted(ExtractKind::Cursors) {
+                    files.extend(resource::cursor_files(provider)?);
+                }
+                if wanted(ExtractKind::Bitmaps) {
+                    files.extend(resource::bitmap_files(provider)?);
+                }
+                Ok(files)
+            })?;
+            for file in files {
+                let file_path = dir.join(extracted_file_name(mod_name, &path, &file));
+                fs::write(&file_path, &file.data).with_context(|| {
+                    self.ui
+                        .text(Msg::CreateFailed, &[&file_path.display().to_string()])
+                })?;
+            }
+        }
+        let signature = if self.args.signature {
+            Some(signature::verify(&read_path)?)
+        } else {
+            None
+        };
+        let module = ModuleOutput {
+            name: mod_name,
+            path: &path,
+            tables: &tables,
+            symbols: symbols.as_ref(),
+            signature: signature.as_ref(),
+            bitness,
+            resource_only,
+        };
+        record.tables = tables.len();
+        record.entries = tables.iter().map(|table| table.entries.len()).sum();
+        match (formatter, &self.args.out_dir, hash) {
+            (Some(formatter), _, _) => write_module(formatter, self.printer, &module)?,
+            (None, Some(dir), Some(hash)) => {
+                let file_name = module_file_name(self.args, mod_name, &path, &hash);
+                let mut formatter = self.create_formatter(Some(&dir.join(&file_name)))?;
+                write_module(formatter.as_mut(), self.printer, &module)?;
+                formatter.finish()?;
+                record.output = Some(file_name);
+            }
+            _ => unreachable!("a formatter is created unless --out-dir is given"),
+        }
+        Ok(())
+    }
+
+    // Writes to path, or stdout without one.
+    fn create_formatter(
+        &self,
+        path: Option<&Path>,
+    ) -> anyhow::Result<Box<dyn OutputFormatter + 'a>> {
+        let out = match path {
+            Some(path) => compress::create(path, self.args.compress).with_context(|| {
+                self.ui
+                    .text(Msg::CreateFailed, &[&path.display().to_string()])
+            })?,
+            None => compress::Writer::stdout(),
+        };
+        let out = SharedWriter::new(out);
+        let formatter = self
+            .formatters
+            .create(self.args.format.name(), Box::new(out.clone()))
+            .context("no formatter for the output format")?;
+        Ok(Box::new(FinishingFormatter { formatter, out }))
+    }
 }
 
-fn get_message_table_entries(mod_name: &str) -> Result<Vec<(u32, String)>> {
-    let mod_name_utf16 = wp::utf8_to_utf16(mod_name);
-    let res = unsafe { LoadLibraryW(PCWSTR::from_raw(mod_name_utf16.as_ptr())) };
-    let module = res.map_err(|e| Error {
-        err_msg: "failed to load the module".to_string(),
-        win_err: wp::Error::from_win_error(e),
-    })?;
+// A formatter whose writer is finished after the formatter, ending a compressed stream.
+struct FinishingFormatter<'a> {
+    formatter: Box<dyn OutputFormatter + 'a>,
+    out: SharedWriter,
+}
 
-    let mut mt_res_names: Vec<PCWSTR> = Vec::new();
-    let param = unsafe { mem::transmute::<&mut Vec<PCWSTR>, isize>(&mut mt_res_names) };
-    if !unsafe { EnumResourceNamesW(module, RT_MESSAGETABLE, Some(enum_res_names), param) }
-        .as_bool()
-    {
-        return Err(Error {
-            err_msg: "failed to enumerate message table resource names".to_string(),
-            win_err: wp::last_error(),
-        });
+impl OutputFormatter for FinishingFormatter<'_> {
+    fn begin_module(&mut self, module: &ModuleOutput) -> io::Result<()> {
+        self.formatter.begin_module(module)
     }
 
-    let mut results = Vec::new();
-    for mt_res_name in mt_res_names {
-        results.extend(get_message_table_entries_inner(module, mt_res_name)?)
+    fn entry(&mut self, module: &ModuleOutput, entry: &EntryOutput) -> io::Result<()> {
+        self.formatter.entry(module, entry)
     }
-    Ok(results)
-}
 
-fn get_message_table_entries_inner(
-    module: HINSTANCE,
-    mt_res_name: PCWSTR,
-) -> Result<Vec<(u32, String)>> {
-    let resource = unsafe { FindResourceW(module, mt_res_name, RT_MESSAGETABLE) };
-    if resource.is_invalid() {
-        return Err(Error {
-            err_msg: "failed to find the resource".to_string(),
-            win_err: wp::last_error(),
-        });
+    fn end_module(&mut self, module: &ModuleOutput) -> io::Result<()> {
+        self.formatter.end_module(module)
     }
 
-    let res_data = unsafe { LoadResource(module, resource) };
-    if res_data == 0 {
-        return Err(Error {
-            err_msg: "failed to load the resource".to_string(),
-            win_err: wp::last_error(),
-        });
+    fn finish(&mut self) -> io::Result<()> {
+        self.formatter.finish()?;
+        self.out.finish()
     }
+}
 
-    let res_mem = unsafe { LockResource(res_data) };
-    if res_mem.is_null() {
-        return Err(Error {
-            err_msg: "failed to lock the resource".to_string(),
-            win_err: wp::last_error(),
-        });
+// <hash>-<name>.<extension>, so modules of the same name from different directories or
+// versions don't collide.
+fn module_file_name(args: &Args, mod_name: &str, path: &Path, hash: &str) -> String {
+    let name = path.file_name().map_or_else(
+        || mod_name.to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    let mut file_name = format!("{}-{}.{}", &hash[..16], name, args.format.extension());
+    if let Some(compression) = args.compress {
+        file_name.push('.');
+        file_name.push_str(compression.extension());
     }
+    file_name
+}
 
-    let data = unsafe { mem::transmute::<*const c_void, &MESSAGE_RESOURCE_DATA>(res_mem) };
-    let blocks = unsafe {
-        std::slice::from_raw_parts(
-            &data.Blocks as *const MESSAGE_RESOURCE_BLOCK,
-            data.NumberOfBlocks as usize,
-        )
+// <module>-<resource>-<LANGID>.<extension>, with anything in a resource's name that a file
+// name can't hold replaced.
+fn extracted_file_name(mod_name: &str, path: &Path, file: &resource::ExtractedFile) -> String {
+    let module = path.file_stem().map_or_else(
+        || mod_name.to_string(),
+        |stem| stem.to_string_lossy().into_owned(),
+    );
+    let resource = match &file.name {
+        ResourceId::Id(id) => id.to_string(),
+        ResourceId::Name(name) => name.clone(),
     };
+    let resource: String = resource
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!(
+        "{}-{}-{:04x}.{}",
+        module, resource, file.lang, file.extension
+    )
+}
 
-    let mut results = Vec::new();
-    for block in blocks {
-        // NOTE: Each entry is variable length.
-        let start_entries = unsafe {
-            (data as *const MESSAGE_RESOURCE_DATA as *const u8).add(block.OffsetToEntries as usize)
-        };
-        let mut entry = unsafe { &*(start_entries as *const MESSAGE_RESOURCE_ENTRY) };
-        for entry_id in block.LowId..block.HighId + 1 {
-            let entry_str = match entry.Flags {
-                // Ansi
-                0 => wp::ansi_to_utf8(entry.Text.as_ptr()),
-                // Unicode
-                1 => wp::utf16_to_utf8(entry.Text.as_ptr() as *const u16),
-                _ => panic!("unexpected flags value in message table entry"),
-            };
-
-            results.push((entry_id, entry_str));
+fn writes_files(args: &Args) -> bool {
+    args.output.is_some() || args.out_dir.is_some()
+}
 
-            unsafe {
-                let next_entry = (entry as *const MESSAGE_RESOURCE_ENTRY as *const u8)
-                    .add(entry.Length as usize);
-                entry = &*(next_entry as *const MESSAGE_RESOURCE_ENTRY);
-            }
-        }
-    }
+// Accepts hex with a 0x prefix, decimal, and negative decimal as printed for HRESULTs by
+// languages with signed 32-bit integers.
+fn parse_id(s: &str) -> std::result::Result<u32, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => s
+            .parse::<u32>()
+            .ok()
+            .or_else(|| s.parse::<i32>().ok().map(|id| id as u32)),
+    };
+    parsed.ok_or_else(|| format!("invalid ID: {}", s))
+}
 
-    Ok(results)
-}
\ No newline at end of file
+fn parse_lang(s: &str) -> std::result::Result<u16, String> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse::<u16>().ok(),
+    };
+    parsed.ok_or_else(|| format!("invalid LANGID: {}", s))
+}
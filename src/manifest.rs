@@ -0,0 +1,134 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use windows::Win32::Foundation::FILETIME;
+
+use crate::signature;
+
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+pub const JOURNAL_FILE_NAME: &str = "journal.ndjson";
+
+// Seconds from 1601, where FILETIMEs start, to 1970.
+const FILETIME_UNIX_EPOCH: u64 = 11_644_473_600;
+
+// A provenance record of a batch run: every module processed, what it was written to and
+// what went wrong, for evidence handling.
+#[derive(Serialize)]
+pub struct Manifest {
+    pub version: &'static str,
+    pub started: String,
+    pub finished: Option<String>,
+    pub modules: Vec<ManifestModule>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ManifestModule {
+    // The name or path the module was given as, and the file that was read for it.
+    pub module: String,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bitness: Option<u32>,
+    pub sha256: Option<String>,
+    // Relative to the manifest.
+    pub output: Option<String>,
+    pub tables: usize,
+    pub entries: usize,
+    pub elapsed_ms: u64,
+    pub error: Option<String>,
+}
+
+impl Manifest {
+    pub fn new() -> Manifest {
+        Manifest {
+            version: env!("CARGO_PKG_VERSION"),
+            started: now(),
+            finished: None,
+            modules: Vec::new(),
+        }
+    }
+
+    // Stamps the finish time and writes the manifest into dir.
+    pub fn finish(&mut self, dir: &Path) -> anyhow::Result<()> {
+        self.finished = Some(now());
+        let path = dir.join(MANIFEST_FILE_NAME);
+        let mut out = BufWriter::new(
+            File::create(&path).with_context(|| format!("failed to create {}", path.display()))?,
+        );
+        serde_json::to_writer_pretty(&mut out, self)?;
+        writeln!(out)?;
+        out.flush()?;
+        Ok(())
+    }
+}
+
+impl Default for Manifest {
+    fn default() -> Manifest {
+        Manifest::new()
+    }
+}
+
+// Records each module as soon as it's done, one JSON object per line, so a run that's
+// interrupted can be resumed without redoing them.
+pub struct Journal {
+    file: File,
+}
+
+impl Journal {
+    // Starts a new journal in dir or, with resume, appends to the one there and returns the
+    // modules it already records as done. Modules that failed are left out, so they're
+    // retried, and so is a final line cut short by the interruption.
+    pub fn open(dir: &Path, resume: bool) -> anyhow::Result<(Journal, Vec<ManifestModule>)> {
+        let path = dir.join(JOURNAL_FILE_NAME);
+        let text = match fs::read_to_string(&path) {
+            Ok(text) if resume => text,
+            Err(e) if resume && e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(e).with_context(|| format!("failed to read {}", path.display()))
+            }
+            _ => String::new(),
+        };
+        let done = text
+            .lines()
+            .filter_map(|line| serde_json::from_str::<ManifestModule>(line).ok())
+            .filter(|module| module.error.is_none())
+            .collect();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resume)
+            .truncate(!resume)
+            .open(&path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        // Ends a line cut short, so the next record starts on a line of its own.
+        if !text.is_empty() && !text.ends_with('\n') {
+            file.write_all(b"\n")?;
+        }
+        Ok((Journal { file }, done))
+    }
+
+    // Synced to disk before returning, so a completed module survives a reboot.
+    pub fn record(&mut self, module: &ManifestModule) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(module)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.file.sync_data()?;
+        Ok(())
+    }
+}
+
+// The current time in UTC, formatted like signature timestamps.
+fn now() -> String {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let intervals = (since_epoch.as_secs() + FILETIME_UNIX_EPOCH) * 10_000_000
+        + u64::from(since_epoch.subsec_nanos() / 100);
+    let filetime = FILETIME {
+        dwLowDateTime: intervals as u32,
+        dwHighDateTime: (intervals >> 32) as u32,
+    };
+    signature::format_filetime(&filetime).unwrap_or_default()
+}
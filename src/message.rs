@@ -0,0 +1,103 @@
+#[cfg(feature = "live")]
+use windows::core::HRESULT;
+
+use crate::decode::{DecodedId, Severity};
+use crate::msgtable::{Encoding, Entry};
+use crate::table::ResourceId;
+
+// A message together with where it came from and what its ID encodes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MessageEntry {
+    id: u32,
+    text: String,
+    lang: u16,
+    encoding: Encoding,
+    resource: ResourceId,
+    severity: Severity,
+    facility: u16,
+    raw: Vec<u8>,
+}
+
+impl MessageEntry {
+    pub fn new(entry: Entry, resource: &ResourceId, lang: u16) -> MessageEntry {
+        let decoded = DecodedId::new(entry.id);
+        MessageEntry {
+            id: entry.id,
+            text: entry.text,
+            lang,
+            encoding: entry.encoding,
+            resource: resource.clone(),
+            severity: decoded.severity,
+            facility: decoded.facility,
+            raw: entry.raw,
+        }
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn into_text(self) -> String {
+        self.text
+    }
+
+    pub fn lang(&self) -> u16 {
+        self.lang
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn resource(&self) -> &ResourceId {
+        &self.resource
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn facility(&self) -> u16 {
+        self.facility
+    }
+
+    // Empty unless the entry was read with DumpOptions::include_raw.
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    #[cfg(feature = "live")]
+    pub fn hresult(&self) -> HRESULT {
+        HRESULT(self.id as i32)
+    }
+}
+
+impl From<&MessageEntry> for u32 {
+    fn from(entry: &MessageEntry) -> u32 {
+        entry.id
+    }
+}
+
+#[cfg(feature = "live")]
+impl From<&MessageEntry> for HRESULT {
+    fn from(entry: &MessageEntry) -> HRESULT {
+        entry.hresult()
+    }
+}
+
+impl PartialEq<u32> for MessageEntry {
+    fn eq(&self, id: &u32) -> bool {
+        self.id == *id
+    }
+}
+
+#[cfg(feature = "live")]
+impl PartialEq<HRESULT> for MessageEntry {
+    fn eq(&self, hr: &HRESULT) -> bool {
+        self.id == hr.0 as u32
+    }
+}
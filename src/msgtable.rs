@@ -0,0 +1,305 @@
+use std::fmt;
+use std::ops::ControlFlow;
+
+// MESSAGE_RESOURCE_ENTRY flags
+pub(crate) const MESSAGE_RESOURCE_ANSI: u16 = 0;
+pub(crate) const MESSAGE_RESOURCE_UNICODE: u16 = 1;
+pub(crate) const MESSAGE_RESOURCE_UTF8: u16 = 2;
+
+pub(crate) const ENTRY_HEADER_SIZE: usize = 4;
+pub(crate) const BLOCK_SIZE: usize = 12;
+
+#[derive(Debug)]
+pub struct ParseError {
+    err_msg: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid message table: {}", self.err_msg)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    Ansi,
+    Unicode,
+    Utf8,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            Encoding::Ansi => "ANSI",
+            Encoding::Unicode => "Unicode",
+            Encoding::Utf8 => "UTF-8",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+// How text that isn't valid in its encoding is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decoding {
+    // Invalid sequences are replaced with U+FFFD.
+    Lossy,
+    // Invalid sequences are a parse error.
+    Strict,
+}
+
+// A MESSAGE_RESOURCE_BLOCK: a run of consecutive IDs whose entries are stored back to back
+// starting at offset_to_entries.
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub low_id: u32,
+    pub high_id: u32,
+    pub offset_to_entries: u32,
+}
+
+impl Block {
+    pub fn contains(&self, id: u32) -> bool {
+        (self.low_id..=self.high_id).contains(&id)
+    }
+}
+
+// A decoded MESSAGE_RESOURCE_ENTRY. offset is relative to the start of the resource and
+// length is the encoded length, including the header and padding. raw holds those length
+// bytes exactly as stored.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub id: u32,
+    pub text: String,
+    pub encoding: Encoding,
+    pub offset: u32,
+    pub length: u16,
+    pub raw: Vec<u8>,
+}
+
+// A decoded MESSAGE_RESOURCE_DATA.
+pub struct MessageResource {
+    pub blocks: Vec<Block>,
+    pub entries: Vec<Entry>,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, ParseError> {
+    data.get(offset..offset + 2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .ok_or_else(|| truncated(offset))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, ParseError> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .ok_or_else(|| truncated(offset))
+}
+
+fn truncated(offset: usize) -> ParseError {
+    ParseError {
+        err_msg: format!("data is truncated at offset {:#x}", offset),
+    }
+}
+
+pub fn parse(data: &[u8]) -> Result<MessageResource, ParseError> {
+    parse_with(data, Decoding::Lossy)
+}
+
+pub fn parse_with(data: &[u8], decoding: Decoding) -> Result<MessageResource, ParseError> {
+    let blocks = read_blocks(data)?;
+    let mut entries = Vec::new();
+    // f never breaks, so there's no ControlFlow to look at.
+    let _ = for_each_entry(data, decoding, |entry| {
+        entries.push(entry);
+        ControlFlow::<()>::Continue(())
+    })?;
+    Ok(MessageResource { blocks, entries })
+}
+
+// Decodes entries one at a time, stopping early if f breaks.
+pub fn for_each_entry<B>(
+    data: &[u8],
+    decoding: Decoding,
+    mut f: impl FnMut(Entry) -> ControlFlow<B>,
+) -> Result<ControlFlow<B>, ParseError> {
+    for block in read_blocks(data)? {
+        if let ControlFlow::Break(b) = for_each_block_entry(data, &block, decoding, &mut f)? {
+            return Ok(ControlFlow::Break(b));
+        }
+    }
+    Ok(ControlFlow::Continue(()))
+}
+
+pub fn parse_block(
+    data: &[u8],
+    block: &Block,
+    decoding: Decoding,
+) -> Result<Vec<Entry>, ParseError> {
+    let mut entries = Vec::new();
+    let _ = for_each_block_entry(data, block, decoding, |entry| {
+        entries.push(entry);
+        ControlFlow::<()>::Continue(())
+    })?;
+    Ok(entries)
+}
+
+fn for_each_block_entry<B>(
+    data: &[u8],
+    block: &Block,
+    decoding: Decoding,
+    mut f: impl FnMut(Entry) -> ControlFlow<B>,
+) -> Result<ControlFlow<B>, ParseError> {
+    // NOTE: Each entry is variable length.
+    let mut offset = block.offset_to_entries as usize;
+    for id in block.low_id..=block.high_id {
+        let entry = read_entry(data, id, offset, decoding)?;
+        offset += entry.length as usize;
+        if let ControlFlow::Break(b) = f(entry) {
+            return Ok(ControlFlow::Break(b));
+        }
+    }
+    Ok(ControlFlow::Continue(()))
+}
+
+// Checks that every entry of block can be decoded, as parse_block would, without keeping
+// any of them. Text is only decoded with Decoding::Strict, the one way it can fail.
+pub fn check_block(data: &[u8], block: &Block, decoding: Decoding) -> Result<(), ParseError> {
+    let mut offset = block.offset_to_entries as usize;
+    for id in block.low_id..=block.high_id {
+        let (length, flags, text) = entry_parts(data, id, offset)?;
+        match decoding {
+            Decoding::Strict => {
+                decode_text(id, text, flags, decoding)?;
+            }
+            Decoding::Lossy => check_flags(id, flags)?,
+        }
+        offset += length as usize;
+    }
+    Ok(())
+}
+
+pub fn read_blocks(data: &[u8]) -> Result<Vec<Block>, ParseError> {
+    let number_of_blocks = read_u32(data, 0)? as usize;
+    let mut blocks = Vec::new();
+    for i in 0..number_of_blocks {
+        let block_offset = 4 + i * BLOCK_SIZE;
+        let block = Block {
+            low_id: read_u32(data, block_offset)?,
+            high_id: read_u32(data, block_offset + 4)?,
+            offset_to_entries: read_u32(data, block_offset + 8)?,
+        };
+        if block.high_id < block.low_id {
+            return Err(ParseError {
+                err_msg: format!(
+                    "block {} has HighId {:#x} below LowId {:#x}",
+                    i, block.high_id, block.low_id
+                ),
+            });
+        }
+        blocks.push(block);
+    }
+    Ok(blocks)
+}
+
+fn read_entry(
+    data: &[u8],
+    id: u32,
+    offset: usize,
+    decoding: Decoding,
+) -> Result<Entry, ParseError> {
+    let (encoded_length, flags, text) = entry_parts(data, id, offset)?;
+    let length = encoded_length as usize;
+    let (text, encoding) = decode_text(id, text, flags, decoding)?;
+    Ok(Entry {
+        id,
+        text,
+        encoding,
+        offset: offset as u32,
+        length: encoded_length,
+        raw: data[offset..offset + length].to_vec(),
+    })
+}
+
+// The encoded length, flags and undecoded text of the entry at offset.
+fn entry_parts(data: &[u8], id: u32, offset: usize) -> Result<(u16, u16, &[u8]), ParseError> {
+    let encoded_length = read_u16(data, offset)?;
+    let length = encoded_length as usize;
+    let flags = read_u16(data, offset + 2)?;
+    if length < ENTRY_HEADER_SIZE {
+        return Err(ParseError {
+            err_msg: format!("entry {:#x} has invalid length {}", id, length),
+        });
+    }
+    let text = data
+        .get(offset + ENTRY_HEADER_SIZE..offset + length)
+        .ok_or_else(|| truncated(offset))?;
+    Ok((encoded_length, flags, text))
+}
+
+fn check_flags(id: u32, flags: u16) -> Result<(), ParseError> {
+    match flags {
+        MESSAGE_RESOURCE_ANSI | MESSAGE_RESOURCE_UNICODE | MESSAGE_RESOURCE_UTF8 => Ok(()),
+        _ => Err(unexpected_flags(id, flags)),
+    }
+}
+
+fn unexpected_flags(id: u32, flags: u16) -> ParseError {
+    ParseError {
+        err_msg: format!("entry {:#x} has unexpected flags {:#x}", id, flags),
+    }
+}
+
+// Text is NUL-terminated and padded out to the entry length.
+fn decode_text(
+    id: u32,
+    text: &[u8],
+    flags: u16,
+    decoding: Decoding,
+) -> Result<(String, Encoding), ParseError> {
+    let invalid = |encoding| ParseError {
+        err_msg: format!("entry {:#x} is not valid {}", id, encoding),
+    };
+    match flags {
+        MESSAGE_RESOURCE_ANSI => Ok((decode_ansi(text), Encoding::Ansi)),
+        MESSAGE_RESOURCE_UNICODE => {
+            let utf16: Vec<u16> = text
+                .chunks_exact(2)
+                .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                .take_while(|&unit| unit != 0)
+                .collect();
+            let text = match decoding {
+                Decoding::Lossy => String::from_utf16_lossy(&utf16),
+                Decoding::Strict => {
+                    String::from_utf16(&utf16).map_err(|_| invalid(Encoding::Unicode))?
+                }
+            };
+            Ok((text, Encoding::Unicode))
+        }
+        MESSAGE_RESOURCE_UTF8 => {
+            let utf8: Vec<u8> = text.iter().copied().take_while(|&b| b != 0).collect();
+            let text = match decoding {
+                Decoding::Lossy => String::from_utf8_lossy(&utf8).into_owned(),
+                Decoding::Strict => String::from_utf8(utf8).map_err(|_| invalid(Encoding::Utf8))?,
+            };
+            Ok((text, Encoding::Utf8))
+        }
+        _ => Err(unexpected_flags(id, flags)),
+    }
+}
+
+#[cfg(feature = "live")]
+fn decode_ansi(text: &[u8]) -> String {
+    let mut ansi: Vec<u8> = text.iter().copied().take_while(|&b| b != 0).collect();
+    ansi.push(0);
+    wp::ansi_to_utf8(ansi.as_ptr())
+}
+
+// Without the ANSI code page to go by, text is decoded as Latin-1, which matches the
+// Western European code page everywhere but 0x80-0x9f.
+#[cfg(not(feature = "live"))]
+fn decode_ansi(text: &[u8]) -> String {
+    text.iter()
+        .take_while(|&&b| b != 0)
+        .map(|&b| b as char)
+        .collect()
+}
@@ -0,0 +1,83 @@
+// Async variants of the scanning and indexing APIs, for hosts running on tokio. The Win32
+// loader and SQLite calls run on tokio's blocking threads; offline parsing reads the file
+// asynchronously and only parses it inline.
+
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use tokio::task::{self, JoinError};
+
+use crate::index::Index;
+use crate::msgtable;
+use crate::pe;
+use crate::policy::LoadPolicy;
+use crate::scan::{self, CancellationToken, Cancelled, NameFilter, ScanProgress};
+use crate::table::{Error, MessageTable, RawMessageTable, Result};
+
+// Like scan::scan_modules, on a blocking thread. Dropping the future doesn't stop the scan;
+// cancel does.
+pub async fn scan_modules(
+    paths: Vec<PathBuf>,
+    policy: Arc<LoadPolicy>,
+    cancel: CancellationToken,
+    progress: Arc<dyn ScanProgress + Send>,
+) -> std::result::Result<Vec<anyhow::Result<Vec<MessageTable>>>, Cancelled> {
+    let task = task::spawn_blocking(move || {
+        scan::scan_modules(&paths, &policy, &cancel, progress.as_ref())
+    });
+    task.await
+        .unwrap_or_else(|e| Err(join_failed(e, Cancelled)))
+}
+
+// Like Index::refresh, on a blocking thread, which holds index's lock until it's done.
+pub async fn refresh_index(
+    index: Arc<Mutex<Index>>,
+    dir: PathBuf,
+    policy: Arc<LoadPolicy>,
+    filter: NameFilter,
+    cancel: CancellationToken,
+    progress: Arc<dyn ScanProgress + Send>,
+) -> anyhow::Result<()> {
+    let task = task::spawn_blocking(move || {
+        let mut index = index
+            .lock()
+            .map_err(|_| anyhow!("the index was poisoned by an earlier refresh"))?;
+        index.refresh(&dir, &policy, &filter, &cancel, progress.as_ref())
+    });
+    task.await
+        .unwrap_or_else(|e| Err(join_failed(e, anyhow!("the index refresh was cancelled"))))
+}
+
+// Like pe::read_raw_message_tables, reading the file asynchronously.
+pub async fn read_raw_message_tables(path: &Path, all_langs: bool) -> Result<Vec<RawMessageTable>> {
+    let data = tokio::fs::read(path)
+        .await
+        .map_err(|e| Error::new(format!("failed to read {}: {}", path.display(), e)))?;
+    pe::parse_raw_message_tables(&data, all_langs)
+}
+
+// Parses path's message tables offline, reading the file asynchronously.
+pub async fn read_message_tables(path: &Path, all_langs: bool) -> Result<Vec<MessageTable>> {
+    let mut results = Vec::new();
+    for raw in read_raw_message_tables(path, all_langs).await? {
+        let parsed = msgtable::parse(&raw.data)?;
+        results.push(MessageTable {
+            resource: raw.resource,
+            lang: raw.lang,
+            blocks: parsed.blocks,
+            entries: parsed.entries,
+        });
+    }
+    Ok(results)
+}
+
+// A blocking task that panicked passes the panic on to the awaiting task; one the runtime
+// dropped while shutting down fails with cancelled.
+fn join_failed<E>(e: JoinError, cancelled: E) -> E {
+    match e.try_into_panic() {
+        Ok(payload) => panic::resume_unwind(payload),
+        Err(_) => cancelled,
+    }
+}
@@ -0,0 +1,676 @@
+use std::io::{self, Write};
+
+use base64::Engine;
+use clap::ValueEnum;
+use serde::Serialize;
+use unicode_normalization::UnicodeNormalization;
+use windows::Win32::Globalization::*;
+use windows::Win32::System::Console::*;
+
+use crate::config::ComponentRange;
+use crate::decode::DecodedId;
+use crate::explain;
+use crate::facility::{FacilityNames, Namespace};
+use crate::formatter::ResxNames;
+use crate::gaps::{self, GapKind};
+use crate::message::MessageEntry;
+use crate::msgtable::{Encoding, Entry};
+use crate::signature::Signature;
+use crate::symbols::Symbols;
+use crate::table::MessageTable;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum IdFormat {
+    Hex,
+    Dec,
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    // A JSON map from each distinct message text to everywhere it occurs.
+    ReverseIndex,
+    // A .NET resource file, named by Printer::resx_names.
+    Resx,
+    // Protobuf, as described by catalog.proto.
+    Proto,
+}
+
+impl OutputFormat {
+    // The name the format's formatter is registered under.
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Text => "text",
+            OutputFormat::Json => "json",
+            OutputFormat::ReverseIndex => "reverse-index",
+            OutputFormat::Resx => "resx",
+            OutputFormat::Proto => "proto",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Text => "txt",
+            OutputFormat::Json | OutputFormat::ReverseIndex => "json",
+            OutputFormat::Resx => "resx",
+            OutputFormat::Proto => "pb",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LangFormat {
+    Langid,
+    Bcp47,
+    Both,
+}
+
+// A single column of text output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Only {
+    Ids,
+    Text,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Hyperlinks {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Normalization {
+    Nfc,
+    Nfd,
+    None,
+}
+
+// Bumped whenever a field is removed or changes meaning. Adding optional fields doesn't
+// bump it. schema.json describes the current version.
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+pub const JSON_SCHEMA: &str = include_str!("schema.json");
+
+// The pages of the system error code reference, by the first code each one lists.
+const WIN32_ERROR_PAGES: &[(u32, &str)] = &[
+    (0, "system-error-codes--0-499-"),
+    (500, "system-error-codes--500-999-"),
+    (1000, "system-error-codes--1000-1299-"),
+    (1300, "system-error-codes--1300-1699-"),
+    (1700, "system-error-codes--1700-3999-"),
+    (4000, "system-error-codes--4000-5999-"),
+    (6000, "system-error-codes--6000-8199-"),
+    (8200, "system-error-codes--8200-8999-"),
+    (9000, "system-error-codes--9000-11999-"),
+    (12000, "system-error-codes--12000-15999-"),
+];
+const WIN32_ERROR_PAGES_END: u32 = 16000;
+const WIN32_ERROR_DOCS: &str = "https://learn.microsoft.com/en-us/windows/win32/debug/";
+const HRESULT_DOCS: &str = "https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-erref/705fb797-2175-4a90-b5a3-3918024b10b8";
+
+const FACILITY_WIN32: u16 = 7;
+
+#[derive(Serialize)]
+pub struct JsonDocument {
+    pub schema_version: u32,
+    pub modules: Vec<JsonModule>,
+}
+
+impl JsonDocument {
+    pub fn new(modules: Vec<JsonModule>) -> JsonDocument {
+        JsonDocument {
+            schema_version: JSON_SCHEMA_VERSION,
+            modules,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct JsonModule {
+    pub module: String,
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bitness: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource_only: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<Signature>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<Summary>,
+    pub entries: Vec<JsonEntry>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Summary {
+    pub entries: usize,
+    pub blocks: usize,
+    pub ansi: usize,
+    pub unicode: usize,
+    pub utf8: usize,
+}
+
+impl Summary {
+    pub fn new(tables: &[MessageTable]) -> Summary {
+        let mut summary = Summary::default();
+        for table in tables {
+            summary.blocks += table.blocks.len();
+            for entry in &table.entries {
+                summary.entries += 1;
+                match entry.encoding {
+                    Encoding::Ansi => summary.ansi += 1,
+                    Encoding::Unicode => summary.unicode += 1,
+                    Encoding::Utf8 => summary.utf8 += 1,
+                }
+            }
+        }
+        summary
+    }
+
+    // Mixed-encoding tables are a common source of mojibake.
+    pub fn is_mixed(&self) -> bool {
+        [self.ansi, self.unicode, self.utf8]
+            .iter()
+            .filter(|&&count| count > 0)
+            .count()
+            > 1
+    }
+}
+
+// Optional fields are only present when the corresponding decoding was requested.
+#[derive(Serialize)]
+pub struct JsonEntry {
+    pub id: u32,
+    // The MESSAGETABLE resource the entry came from, as printed by ResourceId's Display.
+    pub resource: String,
+    pub lang: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    // The first and last IDs of a run of identical entries collapsed into this one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<[u32; 2]>,
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub severity: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub customer: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub facility: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<u16>,
+    pub encoding: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub component: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    pub offset: u32,
+    pub length: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<String>,
+}
+
+pub struct Printer {
+    pub id_format: IdFormat,
+    // Zero-pads IDs to this many digits instead of space-padding them.
+    pub id_width: Option<usize>,
+    pub show_blocks: bool,
+    pub show_encoding: bool,
+    pub show_line_breaks: bool,
+    pub collapse_runs: bool,
+    pub only: Option<Only>,
+    pub lang_format: LangFormat,
+    pub summary: bool,
+    pub verbose: bool,
+    pub include_raw: bool,
+    pub normalize: Normalization,
+    // Lines are truncated to this many characters. Machine formats are never truncated.
+    pub max_width: Option<usize>,
+    pub decode: bool,
+    pub decode_severity: bool,
+    pub namespace: Namespace,
+    pub facilities: FacilityNames,
+    pub components: Vec<ComponentRange>,
+    // Wraps IDs with documentation in OSC 8 hyperlinks.
+    pub hyperlinks: bool,
+    // Precedes each module's entries with its name, for when there are several.
+    pub module_headers: bool,
+    // Instead of every entry, lists this many of each module's largest messages.
+    pub top_size: Option<usize>,
+    // Instead of every entry, lists the IDs each facility leaves unused.
+    pub gaps: bool,
+    // Prints the absolute path each module was resolved to.
+    pub show_paths: bool,
+    // What --format resx names each message's resource.
+    pub resx_names: ResxNames,
+}
+
+impl Printer {
+    pub fn print_entries(
+        &self,
+        entries: &[MessageEntry],
+        symbols: Option<&Symbols>,
+    ) -> io::Result<()> {
+        let mut out = io::stdout().lock();
+        for entry in entries {
+            self.print_entry(
+                &mut out,
+                entry.id(),
+                entry.id(),
+                entry.text(),
+                None,
+                symbols,
+                None,
+            )?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn print_summary(
+        &self,
+        out: &mut dyn Write,
+        tables: &[MessageTable],
+    ) -> io::Result<()> {
+        if self.summary {
+            let summary = Summary::new(tables);
+            writeln!(
+                out,
+                "{} entries in {} blocks: {} ANSI, {} Unicode, {} UTF-8{}",
+                summary.entries,
+                summary.blocks,
+                summary.ansi,
+                summary.unicode,
+                summary.utf8,
+                if summary.is_mixed() {
+                    " (mixed encodings)"
+                } else {
+                    ""
+                }
+            )?;
+        }
+        Ok(())
+    }
+
+    // Lists the count largest entries by encoded length, largest first.
+    pub fn print_top_size(
+        &self,
+        out: &mut dyn Write,
+        tables: &[MessageTable],
+        count: usize,
+    ) -> io::Result<()> {
+        let mut entries: Vec<&Entry> = tables.iter().flat_map(|table| &table.entries).collect();
+        entries.sort_by(|a, b| b.length.cmp(&a.length));
+        for entry in entries.into_iter().take(count) {
+            let text = self.normalize(&entry.text);
+            let line = format!(
+                "{:>6} bytes {}: {}",
+                entry.length,
+                self.format_id(entry.id),
+                text.lines().next().unwrap_or_default()
+            );
+            match self.max_width {
+                Some(width) => writeln!(out, "{}", truncate(&line, width))?,
+                None => writeln!(out, "{}", line)?,
+            }
+        }
+        Ok(())
+    }
+
+    // Lists the unused IDs of each facility the tables use, grouped by the top 16 bits of
+    // their IDs.
+    pub fn print_gaps(&self, out: &mut dyn Write, tables: &[MessageTable]) -> io::Result<()> {
+        for facility in gaps::find_gaps(tables) {
+            let decoded = DecodedId::new(facility.first_id);
+            writeln!(
+                out,
+                "{}-{} ({}, {}{}):",
+                self.format_id(facility.first_id).trim_start(),
+                self.format_id(facility.first_id | 0xffff).trim_start(),
+                decoded.severity,
+                self.facility_label(decoded.facility),
+                if decoded.customer { ", customer" } else { "" }
+            )?;
+            if facility.gaps.is_empty() {
+                writeln!(out, "  none free")?;
+            }
+            for gap in &facility.gaps {
+                let kind = match gap.kind {
+                    GapKind::WithinBlock => "empty within a block",
+                    GapKind::BeforeFirstBlock => "before the first block",
+                    GapKind::BetweenBlocks => "between blocks",
+                    GapKind::AfterLastBlock => "after the last block",
+                };
+                writeln!(
+                    out,
+                    "  {}-{} {:>5} IDs {}",
+                    self.format_id(gap.first),
+                    self.format_id(gap.last),
+                    gap.size(),
+                    kind
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn json_entry(
+        &self,
+        entry: &Entry,
+        last_id: u32,
+        table: &MessageTable,
+        symbols: Option<&Symbols>,
+    ) -> JsonEntry {
+        let decoded = DecodedId::new(entry.id);
+        JsonEntry {
+            id: entry.id,
+            resource: table.resource.to_string(),
+            lang: table.lang,
+            locale: match self.lang_format {
+                LangFormat::Langid => None,
+                LangFormat::Bcp47 | LangFormat::Both => locale_name(table.lang),
+            },
+            range: (last_id != entry.id).then(|| [entry.id, last_id]),
+            text: self.normalize(&entry.text),
+            severity: self.decode_severity.then(|| decoded.severity.to_string()),
+            customer: self.decode.then(|| decoded.customer),
+            facility: self.decode.then(|| self.facility_label(decoded.facility)),
+            code: self.decode.then(|| decoded.code),
+            encoding: entry.encoding.to_string(),
+            component: self.component(entry.id).map(|name| name.to_string()),
+            symbol: symbols
+                .and_then(|symbols| symbols.lookup(entry.id))
+                .map(|name| name.to_string()),
+            offset: entry.offset,
+            length: entry.length,
+            raw: self
+                .include_raw
+                .then(|| base64::engine::general_purpose::STANDARD.encode(&entry.raw)),
+        }
+    }
+
+    // With collapse_runs, consecutive IDs with identical text are merged into a single entry
+    // paired with the last ID of the run.
+    pub(crate) fn runs<'a>(
+        &self,
+        entries: impl IntoIterator<Item = &'a Entry>,
+    ) -> Vec<(&'a Entry, u32)> {
+        let mut runs: Vec<(&Entry, u32)> = Vec::new();
+        for entry in entries {
+            if let Some((first, last_id)) = runs.last_mut() {
+                if self.collapse_runs
+                    && last_id.checked_add(1) == Some(entry.id)
+                    && entry.text == first.text
+                {
+                    *last_id = entry.id;
+                    continue;
+                }
+            }
+            runs.push((entry, entry.id));
+        }
+        runs
+    }
+
+    // module is the name the entry's module was given as, if it came from one.
+    pub(crate) fn print_entry(
+        &self,
+        out: &mut dyn Write,
+        id: u32,
+        last_id: u32,
+        text: &str,
+        layout: Option<&Entry>,
+        symbols: Option<&Symbols>,
+        module: Option<&str>,
+    ) -> io::Result<()> {
+        let mut annotations = self.annotations(id);
+        if let Some(name) = symbols.and_then(|symbols| symbols.lookup(id)) {
+            annotations.push(name.to_string());
+        }
+        if let Some(entry) = layout.filter(|_| self.show_encoding) {
+            annotations.push(entry.encoding.to_string());
+        }
+        if let Some(entry) = layout.filter(|_| self.verbose) {
+            annotations.push(format!(
+                "offset {:#x} length {}",
+                entry.offset, entry.length
+            ));
+        }
+
+        let formatted = if last_id == id {
+            self.format_id(id)
+        } else {
+            format!(
+                "{}–{}",
+                self.format_id(id),
+                self.format_id(last_id).trim_start()
+            )
+        };
+        let text = self.normalize(text);
+        let label = formatted.trim_start();
+        match self.only {
+            Some(Only::Ids) => {
+                return writeln!(out, "{}", self.hyperlink(label, label, id, module));
+            }
+            Some(Only::Text) => {
+                return writeln!(out, "{}", self.fold(&text, 0));
+            }
+            None => {}
+        }
+        let prefix = if annotations.is_empty() {
+            format!("{}: ", formatted)
+        } else {
+            format!("{}: [{}] ", formatted, annotations.join(" "))
+        };
+        let line = format!("{}{}", prefix, self.fold(&text, prefix.chars().count()));
+        // Links are added after truncating, which would count the escape sequences as
+        // characters.
+        let line = match self.max_width {
+            Some(width) => truncate(&line, width),
+            None => line,
+        };
+        writeln!(out, "{}", self.hyperlink(&line, label, id, module))
+    }
+
+    // Links the first occurrence of label in line to the documentation of id, if it has any.
+    fn hyperlink(&self, line: &str, label: &str, id: u32, module: Option<&str>) -> String {
+        let url = match doc_url(id, module) {
+            Some(url) if self.hyperlinks => url,
+            _ => return line.to_string(),
+        };
+        match line.find(label) {
+            Some(start) => {
+                let end = start + label.len();
+                format!(
+                    "{}\x1b]8;;{}\x1b\\{}\x1b]8;;\x1b\\{}",
+                    &line[..start],
+                    url,
+                    label,
+                    &line[end..]
+                )
+            }
+            None => line.to_string(),
+        }
+    }
+
+    // Continuation lines are indented to line up under the first line of text. The line
+    // break that terminates most messages is dropped rather than printed as an empty line.
+    fn fold(&self, text: &str, indent: usize) -> String {
+        let text = text.strip_suffix('\n').unwrap_or(text);
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        let lines: Vec<&str> = text.split('\n').collect();
+        let mut folded = String::new();
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 {
+                folded.push('\n');
+                folded.push_str(&" ".repeat(indent));
+            }
+            folded.push_str(line.trim_end_matches('\r'));
+            if self.show_line_breaks && i + 1 < lines.len() {
+                folded.push('¶');
+            }
+        }
+        folded
+    }
+
+    // Localization toolchains disagree on normalization forms, which otherwise shows up as
+    // spurious differences between catalogs.
+    pub(crate) fn normalize(&self, text: &str) -> String {
+        match self.normalize {
+            Normalization::Nfc => text.nfc().collect(),
+            Normalization::Nfd => text.nfd().collect(),
+            Normalization::None => text.to_string(),
+        }
+    }
+
+    fn format_id(&self, id: u32) -> String {
+        let hex = match self.id_width {
+            Some(width) => format!("{:0width$x}", id, width = width),
+            None => format!("{:>8x}", id),
+        };
+        let dec = match self.id_width {
+            Some(width) => format!("{:0width$}", id, width = width),
+            None => format!("{:>10}", id),
+        };
+        match self.id_format {
+            IdFormat::Hex => hex,
+            IdFormat::Dec => dec,
+            IdFormat::Both => format!("{} {}", hex, dec),
+        }
+    }
+
+    fn annotations(&self, id: u32) -> Vec<String> {
+        let mut annotations = Vec::new();
+        let decoded = DecodedId::new(id);
+        if self.decode_severity {
+            annotations.push(decoded.severity.to_string());
+        }
+        if self.decode {
+            if decoded.customer {
+                annotations.push("CUSTOMER".to_string());
+            }
+            annotations.push(self.facility_label(decoded.facility));
+            annotations.push(format!("{:#06x}", decoded.code));
+        }
+        // Component ranges are labelled whether or not decoding was requested.
+        if let Some(component) = self.component(id) {
+            annotations.push(component.to_string());
+        }
+        annotations
+    }
+
+    fn component(&self, id: u32) -> Option<&str> {
+        self.components
+            .iter()
+            .find(|range| range.contains(id))
+            .map(|range| range.name.as_str())
+    }
+
+    fn facility_label(&self, facility: u16) -> String {
+        match self.facilities.lookup(self.namespace, facility) {
+            Some(name) => name.to_string(),
+            None => format!("FACILITY_{:#x}", facility),
+        }
+    }
+}
+
+// IDs below 0x10000 are only Win32 error codes in the modules system error messages live in;
+// elsewhere they're event IDs and the like.
+fn doc_url(id: u32, module: Option<&str>) -> Option<String> {
+    let decoded = DecodedId::new(id);
+    let win32_code = if id <= 0xffff && module.map_or(false, explain::is_win32_module) {
+        Some(id)
+    } else if id >> 28 == 0x8 && decoded.facility == FACILITY_WIN32 {
+        // HRESULT_FROM_WIN32
+        Some(u32::from(decoded.code))
+    } else {
+        None
+    };
+    if let Some(code) = win32_code.filter(|&code| code < WIN32_ERROR_PAGES_END) {
+        let (_, page) = WIN32_ERROR_PAGES
+            .iter()
+            .rev()
+            .find(|(first, _)| *first <= code)?;
+        return Some(format!("{}{}", WIN32_ERROR_DOCS, page));
+    }
+    // Any other failure HRESULT that isn't a customer code.
+    (id >> 28 == 0x8).then(|| HRESULT_DOCS.to_string())
+}
+
+// Orders tables by resource and language, and blocks and entries by ID, so the output
+// doesn't depend on the order the loader enumerates resources in.
+pub fn sort_tables(tables: &mut [MessageTable]) {
+    tables.sort_by(|a, b| (&a.resource, a.lang).cmp(&(&b.resource, b.lang)));
+    for table in tables {
+        table.blocks.sort_by_key(|block| block.low_id);
+        table.entries.sort_by_key(|entry| entry.id);
+    }
+}
+
+// Each line of a multi-line message is truncated separately.
+fn truncate(text: &str, width: usize) -> String {
+    text.split('\n')
+        .map(|line| {
+            let line = line.trim_end_matches('\r');
+            if line.chars().count() <= width {
+                return line.to_string();
+            }
+            let mut truncated: String = line.chars().take(width.saturating_sub(1)).collect();
+            truncated.push('…');
+            truncated
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// Languages without a locale name, such as LANG_NEUTRAL, fall back to the LANGID.
+pub fn format_lang(format: LangFormat, lang: u16) -> String {
+    let langid = format!("{:#06x}", lang);
+    match (format, locale_name(lang)) {
+        (LangFormat::Bcp47, Some(name)) => name,
+        (LangFormat::Both, Some(name)) => format!("{} {}", name, langid),
+        _ => langid,
+    }
+}
+
+pub fn locale_name(lang: u16) -> Option<String> {
+    let mut name = [0u16; LOCALE_NAME_MAX_LENGTH as usize];
+    // A LANGID is an LCID with the default sort order.
+    let len = unsafe { LCIDToLocaleName(lang as u32, Some(&mut name), 0) };
+    if len <= 1 {
+        return None;
+    }
+    Some(String::from_utf16_lossy(&name[..len as usize - 1]))
+}
+
+// Windows Terminal renders OSC 8 hyperlinks; conhost doesn't. Either way, nothing is
+// linked unless stdout is a console that accepts escape sequences.
+pub fn supports_hyperlinks() -> bool {
+    if std::env::var_os("WT_SESSION").is_none() {
+        return false;
+    }
+    let stdout = match unsafe { GetStdHandle(STD_OUTPUT_HANDLE) } {
+        Ok(stdout) => stdout,
+        Err(_) => return false,
+    };
+    let mut mode = CONSOLE_MODE::default();
+    if !unsafe { GetConsoleMode(stdout, &mut mode) }.as_bool() {
+        return false;
+    }
+    mode.0 & ENABLE_VIRTUAL_TERMINAL_PROCESSING.0 != 0
+        || unsafe {
+            SetConsoleMode(
+                stdout,
+                CONSOLE_MODE(mode.0 | ENABLE_VIRTUAL_TERMINAL_PROCESSING.0),
+            )
+        }
+        .as_bool()
+}
+
+// None when stdout isn't a console, e.g. when it's redirected to a file or a pipe.
+pub fn terminal_width() -> Option<usize> {
+    let stdout = unsafe { GetStdHandle(STD_OUTPUT_HANDLE) }.ok()?;
+    let mut info = CONSOLE_SCREEN_BUFFER_INFO::default();
+    if !unsafe { GetConsoleScreenBufferInfo(stdout, &mut info) }.as_bool() {
+        return None;
+    }
+    Some((info.srWindow.Right - info.srWindow.Left + 1) as usize)
+}
@@ -0,0 +1,111 @@
+use std::borrow::Cow;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf, Prefix};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// std::fs lifts the 260-character limit itself; paths handed straight to Win32, such as
+// LoadLibraryExW's, need the \\?\ prefix to get past it.
+const MAX_PATH: usize = 260;
+
+const VERBATIM: &str = r"\\?\";
+const VERBATIM_UNC: &str = r"\\?\UNC\";
+const DEVICE: &str = r"\\.\";
+
+// Lets SeBackupPrivilege, once enabled, open files their ACLs would otherwise deny.
+#[cfg(windows)]
+const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+
+// Set once the process holds SeBackupPrivilege, so read_file opens files for backup.
+static BACKUP_SEMANTICS: AtomicBool = AtomicBool::new(false);
+
+// path in a form Win32 accepts at any length: absolute and normalized, prefixed with \\?\,
+// or \\?\UNC\ for shares. Paths short enough not to need it, paths that are already
+// prefixed, and bare module names the loader is meant to search for are returned as they
+// are.
+pub fn extended_length(path: &str) -> Cow<'_, str> {
+    if path.len() < MAX_PATH
+        || path.starts_with(VERBATIM)
+        || path.starts_with(DEVICE)
+        || !path.contains(['\\', '/'])
+    {
+        return Cow::Borrowed(path);
+    }
+    let absolute = match env::current_dir() {
+        Ok(dir) => dir.join(path),
+        Err(_) => return Cow::Borrowed(path),
+    };
+    // \\?\ turns off the normalization Win32 otherwise does, so . and .. are resolved here.
+    let mut prefix = None;
+    let mut parts = Vec::new();
+    for component in absolute.components() {
+        match component {
+            Component::Prefix(component) => {
+                prefix = match component.kind() {
+                    Prefix::Disk(drive) => Some(format!("{}{}:", VERBATIM, drive as char)),
+                    Prefix::UNC(server, share) => Some(format!(
+                        "{}{}\\{}",
+                        VERBATIM_UNC,
+                        server.to_string_lossy(),
+                        share.to_string_lossy()
+                    )),
+                    _ => return Cow::Borrowed(path),
+                }
+            }
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir => {
+                parts.pop();
+            }
+            Component::Normal(part) => parts.push(part.to_string_lossy()),
+        }
+    }
+    match prefix {
+        Some(prefix) => {
+            let mut extended = prefix;
+            for part in parts {
+                extended.push('\\');
+                extended.push_str(&part);
+            }
+            Cow::Owned(extended)
+        }
+        None => Cow::Borrowed(path),
+    }
+}
+
+// The inverse of extended_length, for comparing paths with ones spelled without the
+// prefix, such as fs::canonicalize's output against System32's.
+pub fn strip_verbatim(path: &Path) -> PathBuf {
+    let path_str = path.to_string_lossy();
+    if let Some(rest) = path_str.strip_prefix(VERBATIM_UNC) {
+        return PathBuf::from(format!(r"\\{}", rest));
+    }
+    match path_str.strip_prefix(VERBATIM) {
+        Some(rest) => PathBuf::from(rest),
+        None => path.to_path_buf(),
+    }
+}
+
+// Makes read_file open files with backup semantics from now on. Only of use once
+// SeBackupPrivilege has been enabled.
+pub fn use_backup_semantics() {
+    BACKUP_SEMANTICS.store(true, Ordering::Relaxed);
+}
+
+// fs::read, but with backup semantics once use_backup_semantics has been called.
+pub fn read_file(path: &Path) -> io::Result<Vec<u8>> {
+    #[cfg(windows)]
+    if BACKUP_SEMANTICS.load(Ordering::Relaxed) {
+        use std::io::Read;
+        use std::os::windows::fs::OpenOptionsExt;
+
+        let mut file = fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+            .open(path)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        return Ok(data);
+    }
+    fs::read(path)
+}
@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::Path;
+
+use object::pe::{ImageNtHeaders32, ImageNtHeaders64, IMAGE_FILE_DLL};
+use object::read::pe::{
+    ImageNtHeaders, ImageOptionalHeader, PeFile, ResourceDirectory, ResourceDirectoryEntryData,
+    ResourceNameOrId,
+};
+use object::FileKind;
+
+use crate::paths;
+use crate::provider::{self, ResourceProvider};
+use crate::table::{Error, RawMessageTable, ResourceId, Result};
+
+// Reads message tables straight out of a PE file's resource directory, without the
+// loader. This works on any platform, but knows nothing about the user's UI language or
+// MUI redirection: without all_langs, each resource's LANG_NEUTRAL table is read, then
+// en-US, then whichever language comes first.
+pub fn read_raw_message_tables(path: &Path, all_langs: bool) -> Result<Vec<RawMessageTable>> {
+    let data = paths::read_file(path)
+        .map_err(|e| Error::new(format!("failed to read {}: {}", path.display(), e)))?;
+    parse_raw_message_tables(&data, all_langs)
+}
+
+pub fn parse_raw_message_tables(data: &[u8], all_langs: bool) -> Result<Vec<RawMessageTable>> {
+    provider::read_raw_message_tables(&PeResources::parse(data)?, all_langs)
+}
+
+// Entry points that do nothing but return TRUE, which is what the linker's default DllMain
+// and hand-written stubs for resource DLLs amount to.
+const TRIVIAL_ENTRY_POINTS: &[&[u8]] = &[
+    // mov eax, 1; ret
+    &[0xb8, 0x01, 0x00, 0x00, 0x00, 0xc3],
+    // mov eax, 1; ret 0Ch
+    &[0xb8, 0x01, 0x00, 0x00, 0x00, 0xc2, 0x0c, 0x00],
+    // xor eax, eax; inc eax; ret 0Ch
+    &[0x33, 0xc0, 0x40, 0xc2, 0x0c, 0x00],
+    // push 1; pop eax; ret 0Ch
+    &[0x6a, 0x01, 0x58, 0xc2, 0x0c, 0x00],
+];
+
+// Whether path is a resource-only DLL: one that exports nothing and whose entry point is
+// either absent (linked with /NOENTRY) or returns TRUE without doing anything. Loading
+// these as data files loses nothing, since there's no code worth running.
+pub fn is_resource_only(path: &Path) -> Result<bool> {
+    let data = paths::read_file(path)
+        .map_err(|e| Error::new(format!("failed to read {}: {}", path.display(), e)))?;
+    match FileKind::parse(&*data) {
+        Ok(FileKind::Pe32) => resource_only::<ImageNtHeaders32>(&data),
+        Ok(FileKind::Pe64) => resource_only::<ImageNtHeaders64>(&data),
+        _ => Err(Error::new("not a PE file")),
+    }
+}
+
+fn resource_only<Pe: ImageNtHeaders>(data: &[u8]) -> Result<bool> {
+    let file = PeFile::<Pe>::parse(data).map_err(invalid)?;
+    let headers = file.nt_headers();
+    if headers
+        .file_header()
+        .characteristics
+        .get(object::LittleEndian)
+        & IMAGE_FILE_DLL
+        == 0
+    {
+        return Ok(false);
+    }
+    let exports = file.export_table().map_err(invalid)?;
+    if exports.map_or(false, |exports| !exports.addresses().is_empty()) {
+        return Ok(false);
+    }
+    let entry_point = headers.optional_header().address_of_entry_point();
+    if entry_point == 0 {
+        return Ok(true);
+    }
+    let code = file.section_table().pe_data_at(data, entry_point);
+    Ok(code.map_or(false, |code| {
+        TRIVIAL_ENTRY_POINTS
+            .iter()
+            .any(|stub| code.starts_with(stub))
+    }))
+}
+
+type Directory = BTreeMap<ResourceId, BTreeMap<ResourceId, BTreeMap<u16, Range<usize>>>>;
+
+// The resources of a PE file in memory. The resource directory is walked once up front;
+// data is only copied out when asked for.
+pub struct PeResources<'data> {
+    data: &'data [u8],
+    // File offsets of each resource's data.
+    resources: Directory,
+}
+
+impl<'data> PeResources<'data> {
+    pub fn parse(data: &'data [u8]) -> Result<PeResources<'data>> {
+        let resources = match FileKind::parse(data) {
+            Ok(FileKind::Pe32) => parse_pe::<ImageNtHeaders32>(data)?,
+            Ok(FileKind::Pe64) => parse_pe::<ImageNtHeaders64>(data)?,
+            _ => return Err(Error::new("not a PE file")),
+        };
+        Ok(PeResources { data, resources })
+    }
+}
+
+impl ResourceProvider for PeResources<'_> {
+    fn types(&self) -> Result<Vec<ResourceId>> {
+        Ok(self.resources.keys().cloned().collect())
+    }
+
+    fn names(&self, typ: &ResourceId) -> Result<Vec<ResourceId>> {
+        Ok(self
+            .resources
+            .get(typ)
+            .map(|names| names.keys().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn langs(&self, typ: &ResourceId, name: &ResourceId) -> Result<Vec<u16>> {
+        Ok(self
+            .resources
+            .get(typ)
+            .and_then(|names| names.get(name))
+            .map(|langs| langs.keys().copied().collect())
+            .unwrap_or_default())
+    }
+
+    fn data(&self, typ: &ResourceId, name: &ResourceId, lang: u16) -> Result<Vec<u8>> {
+        self.resources
+            .get(typ)
+            .and_then(|names| names.get(name))
+            .and_then(|langs| langs.get(&lang))
+            .map(|range| self.data[range.clone()].to_vec())
+            .ok_or_else(|| {
+                Error::new(format!(
+                    "resource {} {} [{:#06x}] not found",
+                    typ, name, lang
+                ))
+            })
+    }
+}
+
+fn resource_id(name: ResourceNameOrId, directory: ResourceDirectory) -> Result<ResourceId> {
+    match name {
+        ResourceNameOrId::Id(id) => Ok(ResourceId::Id(id)),
+        ResourceNameOrId::Name(name) => Ok(ResourceId::Name(
+            name.to_string_lossy(directory).map_err(invalid)?,
+        )),
+    }
+}
+
+fn invalid(e: object::Error) -> Error {
+    Error::new(format!("invalid PE file: {}", e))
+}
+
+fn parse_pe<Pe: ImageNtHeaders>(data: &[u8]) -> Result<Directory> {
+    let file = PeFile::<Pe>::parse(data).map_err(invalid)?;
+    let sections = file.section_table();
+    let mut resources = Directory::new();
+    let directory = match file
+        .data_directories()
+        .resource_directory(data, &sections)
+        .map_err(invalid)?
+    {
+        Some(directory) => directory,
+        None => return Ok(resources),
+    };
+
+    // The resource directory is a tree of type, then name, then language.
+    for type_entry in &directory.root().map_err(invalid)?.entries {
+        let typ = resource_id(type_entry.name_or_id(), directory)?;
+        let names = match type_entry.data(directory).map_err(invalid)? {
+            ResourceDirectoryEntryData::Table(names) => names,
+            ResourceDirectoryEntryData::Data(_) => continue,
+        };
+        for name_entry in names.entries {
+            let name = resource_id(name_entry.name_or_id(), directory)?;
+            let langs = match name_entry.data(directory).map_err(invalid)? {
+                ResourceDirectoryEntryData::Table(langs) => langs,
+                ResourceDirectoryEntryData::Data(_) => continue,
+            };
+            for lang_entry in langs.entries {
+                let lang = match lang_entry.name_or_id() {
+                    ResourceNameOrId::Id(lang) => lang,
+                    ResourceNameOrId::Name(_) => continue,
+                };
+                let data_entry = match lang_entry.data(directory).map_err(invalid)? {
+                    ResourceDirectoryEntryData::Data(data_entry) => data_entry,
+                    ResourceDirectoryEntryData::Table(_) => continue,
+                };
+                let rva = data_entry.offset_to_data.get(object::LittleEndian);
+                let size = data_entry.size.get(object::LittleEndian) as usize;
+                let bytes = sections
+                    .pe_data_at(data, rva)
+                    .and_then(|bytes| bytes.get(..size))
+                    .ok_or_else(|| {
+                        Error::new(format!(
+                            "resource {} {} [{:#06x}] lies outside the file",
+                            typ, name, lang
+                        ))
+                    })?;
+                let offset = bytes.as_ptr() as usize - data.as_ptr() as usize;
+                resources
+                    .entry(typ.clone())
+                    .or_default()
+                    .entry(name.clone())
+                    .or_default()
+                    .insert(lang, offset..offset + size);
+            }
+        }
+    }
+    Ok(resources)
+}
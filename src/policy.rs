@@ -0,0 +1,321 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use windows::Win32::System::LibraryLoader::*;
+
+use crate::fallback::{LangFallback, Preferring};
+use crate::message::MessageEntry;
+use crate::msgtable;
+use crate::paths;
+use crate::pe::{self, PeResources};
+use crate::provider::{self, ResourceProvider};
+use crate::scan;
+use crate::sys;
+use crate::table::{Error, MessageTable, RawMessageTable, Result};
+
+// How many reads that timed out may still be running before further timed reads are failed
+// outright rather than given a thread of their own.
+const MAX_STALLED_READS: usize = 16;
+
+// Reads given up on that haven't finished yet.
+static STALLED_READS: AtomicUsize = AtomicUsize::new(0);
+
+// Decides which modules may be handed to the loader. With never_load, only modules inside
+// one of the trusted directories are; everything else is parsed offline, so no code from it
+// and no loader callbacks can run.
+#[derive(Debug, Clone, Default)]
+pub struct LoadPolicy {
+    never_load: bool,
+    // Lowercased, since paths are compared case-insensitively.
+    trusted_dirs: Vec<PathBuf>,
+    // Applied to modules parsed offline; the loader honors it through the process's
+    // preferred UI languages.
+    lang_fallback: Option<LangFallback>,
+    // Directories bare module names are looked for in, before the default of the current
+    // directory and System32 unless no_default_search.
+    search_path: Vec<PathBuf>,
+    no_default_search: bool,
+    // Volume roots, lowercased, and the shadow copy devices their files are read from
+    // instead. With any, every module is parsed offline.
+    snapshots: Vec<(PathBuf, PathBuf)>,
+    // How long a module may take to read before it's given up on. With a timeout, every
+    // module is parsed offline.
+    timeout: Option<Duration>,
+}
+
+impl LoadPolicy {
+    pub fn new(never_load: bool, trusted_dirs: &[PathBuf]) -> LoadPolicy {
+        LoadPolicy {
+            never_load,
+            trusted_dirs: trusted_dirs.iter().map(|dir| normalize(dir)).collect(),
+            lang_fallback: None,
+            search_path: Vec::new(),
+            no_default_search: false,
+            snapshots: Vec::new(),
+            timeout: None,
+        }
+    }
+
+    pub fn with_search_path(
+        mut self,
+        search_path: &[PathBuf],
+        no_default_search: bool,
+    ) -> LoadPolicy {
+        self.search_path = search_path.to_vec();
+        self.no_default_search = no_default_search;
+        self
+    }
+
+    // The absolute path mod_name is read from. Names with a directory in them are taken as
+    // given; bare names are looked for in the search path, then, unless default search is
+    // off, the current directory and System32. Without default search, a bare name that
+    // isn't on the search path is an error rather than something for the loader to find.
+    pub fn resolve(&self, mod_name: &str) -> Result<PathBuf> {
+        let path = PathBuf::from(mod_name);
+        let is_bare = !mod_name.contains(['\\', '/']);
+        let found = if is_bare {
+            self.search_path
+                .iter()
+                .map(|dir| dir.join(mod_name))
+                .find(|candidate| candidate.is_file())
+        } else {
+            Some(path.clone())
+        };
+        let resolved = match found {
+            Some(found) => found,
+            None if self.no_default_search => {
+                return Err(Error::new(format!(
+                    "{} was not found on the search path",
+                    mod_name
+                )))
+            }
+            None if path.exists() => path,
+            None => scan::system32_dir().join(mod_name),
+        };
+        Ok(absolute(resolved))
+    }
+
+    // What to hand the loader for mod_name: the resolved path if it exists, so the loader's
+    // own search order never picks the file, and the name as given otherwise. With
+    // never_load, a name that doesn't resolve to an existing file is an error, since the file
+    // the loader would find instead is one allows_loader never checked.
+    pub fn loader_name(&self, mod_name: &str) -> Result<String> {
+        let path = self.resolve(mod_name)?;
+        if path.exists() {
+            return Ok(path.to_string_lossy().into_owned());
+        }
+        if self.never_load {
+            return Err(Error::new(format!(
+                "{} was not found at {}",
+                mod_name,
+                path.display()
+            )));
+        }
+        Ok(mod_name.to_string())
+    }
+
+    pub fn with_lang_fallback(mut self, lang_fallback: Option<LangFallback>) -> LoadPolicy {
+        self.lang_fallback = lang_fallback;
+        self
+    }
+
+    pub fn lang_fallback(&self) -> Option<&LangFallback> {
+        self.lang_fallback.as_ref()
+    }
+
+    pub fn with_snapshots(mut self, snapshots: &[(PathBuf, PathBuf)]) -> LoadPolicy {
+        self.snapshots = snapshots.to_vec();
+        self
+    }
+
+    // Gives up on modules that take longer than timeout to read, such as malformed ones the
+    // parser stalls on. A load that stalls holds the loader lock, so every module after it
+    // would stall as well; with a timeout, modules are therefore all parsed offline and never
+    // handed to the loader.
+    //
+    // There's no stopping a parse in progress, so a read that's given up on is left to
+    // finish, or not, on a thread of its own. Once MAX_STALLED_READS of those are still
+    // running, further reads fail straight away instead of piling up more threads.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> LoadPolicy {
+        self.timeout = timeout;
+        self
+    }
+
+    // Where a resolved path's file is actually read from: its volume's shadow copy, if it
+    // has one, and the path itself otherwise.
+    pub fn read_path(&self, path: &Path) -> PathBuf {
+        let lowered = PathBuf::from(paths::strip_verbatim(path).to_string_lossy().to_lowercase());
+        // The longest root wins, for volumes mounted in folders of other volumes.
+        self.snapshots
+            .iter()
+            .filter_map(|(volume, device)| {
+                let rest = lowered.strip_prefix(volume).ok()?;
+                Some((volume.as_os_str().len(), device.join(rest)))
+            })
+            .max_by_key(|(len, _)| *len)
+            .map_or_else(|| path.to_path_buf(), |(_, read_path)| read_path)
+    }
+
+    pub fn allows_loader(&self, path: &Path) -> bool {
+        if !self.snapshots.is_empty() || self.timeout.is_some() {
+            return false;
+        }
+        if !self.never_load {
+            return true;
+        }
+        let path = normalize(path);
+        self.trusted_dirs.iter().any(|dir| path.starts_with(dir))
+    }
+
+    // Like sys::get_message_tables, but modules the policy doesn't allow to be loaded are
+    // parsed offline instead. Bare names are resolved as resolve does rather than by the
+    // loader's search order, which only gets the names resolve can't find a file for, and
+    // only when never_load is off.
+    pub fn get_message_tables(
+        &self,
+        mod_name: &str,
+        flags: LOAD_LIBRARY_FLAGS,
+        all_langs: bool,
+    ) -> Result<Vec<MessageTable>> {
+        let path = self.resolve(mod_name)?;
+        if self.allows_loader(&path) {
+            return sys::get_message_tables(&self.loader_name(mod_name)?, flags, all_langs);
+        }
+        let path = self.read_path(&path);
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return read_offline(&path, self.lang_fallback.as_ref(), all_langs),
+        };
+        let stalled = STALLED_READS.load(Ordering::SeqCst);
+        if stalled >= MAX_STALLED_READS {
+            return Err(Error::new(format!(
+                "gave up on {}: {} modules that timed out are still being read",
+                mod_name, stalled
+            )));
+        }
+        let (sender, receiver) = mpsc::channel();
+        let lang_fallback = self.lang_fallback.clone();
+        // Set by whichever of the read finishing and the read being given up on comes first.
+        let settled = Arc::new(AtomicBool::new(false));
+        let worker_settled = Arc::clone(&settled);
+        thread::spawn(move || {
+            // The receiver is gone if the read was abandoned.
+            let _ = sender.send(read_offline(&path, lang_fallback.as_ref(), all_langs));
+            if worker_settled.swap(true, Ordering::SeqCst) {
+                STALLED_READS.fetch_sub(1, Ordering::SeqCst);
+            }
+        });
+        match receiver.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                // Counted before settling, so the worker can't uncount it first.
+                STALLED_READS.fetch_add(1, Ordering::SeqCst);
+                if settled.swap(true, Ordering::SeqCst) {
+                    STALLED_READS.fetch_sub(1, Ordering::SeqCst);
+                }
+                Err(Error::new(format!(
+                    "gave up on {} after {} seconds",
+                    mod_name,
+                    timeout.as_secs_f64()
+                )))
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                Err(Error::new(format!("reading {} panicked", mod_name)))
+            }
+        }
+    }
+
+    // Hands f mod_name's resources, read through the loader as a data file when the policy
+    // allows it and straight out of the file otherwise.
+    pub fn with_resources<T>(
+        &self,
+        mod_name: &str,
+        f: impl FnOnce(&dyn ResourceProvider) -> Result<T>,
+    ) -> Result<T> {
+        let path = self.resolve(mod_name)?;
+        if self.allows_loader(&path) {
+            let module = sys::Module::load(
+                &self.loader_name(mod_name)?,
+                LOAD_LIBRARY_AS_DATAFILE | LOAD_LIBRARY_AS_IMAGE_RESOURCE,
+            )?;
+            return f(&module);
+        }
+        let path = self.read_path(&path);
+        let data = paths::read_file(&path)
+            .map_err(|e| Error::new(format!("failed to read {}: {}", path.display(), e)))?;
+        f(&PeResources::parse(&data)?)
+    }
+
+    pub fn get_message_table_entries(
+        &self,
+        mod_name: &str,
+        flags: LOAD_LIBRARY_FLAGS,
+    ) -> Result<Vec<MessageEntry>> {
+        let tables = self.get_message_tables(mod_name, flags, false)?;
+        Ok(tables
+            .into_iter()
+            .flat_map(MessageTable::into_message_entries)
+            .collect())
+    }
+}
+
+fn read_offline(
+    path: &Path,
+    lang_fallback: Option<&LangFallback>,
+    all_langs: bool,
+) -> Result<Vec<MessageTable>> {
+    let raws = match lang_fallback {
+        Some(fallback) if !all_langs => read_preferred(path, fallback)?,
+        _ => pe::read_raw_message_tables(path, all_langs)?,
+    };
+    let mut results = Vec::new();
+    for raw in raws {
+        let parsed = msgtable::parse(&raw.data)?;
+        results.push(MessageTable {
+            resource: raw.resource,
+            lang: raw.lang,
+            blocks: parsed.blocks,
+            entries: parsed.entries,
+        });
+    }
+    Ok(results)
+}
+
+// Like the loader, reads the .mui satellite of the first language in the chain that has one,
+// and otherwise the module itself.
+fn read_preferred(path: &Path, fallback: &LangFallback) -> Result<Vec<RawMessageTable>> {
+    let path = fallback
+        .satellite(path)
+        .unwrap_or_else(|| path.to_path_buf());
+    let data = paths::read_file(&path)
+        .map_err(|e| Error::new(format!("failed to read {}: {}", path.display(), e)))?;
+    let resources = PeResources::parse(&data)?;
+    let preferring = Preferring {
+        provider: &resources,
+        fallback,
+    };
+    provider::read_raw_message_tables(&preferring, false)
+}
+
+fn absolute(path: PathBuf) -> PathBuf {
+    if path.is_absolute() {
+        return path;
+    }
+    match env::current_dir() {
+        Ok(dir) => dir.join(path),
+        Err(_) => path,
+    }
+}
+
+// Resolves .. and links so a path can't escape a trusted directory by naming it. The \\?\
+// prefix canonicalizing adds is dropped, so paths that can't be canonicalized still compare.
+fn normalize(path: &Path) -> PathBuf {
+    let path =
+        paths::strip_verbatim(&fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()));
+    PathBuf::from(path.to_string_lossy().to_lowercase())
+}
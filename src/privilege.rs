@@ -0,0 +1,67 @@
+use std::mem;
+
+use anyhow::anyhow;
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::Security::*;
+use windows::Win32::System::Threading::*;
+
+use crate::paths;
+
+// Closes the handle on drop.
+struct TokenHandle(HANDLE);
+
+impl Drop for TokenHandle {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.0) };
+    }
+}
+
+// Enables SeBackupPrivilege, which administrators hold but is disabled by default, and has
+// files opened with backup semantics from then on, so ACLs that deny reading them, such as
+// WindowsApps', don't stop them being parsed.
+pub fn enable_backup_privilege() -> anyhow::Result<()> {
+    let mut token = HANDLE::default();
+    unsafe {
+        OpenProcessToken(
+            GetCurrentProcess(),
+            TOKEN_ADJUST_PRIVILEGES | TOKEN_QUERY,
+            &mut token,
+        )
+    }
+    .ok()
+    .map_err(|e| anyhow!("failed to open the process token: {}", e.message()))?;
+    let token = TokenHandle(token);
+
+    let mut luid = LUID::default();
+    unsafe { LookupPrivilegeValueW(PCWSTR::null(), SE_BACKUP_NAME, &mut luid) }
+        .ok()
+        .map_err(|e| anyhow!("failed to look up SeBackupPrivilege: {}", e.message()))?;
+    let privileges = TOKEN_PRIVILEGES {
+        PrivilegeCount: 1,
+        Privileges: [LUID_AND_ATTRIBUTES {
+            Luid: luid,
+            Attributes: SE_PRIVILEGE_ENABLED,
+        }],
+    };
+    unsafe {
+        AdjustTokenPrivileges(
+            token.0,
+            false,
+            Some(&privileges),
+            mem::size_of::<TOKEN_PRIVILEGES>() as u32,
+            None,
+            None,
+        )
+    }
+    .ok()
+    .map_err(|e| anyhow!("failed to enable SeBackupPrivilege: {}", e.message()))?;
+    // Succeeding doesn't mean the token held the privilege to enable.
+    if unsafe { GetLastError() } == ERROR_NOT_ALL_ASSIGNED {
+        return Err(anyhow!(
+            "SeBackupPrivilege isn't held; run as an administrator"
+        ));
+    }
+    paths::use_backup_semantics();
+    Ok(())
+}
@@ -0,0 +1,82 @@
+// Just enough of the protobuf wire format to write --format proto, which catalog.proto
+// describes.
+
+pub const PROTO_SCHEMA: &str = include_str!("catalog.proto");
+
+const VARINT: u32 = 0;
+const LEN: u32 = 2;
+
+// Builds a single message. Fields proto3 treats as implicit are left out when they hold
+// their default, as protoc-generated encoders do; optional fields are written whenever
+// they're present.
+#[derive(Debug, Default)]
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Encoder {
+        Encoder::default()
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn uint32(&mut self, field: u32, value: u32) {
+        if value != 0 {
+            self.optional_uint32(field, Some(value));
+        }
+    }
+
+    pub fn optional_uint32(&mut self, field: u32, value: Option<u32>) {
+        if let Some(value) = value {
+            self.tag(field, VARINT);
+            self.varint(value.into());
+        }
+    }
+
+    pub fn optional_bool(&mut self, field: u32, value: Option<bool>) {
+        if let Some(value) = value {
+            self.tag(field, VARINT);
+            self.varint(value.into());
+        }
+    }
+
+    pub fn string(&mut self, field: u32, value: &str) {
+        if !value.is_empty() {
+            self.optional_bytes(field, Some(value.as_bytes()));
+        }
+    }
+
+    pub fn optional_string(&mut self, field: u32, value: Option<&str>) {
+        self.optional_bytes(field, value.map(str::as_bytes));
+    }
+
+    pub fn optional_bytes(&mut self, field: u32, value: Option<&[u8]>) {
+        if let Some(value) = value {
+            self.tag(field, LEN);
+            self.varint(value.len() as u64);
+            self.buf.extend_from_slice(value);
+        }
+    }
+
+    // Embeds an already-encoded message, such as an element of a repeated field.
+    pub fn message(&mut self, field: u32, message: Encoder) {
+        self.tag(field, LEN);
+        self.varint(message.buf.len() as u64);
+        self.buf.extend(message.buf);
+    }
+
+    fn tag(&mut self, field: u32, wire_type: u32) {
+        self.varint(((field << 3) | wire_type).into());
+    }
+
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+}
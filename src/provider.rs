@@ -0,0 +1,215 @@
+use std::collections::BTreeMap;
+
+use crate::table::{Error, RawMessageTable, ResourceId, Result};
+
+pub const RT_MESSAGETABLE: ResourceId = ResourceId::Id(11);
+
+const LANG_NEUTRAL: u16 = 0;
+const LANG_EN_US: u16 = 0x409;
+
+// A source of resources, laid out like a PE resource directory: types, then names, then
+// languages. Message tables are read through this, so the parsing doesn't care whether
+// resources come from the loader, a PE file on disk or memory.
+pub trait ResourceProvider {
+    fn types(&self) -> Result<Vec<ResourceId>>;
+
+    // A type the module doesn't have has no names.
+    fn names(&self, typ: &ResourceId) -> Result<Vec<ResourceId>>;
+
+    fn langs(&self, typ: &ResourceId, name: &ResourceId) -> Result<Vec<u16>>;
+
+    fn data(&self, typ: &ResourceId, name: &ResourceId, lang: u16) -> Result<Vec<u8>>;
+
+    // The language read when the caller doesn't ask for one. Unless a provider knows
+    // better, that's LANG_NEUTRAL, then en-US, then whichever language comes first.
+    fn default_lang(&self, typ: &ResourceId, name: &ResourceId) -> Result<Option<u16>> {
+        let langs = self.langs(typ, name)?;
+        Ok([LANG_NEUTRAL, LANG_EN_US]
+            .into_iter()
+            .find(|lang| langs.contains(lang))
+            .or_else(|| langs.first().copied()))
+    }
+}
+
+// Identifies a single language of a single resource.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ResourceRef {
+    pub typ: ResourceId,
+    pub name: ResourceId,
+    pub lang: u16,
+}
+
+impl ResourceRef {
+    pub fn data(&self, provider: &dyn ResourceProvider) -> Result<Vec<u8>> {
+        provider.data(&self.typ, &self.name, self.lang)
+    }
+}
+
+// Every resource of every type, without reading any data.
+pub fn enumerate_resources(provider: &dyn ResourceProvider) -> Result<Vec<ResourceRef>> {
+    let mut results = Vec::new();
+    for typ in provider.types()? {
+        results.extend(enumerate_type(provider, &typ)?);
+    }
+    Ok(results)
+}
+
+pub fn enumerate_type(
+    provider: &dyn ResourceProvider,
+    typ: &ResourceId,
+) -> Result<Vec<ResourceRef>> {
+    let mut results = Vec::new();
+    for name in provider.names(typ)? {
+        for lang in provider.langs(typ, &name)? {
+            results.push(ResourceRef {
+                typ: typ.clone(),
+                name: name.clone(),
+                lang,
+            });
+        }
+    }
+    Ok(results)
+}
+
+// Without all_langs, only each resource's default language is read.
+pub fn read_raw_message_tables(
+    provider: &dyn ResourceProvider,
+    all_langs: bool,
+) -> Result<Vec<RawMessageTable>> {
+    let mut results = Vec::new();
+    let mut default_lang: Option<(ResourceId, Option<u16>)> = None;
+    for resource in enumerate_type(provider, &RT_MESSAGETABLE)? {
+        if !all_langs {
+            // Refs of the same name are adjacent, so the default is looked up once per name.
+            let default = match &default_lang {
+                Some((name, lang)) if *name == resource.name => *lang,
+                _ => {
+                    let lang = provider.default_lang(&resource.typ, &resource.name)?;
+                    default_lang = Some((resource.name.clone(), lang));
+                    lang
+                }
+            };
+            if default != Some(resource.lang) {
+                continue;
+            }
+        }
+        results.push(RawMessageTable {
+            data: resource.data(provider)?,
+            resource: resource.name,
+            lang: resource.lang,
+        });
+    }
+    Ok(results)
+}
+
+// Resources held in memory, e.g. to exercise the parsing with hand-made tables.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryResources {
+    resources: BTreeMap<ResourceId, BTreeMap<ResourceId, BTreeMap<u16, Vec<u8>>>>,
+}
+
+impl MemoryResources {
+    pub fn new() -> MemoryResources {
+        MemoryResources::default()
+    }
+
+    pub fn insert(&mut self, typ: ResourceId, name: ResourceId, lang: u16, data: Vec<u8>) {
+        self.resources
+            .entry(typ)
+            .or_default()
+            .entry(name)
+            .or_default()
+            .insert(lang, data);
+    }
+}
+
+impl ResourceProvider for MemoryResources {
+    fn types(&self) -> Result<Vec<ResourceId>> {
+        Ok(self.resources.keys().cloned().collect())
+    }
+
+    fn names(&self, typ: &ResourceId) -> Result<Vec<ResourceId>> {
+        Ok(self
+            .resources
+            .get(typ)
+            .map(|names| names.keys().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn langs(&self, typ: &ResourceId, name: &ResourceId) -> Result<Vec<u16>> {
+        Ok(self
+            .resources
+            .get(typ)
+            .and_then(|names| names.get(name))
+            .map(|langs| langs.keys().copied().collect())
+            .unwrap_or_default())
+    }
+
+    fn data(&self, typ: &ResourceId, name: &ResourceId, lang: u16) -> Result<Vec<u8>> {
+        self.resources
+            .get(typ)
+            .and_then(|names| names.get(name))
+            .and_then(|langs| langs.get(&lang))
+            .cloned()
+            .ok_or_else(|| {
+                Error::new(format!(
+                    "resource {} {} [{:#06x}] not found",
+                    typ, name, lang
+                ))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resources(langs: &[u16]) -> MemoryResources {
+        let mut resources = MemoryResources::new();
+        for &lang in langs {
+            resources.insert(
+                RT_MESSAGETABLE,
+                ResourceId::Id(1),
+                lang,
+                lang.to_le_bytes().to_vec(),
+            );
+        }
+        resources.insert(ResourceId::Id(6), ResourceId::Id(1), 0x409, vec![0]);
+        resources
+    }
+
+    fn read_langs(resources: &MemoryResources, all_langs: bool) -> Vec<u16> {
+        read_raw_message_tables(resources, all_langs)
+            .unwrap()
+            .iter()
+            .map(|table| {
+                assert_eq!(table.data, table.lang.to_le_bytes());
+                table.lang
+            })
+            .collect()
+    }
+
+    #[test]
+    fn default_lang_prefers_neutral_then_en_us() {
+        assert_eq!(read_langs(&resources(&[0x407, 0x409, 0]), false), [0]);
+        assert_eq!(read_langs(&resources(&[0x407, 0x409]), false), [0x409]);
+        assert_eq!(read_langs(&resources(&[0x40c, 0x407]), false), [0x407]);
+    }
+
+    #[test]
+    fn all_langs_reads_only_message_tables() {
+        assert_eq!(
+            read_langs(&resources(&[0x407, 0x409, 0]), true),
+            [0, 0x407, 0x409]
+        );
+    }
+
+    #[test]
+    fn missing_resources_are_errors() {
+        let resources = resources(&[0x409]);
+        assert!(resources
+            .data(&RT_MESSAGETABLE, &ResourceId::Id(1), 0x407)
+            .is_err());
+        assert!(resources.names(&ResourceId::Id(5)).unwrap().is_empty());
+    }
+}
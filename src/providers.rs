@@ -0,0 +1,379 @@
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::ffi::c_void;
+use std::mem;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::anyhow;
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::System::LibraryLoader::*;
+use windows::Win32::System::Registry::*;
+use windows::Win32::System::Threading::*;
+
+use crate::message::MessageEntry;
+use crate::output::Printer;
+use crate::policy::LoadPolicy;
+use crate::services;
+
+// Every event log has a subkey here, and every event source a subkey of its log.
+const EVENTLOG_KEY: &str = "SYSTEM\\CurrentControlSet\\Services\\EventLog";
+
+// An offline image has no CurrentControlSet, which the kernel links at boot; Select says
+// which numbered control set it would have linked.
+const SYSTEM_HIVE: &str = "Windows\\System32\\config\\SYSTEM";
+const SELECT_KEY: &str = "Select";
+const EVENTLOG_UNDER_CONTROL_SET: &str = "Services\\EventLog";
+
+// Manifest-based providers are registered here by GUID instead.
+const PUBLISHERS_KEY: &str = "SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\WINEVT\\Publishers";
+
+// Registry key names are at most 255 characters.
+const MAX_KEY_NAME: usize = 256;
+
+// An event source registered with the classic event log.
+pub struct Provider {
+    pub log: String,
+    pub source: String,
+    // In registry order, which is the order the event log service looks messages up in.
+    pub message_files: Vec<PathBuf>,
+}
+
+// Where providers are registered: this machine's registry, or the SYSTEM hive of an offline
+// Windows image whose system drive is mounted at a directory.
+pub enum Registry {
+    Live,
+    Image(PathBuf),
+}
+
+impl Registry {
+    fn eventlog_key(&self, access: REG_SAM_FLAGS) -> anyhow::Result<RegKey> {
+        let root = match self {
+            Registry::Live => return RegKey::open(HKEY_LOCAL_MACHINE, EVENTLOG_KEY, access),
+            Registry::Image(root) => root,
+        };
+        let hive = RegKey::load_app_hive(&root.join(SYSTEM_HIVE))?;
+        let current = hive
+            .dword_value(SELECT_KEY, "Current")
+            .ok_or_else(|| anyhow!("the image's SYSTEM hive has no current control set"))?;
+        RegKey::open(
+            hive.0,
+            &format!("ControlSet{:03}\\{}", current, EVENTLOG_UNDER_CONTROL_SET),
+            access,
+        )
+    }
+
+    // The system-wide variables message file paths use, as they'd be set on the image's own
+    // system. Per-user and unknown variables aren't expanded.
+    fn variable(&self, name: &str) -> Option<String> {
+        let root = match self {
+            Registry::Live => return env::var(name).ok(),
+            Registry::Image(root) => root,
+        };
+        let under_root = |path: &str| Some(root.join(path).to_string_lossy().into_owned());
+        match name.to_ascii_lowercase().as_str() {
+            "systemroot" | "windir" => under_root("Windows"),
+            "systemdrive" => Some(root.to_string_lossy().trim_end_matches('\\').to_string()),
+            "programfiles" => under_root("Program Files"),
+            "programfiles(x86)" => under_root("Program Files (x86)"),
+            "commonprogramfiles" => under_root("Program Files\\Common Files"),
+            "commonprogramfiles(x86)" => under_root("Program Files (x86)\\Common Files"),
+            "programdata" | "allusersprofile" => under_root("ProgramData"),
+            _ => None,
+        }
+    }
+
+    // Splits a message file list on semicolons and expands each file's variables. Relative
+    // paths are relative to the system root. Absolute paths an image stores refer to its own
+    // drive, so they're moved under its root.
+    fn message_files(&self, value: &str) -> Vec<PathBuf> {
+        let system_root = PathBuf::from(
+            self.variable("SystemRoot")
+                .unwrap_or_else(|| "C:\\Windows".to_string()),
+        );
+        value
+            .split(';')
+            .map(|file| file.trim().trim_matches('"'))
+            .filter(|file| !file.is_empty())
+            .map(|file| {
+                let path =
+                    PathBuf::from(services::expand_env_vars(file, &|name| self.variable(name)));
+                match self {
+                    _ if path.is_relative() => system_root.join(path),
+                    Registry::Image(root) if !path.starts_with(root) => {
+                        under_image_root(root, &path)
+                    }
+                    _ => path,
+                }
+            })
+            .collect()
+    }
+}
+
+// C:\Windows\x.dll on an image mounted at D:\mnt is D:\mnt\Windows\x.dll.
+fn under_image_root(root: &Path, path: &Path) -> PathBuf {
+    let relative: PathBuf = path
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect();
+    root.join(relative)
+}
+
+// Closes the key on drop.
+struct RegKey(HKEY);
+
+impl Drop for RegKey {
+    fn drop(&mut self) {
+        unsafe { RegCloseKey(self.0) };
+    }
+}
+
+impl RegKey {
+    // Loads a hive file privately, without the privileges RegLoadKey needs. It's unloaded
+    // when the last key opened from it is closed.
+    fn load_app_hive(path: &Path) -> anyhow::Result<RegKey> {
+        let path_utf16 = wp::utf8_to_utf16(&path.to_string_lossy());
+        let mut key = HKEY::default();
+        check(
+            unsafe { RegLoadAppKeyW(PCWSTR(path_utf16.as_ptr()), &mut key, KEY_READ, 0, 0) },
+            || format!("failed to load {}", path.display()),
+        )?;
+        Ok(RegKey(key))
+    }
+
+    fn open(parent: HKEY, path: &str, access: REG_SAM_FLAGS) -> anyhow::Result<RegKey> {
+        let path_utf16 = wp::utf8_to_utf16(path);
+        let mut key = HKEY::default();
+        check(
+            unsafe { RegOpenKeyExW(parent, PCWSTR(path_utf16.as_ptr()), 0, access, &mut key) },
+            || format!("failed to open {}", path),
+        )?;
+        Ok(RegKey(key))
+    }
+
+    fn subkeys(&self) -> anyhow::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for index in 0.. {
+            let mut name = [0u16; MAX_KEY_NAME];
+            let mut len = name.len() as u32;
+            let result = unsafe {
+                RegEnumKeyExW(
+                    self.0,
+                    index,
+                    PWSTR(name.as_mut_ptr()),
+                    &mut len,
+                    None,
+                    PWSTR::null(),
+                    None,
+                    None,
+                )
+            };
+            if result == ERROR_NO_MORE_ITEMS {
+                break;
+            }
+            check(result, || "failed to enumerate subkeys".to_string())?;
+            names.push(String::from_utf16_lossy(&name[..len as usize]));
+        }
+        Ok(names)
+    }
+
+    // REG_EXPAND_SZ values come back unexpanded, since only the caller knows which system
+    // their variables belong to. None if the value doesn't exist or isn't a string.
+    fn string_value(&self, subkey: &str, name: &str) -> Option<String> {
+        let subkey_utf16 = wp::utf8_to_utf16(subkey);
+        let name_utf16 = wp::utf8_to_utf16(name);
+        let flags = RRF_RT_REG_SZ | RRF_RT_REG_EXPAND_SZ | RRF_NOEXPAND;
+        let mut size = 0;
+        let result = unsafe {
+            RegGetValueW(
+                self.0,
+                PCWSTR(subkey_utf16.as_ptr()),
+                PCWSTR(name_utf16.as_ptr()),
+                flags,
+                None,
+                None,
+                Some(&mut size),
+            )
+        };
+        if result != ERROR_SUCCESS {
+            return None;
+        }
+        let mut buf = vec![0u16; (size as usize + 1) / 2];
+        let result = unsafe {
+            RegGetValueW(
+                self.0,
+                PCWSTR(subkey_utf16.as_ptr()),
+                PCWSTR(name_utf16.as_ptr()),
+                flags,
+                None,
+                Some(buf.as_mut_ptr() as *mut c_void),
+                Some(&mut size),
+            )
+        };
+        if result != ERROR_SUCCESS {
+            return None;
+        }
+        let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+        Some(String::from_utf16_lossy(&buf[..len]))
+    }
+
+    fn dword_value(&self, subkey: &str, name: &str) -> Option<u32> {
+        let subkey_utf16 = wp::utf8_to_utf16(subkey);
+        let name_utf16 = wp::utf8_to_utf16(name);
+        let mut value = 0u32;
+        let mut size = mem::size_of::<u32>() as u32;
+        let result = unsafe {
+            RegGetValueW(
+                self.0,
+                PCWSTR(subkey_utf16.as_ptr()),
+                PCWSTR(name_utf16.as_ptr()),
+                RRF_RT_REG_DWORD,
+                None,
+                Some(&mut value as *mut u32 as *mut c_void),
+                Some(&mut size),
+            )
+        };
+        (result == ERROR_SUCCESS).then_some(value)
+    }
+}
+
+fn check(result: WIN32_ERROR, context: impl FnOnce() -> String) -> anyhow::Result<()> {
+    if result == ERROR_SUCCESS {
+        return Ok(());
+    }
+    Err(anyhow!(
+        "{}: {}",
+        context(),
+        Error::from(result.to_hresult()).message()
+    ))
+}
+
+// Every event source with an EventMessageFile, in every log.
+pub fn event_providers(registry: &Registry) -> anyhow::Result<Vec<Provider>> {
+    let eventlog = registry.eventlog_key(KEY_READ)?;
+    let mut providers = Vec::new();
+    for log in eventlog.subkeys()? {
+        let log_key = match RegKey::open(eventlog.0, &log, KEY_READ) {
+            Ok(log_key) => log_key,
+            Err(e) => {
+                eprintln!("WARNING: {}: {}", log, e);
+                continue;
+            }
+        };
+        for source in log_key.subkeys()? {
+            if let Some(value) = log_key.string_value(&source, "EventMessageFile") {
+                providers.push(Provider {
+                    log: log.clone(),
+                    message_files: registry.message_files(&value),
+                    source,
+                });
+            }
+        }
+    }
+    Ok(providers)
+}
+
+// The message file of the manifest-based provider with guid, in {...} form, on this machine.
+// None if it isn't registered or has no message file.
+pub fn publisher_message_file(guid: &str) -> Option<PathBuf> {
+    let publishers = RegKey::open(HKEY_LOCAL_MACHINE, PUBLISHERS_KEY, KEY_READ).ok()?;
+    let value = publishers.string_value(guid, "MessageFileName")?;
+    Registry::Live.message_files(&value).into_iter().next()
+}
+
+// Dumps the message files of every event source, grouped so each list of files is dumped
+// once. With watch, then waits for sources to be registered or changed and dumps only the
+// lists it hasn't dumped before, until interrupted.
+pub fn dump_providers(
+    printer: &Printer,
+    policy: &LoadPolicy,
+    registry: &Registry,
+    watch: bool,
+) -> anyhow::Result<()> {
+    let mut dumped = HashSet::new();
+    if !watch {
+        return dump_new(printer, policy, registry, &mut dumped);
+    }
+
+    let eventlog = registry.eventlog_key(KEY_NOTIFY)?;
+    let event = unsafe { CreateEventW(None, false, false, PCWSTR::null()) }?;
+    loop {
+        // Armed before scanning, so changes made during the scan aren't missed.
+        check(
+            unsafe {
+                RegNotifyChangeKeyValue(
+                    eventlog.0,
+                    true,
+                    REG_NOTIFY_CHANGE_NAME | REG_NOTIFY_CHANGE_LAST_SET,
+                    event,
+                    true,
+                )
+            },
+            || "failed to watch the EventLog key".to_string(),
+        )?;
+        dump_new(printer, policy, registry, &mut dumped)?;
+        unsafe { WaitForSingleObject(event, INFINITE) };
+    }
+}
+
+fn dump_new(
+    printer: &Printer,
+    policy: &LoadPolicy,
+    registry: &Registry,
+    dumped: &mut HashSet<String>,
+) -> anyhow::Result<()> {
+    // Keyed by the lowercased list, since sources spell the same files differently.
+    let mut lists: BTreeMap<String, (Vec<PathBuf>, Vec<String>)> = BTreeMap::new();
+    for provider in event_providers(registry)? {
+        let key = list_name(&provider.message_files).to_lowercase();
+        if dumped.contains(&key) {
+            continue;
+        }
+        lists
+            .entry(key)
+            .or_insert_with(|| (provider.message_files, Vec::new()))
+            .1
+            .push(format!("{}/{}", provider.log, provider.source));
+    }
+
+    for (key, (files, sources)) in lists {
+        dumped.insert(key);
+        let entries = merged_entries(policy, &files);
+        println!("{}:", list_name(&files));
+        for source in &sources {
+            println!("  {}", source);
+        }
+        printer.print_entries(&entries, None)?;
+    }
+    Ok(())
+}
+
+fn list_name(files: &[PathBuf]) -> String {
+    files
+        .iter()
+        .map(|file| file.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+// Like the event log service, which tries each file in turn, the first file that defines an
+// ID provides its message. Files that can't be read are skipped.
+fn merged_entries(policy: &LoadPolicy, files: &[PathBuf]) -> Vec<MessageEntry> {
+    let mut merged: BTreeMap<u32, MessageEntry> = BTreeMap::new();
+    for file in files {
+        let entries = match policy.get_message_table_entries(
+            &file.to_string_lossy(),
+            LOAD_LIBRARY_AS_DATAFILE | LOAD_LIBRARY_AS_IMAGE_RESOURCE,
+        ) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("WARNING: {}: {}", file.display(), e);
+                continue;
+            }
+        };
+        for entry in entries {
+            merged.entry(entry.id()).or_insert(entry);
+        }
+    }
+    merged.into_values().collect()
+}
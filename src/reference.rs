@@ -0,0 +1,82 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+
+use crate::table::MessageTable;
+
+// The message IDs documented somewhere, to spot the ones a module defines that aren't.
+#[derive(Debug, Clone, Default)]
+pub struct Reference {
+    ids: HashSet<u32>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Code {
+    // Negative numbers are HRESULTs as languages with signed 32-bit integers print them.
+    Number(i64),
+    Text(String),
+}
+
+// Either a list of codes, or an object keyed by code whose values, such as symbolic names
+// or descriptions, are ignored. Codes are numbers, or strings in hex with a 0x prefix or
+// in decimal.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ReferenceFile {
+    List(Vec<Code>),
+    Map(BTreeMap<String, serde_json::Value>),
+}
+
+impl Reference {
+    pub fn load(path: &Path) -> anyhow::Result<Reference> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read the reference {}", path.display()))?;
+        let file: ReferenceFile = serde_json::from_str(&text)
+            .with_context(|| format!("failed to parse the reference {}", path.display()))?;
+        let codes = match file {
+            ReferenceFile::List(codes) => codes,
+            ReferenceFile::Map(map) => map.into_keys().map(Code::Text).collect(),
+        };
+        let mut ids = HashSet::with_capacity(codes.len());
+        for code in codes {
+            let id = match code {
+                Code::Number(number) => u32::try_from(number)
+                    .ok()
+                    .or_else(|| i32::try_from(number).ok().map(|id| id as u32)),
+                Code::Text(text) => parse_code(text.trim()),
+            };
+            let id = id.ok_or_else(|| anyhow!("{}: not a message ID", path.display()))?;
+            ids.insert(id);
+        }
+        Ok(Reference { ids })
+    }
+
+    pub fn contains(&self, id: u32) -> bool {
+        self.ids.contains(&id)
+    }
+
+    // The IDs defined in any of tables that the reference lacks, in order.
+    pub fn undocumented(&self, tables: &[MessageTable]) -> Vec<u32> {
+        let ids: BTreeSet<u32> = tables
+            .iter()
+            .flat_map(|table| &table.entries)
+            .map(|entry| entry.id)
+            .filter(|&id| !self.contains(id))
+            .collect();
+        ids.into_iter().collect()
+    }
+}
+
+fn parse_code(s: &str) -> Option<u32> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => s
+            .parse::<u32>()
+            .ok()
+            .or_else(|| s.parse::<i32>().ok().map(|id| id as u32)),
+    }
+}
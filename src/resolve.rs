@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use anyhow::{anyhow, Context};
+use windows::Win32::System::LibraryLoader::*;
+
+use crate::output::{self, LangFormat};
+use crate::policy::LoadPolicy;
+use crate::table::MessageTable;
+
+// A row of the input: module, ID and optionally language.
+struct Pair {
+    module: String,
+    id: u32,
+    lang: Option<u16>,
+}
+
+// Each module is read once no matter how many rows name it. Rows without a language are
+// looked up in the language the loader picks, so those are cached separately.
+struct Modules<'a> {
+    tables: HashMap<(String, bool), Vec<MessageTable>>,
+    policy: &'a LoadPolicy,
+}
+
+impl Modules<'_> {
+    fn lookup(&mut self, pair: &Pair) -> anyhow::Result<Option<(u16, &str)>> {
+        let all_langs = pair.lang.is_some();
+        let key = (pair.module.to_lowercase(), all_langs);
+        if !self.tables.contains_key(&key) {
+            let tables = self.policy.get_message_tables(
+                &pair.module,
+                LOAD_LIBRARY_AS_DATAFILE | LOAD_LIBRARY_AS_IMAGE_RESOURCE,
+                all_langs,
+            )?;
+            self.tables.insert(key.clone(), tables);
+        }
+        Ok(self.tables[&key]
+            .iter()
+            .filter(|table| pair.lang.map_or(true, |lang| table.lang == lang))
+            .flat_map(|table| table.entries.iter().map(move |entry| (table.lang, entry)))
+            .find(|(_, entry)| entry.id == pair.id)
+            .map(|(lang, entry)| (lang, entry.text.as_str())))
+    }
+}
+
+// Reads module,id[,lang] rows and writes module,id,lang,text rows. A header row is
+// skipped. Rows that can't be resolved are reported on stderr and written with empty text,
+// so output rows line up with input rows.
+pub fn resolve_pairs(
+    input: impl BufRead,
+    mut out: impl Write,
+    policy: &LoadPolicy,
+    lang_format: LangFormat,
+) -> anyhow::Result<usize> {
+    let mut modules = Modules {
+        tables: HashMap::new(),
+        policy,
+    };
+    let mut failures = 0;
+    writeln!(out, "module,id,lang,text")?;
+    for (index, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let pair = match parse_pair(&line) {
+            Ok(pair) => pair,
+            Err(_) if index == 0 => continue,
+            Err(e) => return Err(e).with_context(|| format!("line {}", index + 1)),
+        };
+
+        let (lang, text) = match modules.lookup(&pair) {
+            Ok(Some((lang, text))) => (Some(lang), text.trim_end()),
+            Ok(None) => {
+                eprintln!("WARNING: {}: {:#x} not found", pair.module, pair.id);
+                failures += 1;
+                (pair.lang, "")
+            }
+            Err(e) => {
+                eprintln!("WARNING: {}: {}", pair.module, e);
+                failures += 1;
+                (pair.lang, "")
+            }
+        };
+        writeln!(
+            out,
+            "{},{:#010x},{},{}",
+            quote(&pair.module),
+            pair.id,
+            lang.map(|lang| quote(&output::format_lang(lang_format, lang)))
+                .unwrap_or_default(),
+            quote(text)
+        )?;
+    }
+    Ok(failures)
+}
+
+fn parse_pair(line: &str) -> anyhow::Result<Pair> {
+    let fields = split_fields(line);
+    let field = |index: usize| fields.get(index).map(|field| field.trim());
+    let module = field(0)
+        .filter(|module| !module.is_empty())
+        .ok_or_else(|| anyhow!("missing module"))?;
+    let id = field(1).ok_or_else(|| anyhow!("missing ID"))?;
+    let id = parse_number(id).ok_or_else(|| anyhow!("invalid ID {:?}", id))?;
+    let lang = match field(2).filter(|lang| !lang.is_empty()) {
+        Some(lang) => Some(
+            parse_number(lang)
+                .and_then(|lang| u16::try_from(lang).ok())
+                .ok_or_else(|| anyhow!("invalid language {:?}", lang))?,
+        ),
+        None => None,
+    };
+    Ok(Pair {
+        module: module.to_string(),
+        id,
+        lang,
+    })
+}
+
+// Hex with a 0x prefix, decimal, or negative decimal as HRESULTs are often logged.
+fn parse_number(s: &str) -> Option<u32> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => s
+            .parse::<u32>()
+            .ok()
+            .or_else(|| s.parse::<i32>().ok().map(|n| n as u32)),
+    }
+}
+
+// Fields may be quoted, with "" standing for a quote inside a quoted field.
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -0,0 +1,810 @@
+use std::fmt;
+
+use crate::provider::{self, ResourceProvider};
+use crate::table::{Error, ResourceId, Result};
+
+pub const RT_CURSOR: ResourceId = ResourceId::Id(1);
+pub const RT_BITMAP: ResourceId = ResourceId::Id(2);
+pub const RT_ICON: ResourceId = ResourceId::Id(3);
+pub const RT_MENU: ResourceId = ResourceId::Id(4);
+pub const RT_DIALOG: ResourceId = ResourceId::Id(5);
+pub const RT_STRING: ResourceId = ResourceId::Id(6);
+pub const RT_ACCELERATOR: ResourceId = ResourceId::Id(9);
+pub const RT_GROUP_CURSOR: ResourceId = ResourceId::Id(12);
+pub const RT_GROUP_ICON: ResourceId = ResourceId::Id(14);
+pub const RT_VERSION: ResourceId = ResourceId::Id(16);
+
+const VS_FFI_SIGNATURE: u32 = 0xfeef04bd;
+
+// ACCELTABLEENTRY flags
+const FVIRTKEY: u16 = 0x01;
+const FNOINVERT: u16 = 0x02;
+const FSHIFT: u16 = 0x04;
+const FCONTROL: u16 = 0x08;
+const FALT: u16 = 0x10;
+const ACCEL_LAST_ENTRY: u16 = 0x80;
+
+const ACCEL_ENTRY_SIZE: usize = 8;
+
+// DS_SETFONT, which DS_SHELLFONT includes: the template names a font.
+const DS_SETFONT: u32 = 0x40;
+const DLGTEMPLATEEX_SIGNATURE: u16 = 0xffff;
+
+// NEWHEADER, as icon and cursor groups and .ico and .cur files all start.
+const ICON_HEADER_SIZE: usize = 6;
+const ICON_TYPE: u16 = 1;
+const CURSOR_TYPE: u16 = 2;
+// A RESDIR, which describes an image much as an ICONDIRENTRY does, with the image's
+// RT_ICON or RT_CURSOR ID in place of its offset.
+const GROUP_ENTRY_SIZE: usize = 14;
+const ICON_DIR_ENTRY_SIZE: usize = 16;
+// RT_CURSOR images start with their hotspot, which .cur files keep in the directory.
+const CURSOR_HOTSPOT_SIZE: usize = 4;
+
+const BITMAP_FILE_HEADER_SIZE: usize = 14;
+const BITMAP_CORE_HEADER_SIZE: u32 = 12;
+const BITMAP_INFO_HEADER_SIZE: u32 = 40;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+// Menu item flags of standard templates.
+const MF_GRAYED: u16 = 0x01;
+const MF_CHECKED: u16 = 0x08;
+const MF_POPUP: u16 = 0x10;
+const MF_END: u16 = 0x80;
+// And of MENUEX templates, where the type, state and the flags are separate.
+const MFT_SEPARATOR: u32 = 0x800;
+const MFS_GRAYED: u32 = 0x03;
+const MFS_CHECKED: u32 = 0x08;
+const MENUEX_POPUP: u16 = 0x01;
+const MENUEX_VERSION: u16 = 1;
+// Far deeper than any real menu nests, and shallow enough that a crafted one can't overflow
+// the stack.
+const MAX_MENU_DEPTH: usize = 64;
+
+// A single string of a STRINGTABLE, in a single language.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StringEntry {
+    pub id: u16,
+    pub lang: u16,
+    pub text: String,
+}
+
+// Every non-empty string of every STRINGTABLE, ordered by ID and then language.
+pub fn string_tables(provider: &dyn ResourceProvider) -> Result<Vec<StringEntry>> {
+    let mut results = Vec::new();
+    for resource in provider::enumerate_type(provider, &RT_STRING)? {
+        let block = match resource.name {
+            ResourceId::Id(block) => block,
+            // String tables are always named by number.
+            ResourceId::Name(_) => continue,
+        };
+        let data = resource.data(provider)?;
+        results.extend(
+            parse_string_block(block, &data)?
+                .into_iter()
+                .map(|(id, text)| StringEntry {
+                    id,
+                    lang: resource.lang,
+                    text,
+                }),
+        );
+    }
+    results.sort_by_key(|entry| (entry.id, entry.lang));
+    Ok(results)
+}
+
+// Block n holds strings (n - 1) * 16 to (n - 1) * 16 + 15, each a length-prefixed UTF-16
+// string. Unused slots have a length of 0.
+fn parse_string_block(block: u16, data: &[u8]) -> Result<Vec<(u16, String)>> {
+    let first_id = block.wrapping_sub(1).wrapping_mul(16);
+    let mut strings = Vec::new();
+    let mut offset = 0;
+    for index in 0..16 {
+        let len = match data.get(offset..offset + 2) {
+            Some(bytes) => u16::from_le_bytes([bytes[0], bytes[1]]) as usize,
+            None => break,
+        };
+        offset += 2;
+        let bytes = data
+            .get(offset..offset + len * 2)
+            .ok_or_else(|| Error::new(format!("string table block {} is truncated", block)))?;
+        offset += len * 2;
+        if len == 0 {
+            continue;
+        }
+        let utf16: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        strings.push((
+            first_id.wrapping_add(index),
+            String::from_utf16_lossy(&utf16),
+        ));
+    }
+    Ok(strings)
+}
+
+// The fixed part of a VERSIONINFO resource. Versions are major, minor, build, revision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionInfo {
+    pub lang: u16,
+    pub file_version: [u16; 4],
+    pub product_version: [u16; 4],
+}
+
+// None if the module has no VERSIONINFO, or one without a VS_FIXEDFILEINFO.
+pub fn version_info(provider: &dyn ResourceProvider) -> Result<Option<VersionInfo>> {
+    let names = provider.names(&RT_VERSION)?;
+    let name = match names.first() {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    let lang = match provider.default_lang(&RT_VERSION, name)? {
+        Some(lang) => lang,
+        None => return Ok(None),
+    };
+    let data = provider.data(&RT_VERSION, name, lang)?;
+    Ok(
+        parse_fixed_file_info(&data).map(|(file_version, product_version)| VersionInfo {
+            lang,
+            file_version,
+            product_version,
+        }),
+    )
+}
+
+// VS_FIXEDFILEINFO follows the VS_VERSION_INFO key on a 32-bit boundary; it's found by its
+// signature rather than by walking the header.
+fn parse_fixed_file_info(data: &[u8]) -> Option<([u16; 4], [u16; 4])> {
+    let dword = |offset: usize| -> Option<u32> {
+        let bytes = data.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+    let start = (0..data.len())
+        .step_by(4)
+        .find(|&offset| dword(offset) == Some(VS_FFI_SIGNATURE))?;
+    // dwSignature, dwStrucVersion, then the file and product versions as MS/LS dwords.
+    let version = |offset: usize| -> Option<[u16; 4]> {
+        let (ms, ls) = (dword(offset)?, dword(offset + 4)?);
+        Some([(ms >> 16) as u16, ms as u16, (ls >> 16) as u16, ls as u16])
+    };
+    Some((version(start + 8)?, version(start + 16)?))
+}
+
+// A single key combination of an ACCELERATORS table and the command it sends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Accelerator {
+    pub key: Key,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    // NOINVERT: the menu item isn't highlighted when the accelerator is used.
+    pub no_invert: bool,
+    pub command: u16,
+}
+
+// A virtual-key code, or a character as typed, which may be a control character.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Virtual(u16),
+    Char(u16),
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Key::Virtual(vk) => match virtual_key_name(vk) {
+                Some(name) => write!(f, "{}", name),
+                None => write!(f, "VK {:#04x}", vk),
+            },
+            // rc writes control characters as ^C.
+            Key::Char(c @ 0x01..=0x1a) => write!(f, "^{}", (b'@' + c as u8) as char),
+            Key::Char(c) => match char::from_u32(c as u32) {
+                Some(c) if !c.is_control() => write!(f, "\"{}\"", c),
+                _ => write!(f, "{:#04x}", c),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Accelerator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (held, name) in [
+            (self.ctrl, "Ctrl"),
+            (self.alt, "Alt"),
+            (self.shift, "Shift"),
+        ] {
+            if held {
+                write!(f, "{}+", name)?;
+            }
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+// A single language of a single ACCELERATORS resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AcceleratorTable {
+    pub name: ResourceId,
+    pub lang: u16,
+    pub accelerators: Vec<Accelerator>,
+}
+
+// Every language of every accelerator table.
+pub fn accelerator_tables(provider: &dyn ResourceProvider) -> Result<Vec<AcceleratorTable>> {
+    let mut results = Vec::new();
+    for resource in provider::enumerate_type(provider, &RT_ACCELERATOR)? {
+        let data = resource.data(provider)?;
+        results.push(AcceleratorTable {
+            accelerators: parse_accelerators(&data),
+            name: resource.name,
+            lang: resource.lang,
+        });
+    }
+    Ok(results)
+}
+
+// An array of ACCELTABLEENTRY: flags, key, command and padding, each a WORD. The last entry
+// is flagged; a table missing the flag ends with its data.
+fn parse_accelerators(data: &[u8]) -> Vec<Accelerator> {
+    let mut accelerators = Vec::new();
+    for entry in data.chunks_exact(ACCEL_ENTRY_SIZE) {
+        let word = |offset: usize| u16::from_le_bytes([entry[offset], entry[offset + 1]]);
+        let (flags, key, command) = (word(0), word(2), word(4));
+        accelerators.push(Accelerator {
+            key: if flags & FVIRTKEY != 0 {
+                Key::Virtual(key)
+            } else {
+                Key::Char(key)
+            },
+            ctrl: flags & FCONTROL != 0,
+            alt: flags & FALT != 0,
+            shift: flags & FSHIFT != 0,
+            no_invert: flags & FNOINVERT != 0,
+            command,
+        });
+        if flags & ACCEL_LAST_ENTRY != 0 {
+            break;
+        }
+    }
+    accelerators
+}
+
+fn virtual_key_name(vk: u16) -> Option<String> {
+    let name = match vk {
+        0x08 => "Backspace",
+        0x09 => "Tab",
+        0x0c => "Clear",
+        0x0d => "Enter",
+        0x13 => "Pause",
+        0x14 => "Caps Lock",
+        0x1b => "Esc",
+        0x20 => "Space",
+        0x21 => "Page Up",
+        0x22 => "Page Down",
+        0x23 => "End",
+        0x24 => "Home",
+        0x25 => "Left",
+        0x26 => "Up",
+        0x27 => "Right",
+        0x28 => "Down",
+        0x2c => "Print Screen",
+        0x2d => "Insert",
+        0x2e => "Delete",
+        0x2f => "Help",
+        0x30..=0x39 | 0x41..=0x5a => return Some((vk as u8 as char).to_string()),
+        0x5d => "Apps",
+        0x60..=0x69 => return Some(format!("Num {}", vk - 0x60)),
+        0x6a => "Num *",
+        0x6b => "Num +",
+        0x6d => "Num -",
+        0x6e => "Num .",
+        0x6f => "Num /",
+        0x70..=0x87 => return Some(format!("F{}", vk - 0x6f)),
+        0x90 => "Num Lock",
+        0x91 => "Scroll Lock",
+        0xba => ";",
+        0xbb => "=",
+        0xbc => ",",
+        0xbd => "-",
+        0xbe => ".",
+        0xbf => "/",
+        0xc0 => "`",
+        0xdb => "[",
+        0xdc => "\\",
+        0xdd => "]",
+        0xde => "'",
+        _ => return None,
+    };
+    Some(name.to_string())
+}
+
+// A single language of a single DIALOG or DIALOGEX resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dialog {
+    pub name: ResourceId,
+    pub lang: u16,
+    // Whether it's a DLGTEMPLATEEX.
+    pub extended: bool,
+    pub style: u32,
+    pub ex_style: u32,
+    // In dialog units.
+    pub rect: [i16; 4],
+    pub menu: Option<ResourceId>,
+    pub class: Option<ResourceId>,
+    pub caption: String,
+    // Point size and typeface.
+    pub font: Option<(u16, String)>,
+    pub controls: Vec<DialogControl>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DialogControl {
+    // -1 for IDC_STATIC.
+    pub id: i32,
+    // The predefined classes, stored as atoms, are named after their window classes.
+    pub class: ResourceId,
+    // An ordinal text names a resource, such as a static control's icon.
+    pub text: Option<ResourceId>,
+    pub style: u32,
+    pub ex_style: u32,
+    pub rect: [i16; 4],
+}
+
+// Every language of every dialog.
+pub fn dialogs(provider: &dyn ResourceProvider) -> Result<Vec<Dialog>> {
+    let mut results = Vec::new();
+    for resource in provider::enumerate_type(provider, &RT_DIALOG)? {
+        let data = resource.data(provider)?;
+        let mut reader = TemplateReader {
+            data: &data,
+            offset: 0,
+        };
+        let dialog = parse_dialog(&mut reader, resource.name.clone(), resource.lang)
+            .ok_or_else(|| Error::new(format!("dialog {} is truncated", resource.name)))?;
+        results.push(dialog);
+    }
+    Ok(results)
+}
+
+// Reads the little-endian fields of dialog and menu templates, which are packed except where
+// DWORD alignment is asked for.
+struct TemplateReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl TemplateReader<'_> {
+    fn u8(&mut self) -> Option<u8> {
+        let byte = *self.data.get(self.offset)?;
+        self.offset += 1;
+        Some(byte)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.data.get(self.offset..self.offset + 2)?;
+        self.offset += 2;
+        Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(self.u16()? as u32 | (self.u16()? as u32) << 16)
+    }
+
+    fn rect(&mut self) -> Option<[i16; 4]> {
+        Some([
+            self.u16()? as i16,
+            self.u16()? as i16,
+            self.u16()? as i16,
+            self.u16()? as i16,
+        ])
+    }
+
+    // A NUL-terminated UTF-16 string.
+    fn string(&mut self) -> Option<String> {
+        let mut utf16 = Vec::new();
+        loop {
+            match self.u16()? {
+                0 => return Some(String::from_utf16_lossy(&utf16)),
+                unit => utf16.push(unit),
+            }
+        }
+    }
+
+    // A string, an ordinal after 0xffff, or nothing at all for a lone 0.
+    fn string_or_ordinal(&mut self) -> Option<Option<ResourceId>> {
+        match self.data.get(self.offset..self.offset + 2)? {
+            [0, 0] => {
+                self.offset += 2;
+                Some(None)
+            }
+            [0xff, 0xff] => {
+                self.offset += 2;
+                Some(Some(ResourceId::Id(self.u16()?)))
+            }
+            _ => Some(Some(ResourceId::Name(self.string()?))),
+        }
+    }
+
+    fn align4(&mut self) {
+        self.offset = (self.offset + 3) & !3;
+    }
+}
+
+fn parse_dialog(reader: &mut TemplateReader, name: ResourceId, lang: u16) -> Option<Dialog> {
+    let extended = reader.data.get(2..4) == Some(&DLGTEMPLATEEX_SIGNATURE.to_le_bytes()[..]);
+    let (style, ex_style) = if extended {
+        // dlgVer, signature and helpID.
+        reader.offset = 8;
+        let ex_style = reader.u32()?;
+        (reader.u32()?, ex_style)
+    } else {
+        (reader.u32()?, reader.u32()?)
+    };
+    let count = reader.u16()?;
+    let rect = reader.rect()?;
+    let menu = reader.string_or_ordinal()?;
+    let class = reader.string_or_ordinal()?;
+    let caption = reader.string()?;
+    let font = if style & DS_SETFONT != 0 {
+        let size = reader.u16()?;
+        if extended {
+            // Weight, italic and charset.
+            reader.u16()?;
+            reader.u8()?;
+            reader.u8()?;
+        }
+        Some((size, reader.string()?))
+    } else {
+        None
+    };
+
+    let mut controls = Vec::new();
+    for _ in 0..count {
+        reader.align4();
+        let (style, ex_style, rect, id) = if extended {
+            // helpID.
+            reader.u32()?;
+            let ex_style = reader.u32()?;
+            let style = reader.u32()?;
+            let rect = reader.rect()?;
+            (style, ex_style, rect, reader.u32()? as i32)
+        } else {
+            let style = reader.u32()?;
+            let ex_style = reader.u32()?;
+            let rect = reader.rect()?;
+            (style, ex_style, rect, reader.u16()? as i16 as i32)
+        };
+        let class = match reader.string_or_ordinal()? {
+            Some(ResourceId::Id(atom)) => control_class(atom),
+            Some(class) => class,
+            None => ResourceId::Name(String::new()),
+        };
+        let text = reader.string_or_ordinal()?;
+        // Creation data for the control, which isn't decoded.
+        let extra = reader.u16()? as usize;
+        reader.offset += extra;
+        controls.push(DialogControl {
+            id,
+            class,
+            text,
+            style,
+            ex_style,
+            rect,
+        });
+    }
+    Some(Dialog {
+        name,
+        lang,
+        extended,
+        style,
+        ex_style,
+        rect,
+        menu,
+        class,
+        caption,
+        font,
+        controls,
+    })
+}
+
+fn control_class(atom: u16) -> ResourceId {
+    let name = match atom {
+        0x80 => "Button",
+        0x81 => "Edit",
+        0x82 => "Static",
+        0x83 => "ListBox",
+        0x84 => "ScrollBar",
+        0x85 => "ComboBox",
+        _ => return ResourceId::Id(atom),
+    };
+    ResourceId::Name(name.to_string())
+}
+
+// A single language of a single MENU or MENUEX resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Menu {
+    pub name: ResourceId,
+    pub lang: u16,
+    // Whether it's a MENUEX template.
+    pub extended: bool,
+    pub items: Vec<MenuItem>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MenuItem {
+    // Popups of standard templates have no ID, and are given 0.
+    pub id: u32,
+    pub text: String,
+    pub popup: bool,
+    pub separator: bool,
+    pub grayed: bool,
+    pub checked: bool,
+    // The items of a popup.
+    pub children: Vec<MenuItem>,
+}
+
+// Every language of every menu.
+pub fn menus(provider: &dyn ResourceProvider) -> Result<Vec<Menu>> {
+    let mut results = Vec::new();
+    for resource in provider::enumerate_type(provider, &RT_MENU)? {
+        let data = resource.data(provider)?;
+        let mut reader = TemplateReader {
+            data: &data,
+            offset: 0,
+        };
+        let menu =
+            parse_menu(&mut reader, resource.name.clone(), resource.lang).ok_or_else(|| {
+                Error::new(format!(
+                    "menu {} is truncated or nested too deeply",
+                    resource.name
+                ))
+            })?;
+        results.push(menu);
+    }
+    Ok(results)
+}
+
+fn parse_menu(reader: &mut TemplateReader, name: ResourceId, lang: u16) -> Option<Menu> {
+    let version = reader.u16()?;
+    let header_size = reader.u16()? as usize;
+    let extended = version == MENUEX_VERSION;
+    // Items follow the header, whose size is counted from the end of its second field.
+    reader.offset += header_size;
+    let items = if extended {
+        parse_menuex_items(reader, 0)?
+    } else {
+        parse_menu_items(reader, 0)?
+    };
+    Some(Menu {
+        name,
+        lang,
+        extended,
+        items,
+    })
+}
+
+// Items follow one another, the last of each level flagged MF_END, with a popup's items
+// following the popup. A separator is an item with no ID and no text. Popups nested deeper
+// than MAX_MENU_DEPTH fail the menu.
+fn parse_menu_items(reader: &mut TemplateReader, depth: usize) -> Option<Vec<MenuItem>> {
+    if depth > MAX_MENU_DEPTH {
+        return None;
+    }
+    let mut items = Vec::new();
+    loop {
+        let flags = reader.u16()?;
+        let popup = flags & MF_POPUP != 0;
+        let id = if popup { 0 } else { reader.u16()? as u32 };
+        let text = reader.string()?;
+        let children = if popup {
+            parse_menu_items(reader, depth + 1)?
+        } else {
+            Vec::new()
+        };
+        items.push(MenuItem {
+            id,
+            separator: !popup && id == 0 && text.is_empty(),
+            text,
+            popup,
+            grayed: flags & MF_GRAYED != 0,
+            checked: flags & MF_CHECKED != 0,
+            children,
+        });
+        if flags & MF_END != 0 {
+            return Some(items);
+        }
+    }
+}
+
+// Each MENUEX_TEMPLATE_ITEM is DWORD-aligned; a popup's has a help ID after its text.
+fn parse_menuex_items(reader: &mut TemplateReader, depth: usize) -> Option<Vec<MenuItem>> {
+    if depth > MAX_MENU_DEPTH {
+        return None;
+    }
+    let mut items = Vec::new();
+    loop {
+        reader.align4();
+        let typ = reader.u32()?;
+        let state = reader.u32()?;
+        let id = reader.u32()?;
+        let flags = reader.u16()?;
+        let text = reader.string()?;
+        let popup = flags & MENUEX_POPUP != 0;
+        let children = if popup {
+            reader.align4();
+            reader.u32()?;
+            parse_menuex_items(reader, depth + 1)?
+        } else {
+            Vec::new()
+        };
+        items.push(MenuItem {
+            id,
+            text,
+            popup,
+            separator: typ & MFT_SEPARATOR != 0,
+            grayed: state & MFS_GRAYED != 0,
+            checked: state & MFS_CHECKED != 0,
+            children,
+        });
+        if flags & MF_END != 0 {
+            return Some(items);
+        }
+    }
+}
+
+// A single language of an icon group, cursor group or bitmap, reassembled into the
+// contents of a file with the given extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractedFile {
+    pub name: ResourceId,
+    pub lang: u16,
+    pub extension: &'static str,
+    pub data: Vec<u8>,
+}
+
+// Every icon group, cursor group and bitmap, in every language.
+pub fn extract_files(provider: &dyn ResourceProvider) -> Result<Vec<ExtractedFile>> {
+    let mut results = icon_files(provider)?;
+    results.extend(cursor_files(provider)?);
+    results.extend(bitmap_files(provider)?);
+    Ok(results)
+}
+
+pub fn icon_files(provider: &dyn ResourceProvider) -> Result<Vec<ExtractedFile>> {
+    group_files(provider, &RT_GROUP_ICON, &RT_ICON, ICON_TYPE, "ico")
+}
+
+pub fn cursor_files(provider: &dyn ResourceProvider) -> Result<Vec<ExtractedFile>> {
+    group_files(provider, &RT_GROUP_CURSOR, &RT_CURSOR, CURSOR_TYPE, "cur")
+}
+
+// Each group's images are read in the group's language, or the image's default language
+// when it has none in that language.
+fn group_files(
+    provider: &dyn ResourceProvider,
+    group_type: &ResourceId,
+    image_type: &ResourceId,
+    file_type: u16,
+    extension: &'static str,
+) -> Result<Vec<ExtractedFile>> {
+    let mut results = Vec::new();
+    for resource in provider::enumerate_type(provider, group_type)? {
+        let group = resource.data(provider)?;
+        let truncated = || Error::new(format!("group {} is truncated", resource.name));
+        let count = match group.get(..ICON_HEADER_SIZE) {
+            Some(header) if u16_at(header, 2) == file_type => u16_at(header, 4) as usize,
+            _ => return Err(truncated()),
+        };
+        let mut images = Vec::with_capacity(count);
+        for index in 0..count {
+            let offset = ICON_HEADER_SIZE + index * GROUP_ENTRY_SIZE;
+            let entry = group
+                .get(offset..offset + GROUP_ENTRY_SIZE)
+                .ok_or_else(truncated)?;
+            let name = ResourceId::Id(u16_at(entry, 12));
+            let langs = provider.langs(image_type, &name)?;
+            let lang = if langs.contains(&resource.lang) {
+                Some(resource.lang)
+            } else {
+                provider.default_lang(image_type, &name)?
+            };
+            let lang = lang.ok_or_else(|| {
+                Error::new(format!(
+                    "group {} refers to missing image {}",
+                    resource.name, name
+                ))
+            })?;
+            let image = provider.data(image_type, &name, lang)?;
+            if file_type == CURSOR_TYPE && image.len() < CURSOR_HOTSPOT_SIZE {
+                return Err(Error::new(format!("cursor {} is truncated", name)));
+            }
+            images.push((entry, image));
+        }
+
+        // The header is the group's own; each entry describes its image as the group does,
+        // with the image's offset in the file in place of its ID.
+        let mut data = group[..ICON_HEADER_SIZE].to_vec();
+        let mut image_offset = ICON_HEADER_SIZE + count * ICON_DIR_ENTRY_SIZE;
+        for (entry, image) in &mut images {
+            if file_type == CURSOR_TYPE {
+                // Cursor groups give 16-bit sizes, with the height of both the XOR and AND
+                // masks; .cur files give 8-bit ones, 0 meaning 256, and the hotspot.
+                data.push(u16_at(entry, 0) as u8);
+                data.push((u16_at(entry, 2) / 2) as u8);
+                data.extend_from_slice(&[0, 0]);
+                data.extend_from_slice(&image[..CURSOR_HOTSPOT_SIZE]);
+                image.drain(..CURSOR_HOTSPOT_SIZE);
+            } else {
+                data.extend_from_slice(&entry[..8]);
+            }
+            data.extend_from_slice(&(image.len() as u32).to_le_bytes());
+            data.extend_from_slice(&(image_offset as u32).to_le_bytes());
+            image_offset += image.len();
+        }
+        for (_, image) in &images {
+            data.extend_from_slice(image);
+        }
+        results.push(ExtractedFile {
+            name: resource.name,
+            lang: resource.lang,
+            extension,
+            data,
+        });
+    }
+    Ok(results)
+}
+
+// RT_BITMAP resources are .bmp files without their BITMAPFILEHEADER, which only needs the
+// offset of the pixels: after the header and any color table or masks.
+pub fn bitmap_files(provider: &dyn ResourceProvider) -> Result<Vec<ExtractedFile>> {
+    let mut results = Vec::new();
+    for resource in provider::enumerate_type(provider, &RT_BITMAP)? {
+        let dib = resource.data(provider)?;
+        let pixels_offset = dib_pixels_offset(&dib)
+            .ok_or_else(|| Error::new(format!("bitmap {} is truncated", resource.name)))?;
+        let mut data = Vec::with_capacity(BITMAP_FILE_HEADER_SIZE + dib.len());
+        data.extend_from_slice(b"BM");
+        data.extend_from_slice(&((BITMAP_FILE_HEADER_SIZE + dib.len()) as u32).to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&((BITMAP_FILE_HEADER_SIZE + pixels_offset) as u32).to_le_bytes());
+        data.extend_from_slice(&dib);
+        results.push(ExtractedFile {
+            name: resource.name,
+            lang: resource.lang,
+            extension: "bmp",
+            data,
+        });
+    }
+    Ok(results)
+}
+
+fn dib_pixels_offset(dib: &[u8]) -> Option<usize> {
+    let header_size = u32::from_le_bytes(dib.get(..4)?.try_into().ok()?);
+    if header_size == BITMAP_CORE_HEADER_SIZE {
+        // BITMAPCOREHEADER, whose color table has RGBTRIPLEs for up to 8 bits per pixel.
+        let bit_count = u16_at(dib.get(..12)?, 10);
+        let colors = if bit_count <= 8 { 1 << bit_count } else { 0 };
+        return Some(header_size as usize + colors * 3);
+    }
+    let header = dib.get(..BITMAP_INFO_HEADER_SIZE as usize)?;
+    let bit_count = u16_at(header, 14);
+    let compression = u32::from_le_bytes(header[16..20].try_into().ok()?);
+    let colors_used = u32::from_le_bytes(header[32..36].try_into().ok()?) as usize;
+    let colors = match colors_used {
+        0 if bit_count <= 8 => 1 << bit_count,
+        colors_used => colors_used,
+    };
+    // Only a plain BITMAPINFOHEADER is followed by masks; later headers include them.
+    let masks = match compression {
+        BI_BITFIELDS if header_size == BITMAP_INFO_HEADER_SIZE => 12,
+        BI_ALPHABITFIELDS if header_size == BITMAP_INFO_HEADER_SIZE => 16,
+        _ => 0,
+    };
+    Some(header_size as usize + masks + colors * 4)
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
@@ -0,0 +1,278 @@
+use std::env;
+use std::ffi::c_void;
+use std::io;
+use std::mem;
+use std::os::windows::io::AsRawHandle;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::Security::Authorization::ConvertStringSidToSidW;
+use windows::Win32::Security::*;
+use windows::Win32::System::JobObjects::*;
+use windows::Win32::System::Memory::LocalFree;
+use windows::Win32::System::Threading::*;
+
+use crate::msgtable::{self, Block, Encoding, Entry};
+use crate::pe;
+use crate::table::{MessageTable, ResourceId};
+
+// The hidden subcommand the child is started with.
+pub const WORKER_COMMAND: &str = "sandbox-worker";
+
+// A parser that goes off the rails gets this much memory before it's killed.
+const WORKER_MEMORY_LIMIT: usize = 512 * 1024 * 1024;
+
+// Low mandatory level.
+const LOW_INTEGRITY_SID: PCWSTR = w!("S-1-16-4096");
+
+// Tables as they're sent from the worker back to the parent.
+#[derive(Serialize, Deserialize)]
+struct WireTable {
+    resource: WireResource,
+    lang: u16,
+    // low ID, high ID, offset to entries
+    blocks: Vec<[u32; 3]>,
+    entries: Vec<WireEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum WireResource {
+    Id(u16),
+    Name(String),
+}
+
+#[derive(Serialize, Deserialize)]
+struct WireEntry {
+    id: u32,
+    text: String,
+    // MESSAGE_RESOURCE_ENTRY flags
+    flags: u16,
+    offset: u32,
+    length: u16,
+    // Base64
+    raw: String,
+}
+
+impl WireTable {
+    fn new(table: MessageTable) -> WireTable {
+        WireTable {
+            resource: match table.resource {
+                ResourceId::Id(id) => WireResource::Id(id),
+                ResourceId::Name(name) => WireResource::Name(name),
+            },
+            lang: table.lang,
+            blocks: table
+                .blocks
+                .iter()
+                .map(|block| [block.low_id, block.high_id, block.offset_to_entries])
+                .collect(),
+            entries: table
+                .entries
+                .into_iter()
+                .map(|entry| WireEntry {
+                    id: entry.id,
+                    text: entry.text,
+                    flags: match entry.encoding {
+                        Encoding::Ansi => 0,
+                        Encoding::Unicode => 1,
+                        Encoding::Utf8 => 2,
+                    },
+                    offset: entry.offset,
+                    length: entry.length,
+                    raw: STANDARD.encode(&entry.raw),
+                })
+                .collect(),
+        }
+    }
+
+    fn into_table(self) -> anyhow::Result<MessageTable> {
+        let mut entries = Vec::new();
+        for entry in self.entries {
+            let encoding = match entry.flags {
+                0 => Encoding::Ansi,
+                1 => Encoding::Unicode,
+                2 => Encoding::Utf8,
+                flags => bail!("the sandboxed parser sent unknown flags {:#x}", flags),
+            };
+            entries.push(Entry {
+                id: entry.id,
+                text: entry.text,
+                encoding,
+                offset: entry.offset,
+                length: entry.length,
+                raw: STANDARD
+                    .decode(&entry.raw)
+                    .map_err(|e| anyhow!("the sandboxed parser sent invalid raw bytes: {}", e))?,
+            });
+        }
+        Ok(MessageTable {
+            resource: match self.resource {
+                WireResource::Id(id) => ResourceId::Id(id),
+                WireResource::Name(name) => ResourceId::Name(name),
+            },
+            lang: self.lang,
+            blocks: self
+                .blocks
+                .into_iter()
+                .map(|[low_id, high_id, offset_to_entries]| Block {
+                    low_id,
+                    high_id,
+                    offset_to_entries,
+                })
+                .collect(),
+            entries,
+        })
+    }
+}
+
+// Closed on drop. Closing a job kills every process in it.
+struct OwnedHandle(HANDLE);
+
+impl Drop for OwnedHandle {
+    fn drop(&mut self) {
+        unsafe { CloseHandle(self.0) };
+    }
+}
+
+fn create_job() -> anyhow::Result<OwnedHandle> {
+    let job = OwnedHandle(unsafe { CreateJobObjectW(None, PCWSTR::null()) }?);
+    let mut info = JOBOBJECT_EXTENDED_LIMIT_INFORMATION::default();
+    info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE
+        | JOB_OBJECT_LIMIT_ACTIVE_PROCESS
+        | JOB_OBJECT_LIMIT_PROCESS_MEMORY
+        | JOB_OBJECT_LIMIT_DIE_ON_UNHANDLED_EXCEPTION;
+    info.BasicLimitInformation.ActiveProcessLimit = 1;
+    info.ProcessMemoryLimit = WORKER_MEMORY_LIMIT;
+    unsafe {
+        SetInformationJobObject(
+            job.0,
+            JobObjectExtendedLimitInformation,
+            &info as *const _ as *const c_void,
+            mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+        )
+    }
+    .ok()?;
+    Ok(job)
+}
+
+// Parses path's message tables in a child process that runs at low integrity inside a job
+// that caps its memory and stops it from starting processes of its own. The child never
+// loads the module; it parses the file with the offline parser. A child still running after
+// timeout is killed.
+pub fn read_message_tables(
+    path: &Path,
+    all_langs: bool,
+    timeout: Option<Duration>,
+) -> anyhow::Result<Vec<MessageTable>> {
+    let mut command = Command::new(env::current_exe()?);
+    command.arg(WORKER_COMMAND).arg(path);
+    if all_langs {
+        command.arg("--all-langs");
+    }
+    let child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // NOTE: std can't start a child suspended, so the job is assigned just after it starts.
+    // The child lowers its own integrity before it opens the file, so it never reads
+    // untrusted data at the analyst's integrity level either way.
+    let job = create_job()?;
+    unsafe { AssignProcessToJobObject(job.0, HANDLE(child.as_raw_handle() as isize)) }.ok()?;
+    let timed_out = AtomicBool::new(false);
+    let output = thread::scope(|scope| {
+        let (done, finished) = mpsc::channel::<()>();
+        if let Some(timeout) = timeout {
+            let (job, timed_out) = (job.0, &timed_out);
+            scope.spawn(move || {
+                if let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(timeout) {
+                    timed_out.store(true, Ordering::Relaxed);
+                    unsafe { TerminateJobObject(job, 1) };
+                }
+            });
+        }
+        let output = child.wait_with_output();
+        // Stops the watchdog, which the scope then waits for.
+        drop(done);
+        output
+    })?;
+    drop(job);
+
+    if timed_out.load(Ordering::Relaxed) {
+        bail!(
+            "the sandboxed parser was killed after {} seconds",
+            timeout.unwrap_or_default().as_secs_f64()
+        );
+    }
+
+    if !output.status.success() {
+        bail!(
+            "the sandboxed parser failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let tables: Vec<WireTable> = serde_json::from_slice(&output.stdout)?;
+    tables.into_iter().map(WireTable::into_table).collect()
+}
+
+// The child's side of read_message_tables.
+pub fn run_worker(path: &Path, all_langs: bool) -> anyhow::Result<()> {
+    lower_integrity()?;
+    let mut tables = Vec::new();
+    for raw in pe::read_raw_message_tables(path, all_langs)? {
+        let parsed = msgtable::parse(&raw.data)?;
+        tables.push(WireTable::new(MessageTable {
+            resource: raw.resource,
+            lang: raw.lang,
+            blocks: parsed.blocks,
+            entries: parsed.entries,
+        }));
+    }
+    serde_json::to_writer(io::stdout().lock(), &tables)?;
+    Ok(())
+}
+
+fn lower_integrity() -> anyhow::Result<()> {
+    let mut token = HANDLE::default();
+    unsafe {
+        OpenProcessToken(
+            GetCurrentProcess(),
+            TOKEN_ADJUST_DEFAULT | TOKEN_QUERY,
+            &mut token,
+        )
+    }
+    .ok()?;
+    let token = OwnedHandle(token);
+
+    let mut sid = PSID::default();
+    unsafe { ConvertStringSidToSidW(LOW_INTEGRITY_SID, &mut sid) }.ok()?;
+    let label = TOKEN_MANDATORY_LABEL {
+        Label: SID_AND_ATTRIBUTES {
+            Sid: sid,
+            Attributes: SE_GROUP_INTEGRITY as u32,
+        },
+    };
+    let result = unsafe {
+        SetTokenInformation(
+            token.0,
+            TokenIntegrityLevel,
+            &label as *const _ as *const c_void,
+            mem::size_of::<TOKEN_MANDATORY_LABEL>() as u32 + GetLengthSid(sid),
+        )
+    };
+    unsafe { LocalFree(sid.0 as isize) };
+    result
+        .ok()
+        .map_err(|e| anyhow!("failed to lower the integrity level: {}", e))
+}
@@ -0,0 +1,369 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::os::windows::fs::OpenOptionsExt;
+use std::os::windows::io::AsRawHandle;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::{anyhow, Context};
+use rayon::prelude::*;
+use regex::{Regex, RegexBuilder};
+use windows::Win32::Foundation::HANDLE;
+use windows::Win32::Storage::FileSystem::*;
+use windows::Win32::System::LibraryLoader::*;
+use windows::Win32::System::Threading::*;
+
+use crate::hash;
+use crate::paths;
+use crate::policy::LoadPolicy;
+use crate::table::MessageTable;
+
+// Only files with these extensions are handed to the loader.
+const MODULE_EXTENSIONS: &[&str] = &["dll", "exe", "sys", "mui", "cpl", "ocx", "drv"];
+
+// Lets another thread, such as a GUI's, stop a scan. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.is_cancelled() {
+            return Err(Cancelled);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the scan was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+// Lets a host render its own progress for scan_modules. Callbacks arrive from the worker
+// threads, in no particular order.
+pub trait ScanProgress: Sync {
+    fn modules_discovered(&self, _count: usize) {}
+
+    // Called for every module, whether or not it could be read.
+    fn module_completed(&self, _path: &Path) {}
+
+    fn entries_parsed(&self, _count: usize) {}
+}
+
+// No progress reporting.
+impl ScanProgress for () {}
+
+// Scans in the background get this share of the CPUs.
+const BACKGROUND_CPU_DIVISOR: usize = 4;
+
+// Lowers the process's CPU, I/O and memory priority to background and limits scans to a
+// quarter of the CPUs, so a long scan doesn't get in the way of the workstation's user.
+// Must be called before the first scan, which fixes the size of rayon's thread pool.
+pub fn enter_background_mode() -> anyhow::Result<()> {
+    if !unsafe { SetPriorityClass(GetCurrentProcess(), PROCESS_MODE_BACKGROUND_BEGIN) }.as_bool() {
+        return Err(anyhow!(
+            "failed to enter background mode: {}",
+            windows::core::Error::from_win32().message()
+        ));
+    }
+    let cpus = thread::available_parallelism().map_or(1, |cpus| cpus.get());
+    rayon::ThreadPoolBuilder::new()
+        .num_threads((cpus / BACKGROUND_CPU_DIVISOR).max(1))
+        .build_global()
+        .context("failed to limit the scan's threads")?;
+    Ok(())
+}
+
+pub fn system32_dir() -> PathBuf {
+    let system_root = env::var("SystemRoot").unwrap_or_else(|_| "C:\\Windows".to_string());
+    Path::new(&system_root).join("System32")
+}
+
+// Where 64-bit Windows keeps the 32-bit copies of System32's modules.
+pub fn syswow64_dir() -> PathBuf {
+    let system_root = env::var("SystemRoot").unwrap_or_else(|_| "C:\\Windows".to_string());
+    Path::new(&system_root).join("SysWOW64")
+}
+
+// The SysWOW64 copy of a module in System32, if there is one. 32-bit processes resolve
+// messages from that copy, which isn't always identical.
+pub fn wow64_counterpart(path: &Path) -> Option<PathBuf> {
+    let system32 = system32_dir().to_string_lossy().to_lowercase();
+    let path = paths::strip_verbatim(path);
+    let path_str = path.to_string_lossy();
+    let relative = path_str
+        .get(..system32.len())
+        .filter(|head| head.to_lowercase() == system32)
+        .map(|_| &path_str[system32.len()..])?;
+    let counterpart = PathBuf::from(format!("{}{}", syswow64_dir().to_string_lossy(), relative));
+    counterpart.is_file().then_some(counterpart)
+}
+
+pub fn is_module_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| MODULE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+// A glob, such as net*.dll, where * matches any run of characters and ? any single one, or
+// with a re: prefix, a regex that must match somewhere in the name. Either way, case is
+// ignored, as Windows does for file names.
+#[derive(Debug, Clone)]
+pub struct NamePattern(Regex);
+
+impl NamePattern {
+    pub fn parse(s: &str) -> Result<NamePattern, String> {
+        let pattern = match s.strip_prefix("re:") {
+            Some(regex) => regex.to_string(),
+            None => {
+                let mut regex = String::from("^");
+                for c in s.chars() {
+                    match c {
+                        '*' => regex.push_str(".*"),
+                        '?' => regex.push('.'),
+                        c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+                    }
+                }
+                regex.push('$');
+                regex
+            }
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(true)
+            .build()
+            .map(NamePattern)
+            .map_err(|e| e.to_string())
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.0.is_match(name)
+    }
+}
+
+// Which modules a directory scan takes, by file name alone, so the others are never opened.
+// With no include patterns, every module not excluded is taken.
+#[derive(Debug, Clone, Default)]
+pub struct NameFilter {
+    include: Vec<NamePattern>,
+    exclude: Vec<NamePattern>,
+}
+
+impl NameFilter {
+    pub fn new(include: Vec<NamePattern>, exclude: Vec<NamePattern>) -> NameFilter {
+        NameFilter { include, exclude }
+    }
+
+    pub fn admits(&self, path: &Path) -> bool {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        (self.include.is_empty() || self.include.iter().any(|pattern| pattern.matches(&name)))
+            && !self.exclude.iter().any(|pattern| pattern.matches(&name))
+    }
+}
+
+// The modules directly inside dir that filter admits.
+pub fn module_files(dir: &Path, filter: &NameFilter) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for dir_entry in
+        fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?
+    {
+        let path = dir_entry?.path();
+        if is_module_file(&path) && filter.admits(&path) && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// module_files of dir and every directory below it that filter admits. Symbolic links and
+// junctions aren't followed, so a tree that links back into itself is still only walked
+// once.
+pub fn module_files_recursive(dir: &Path, filter: &NameFilter) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for dir_entry in
+            fs::read_dir(&dir).with_context(|| format!("failed to read {}", dir.display()))?
+        {
+            let dir_entry = dir_entry?;
+            let file_type = dir_entry.file_type()?;
+            let path = dir_entry.path();
+            if file_type.is_dir() {
+                dirs.push(path);
+            } else if file_type.is_file() && is_module_file(&path) && filter.admits(&path) {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// Identifies a file whatever path it's reached through, so hardlinks compare equal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId {
+    volume: u32,
+    index: u64,
+}
+
+// Opens path without asking for any access, so even files whose ACLs deny reading them can
+// be identified.
+pub fn file_id(path: &Path) -> io::Result<FileId> {
+    let file = fs::OpenOptions::new()
+        .access_mode(0)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS.0)
+        .open(path)?;
+    let mut info = BY_HANDLE_FILE_INFORMATION::default();
+    if !unsafe { GetFileInformationByHandle(HANDLE(file.as_raw_handle() as isize), &mut info) }
+        .as_bool()
+    {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(FileId {
+        volume: info.dwVolumeSerialNumber,
+        index: (u64::from(info.nFileIndexHigh) << 32) | u64::from(info.nFileIndexLow),
+    })
+}
+
+// Groups paths that hold the same payload: hardlinks of a single file, and copies whose
+// contents hash the same. Only files the same size as another are hashed. Each group lists
+// indices into paths in ascending order, so its first path is the one to read, and the
+// groups are ordered by it. Files that can't be identified are a group of their own.
+pub fn group_duplicates(paths: &[PathBuf]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut by_file: HashMap<FileId, usize> = HashMap::new();
+    let mut by_size: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (i, path) in paths.iter().enumerate() {
+        match file_id(path) {
+            Ok(id) => match by_file.get(&id) {
+                Some(&group) => groups[group].push(i),
+                None => {
+                    by_file.insert(id, groups.len());
+                    if let Ok(metadata) = fs::metadata(path) {
+                        by_size
+                            .entry(metadata.len())
+                            .or_default()
+                            .push(groups.len());
+                    }
+                    groups.push(vec![i]);
+                }
+            },
+            Err(_) => groups.push(vec![i]),
+        }
+    }
+
+    // Different files of the same size are told apart by hashing them.
+    let candidates: Vec<usize> = by_size
+        .into_values()
+        .filter(|same_size| same_size.len() > 1)
+        .flatten()
+        .collect();
+    let mut hashes: Vec<(usize, String)> = candidates
+        .par_iter()
+        .filter_map(|&group| {
+            let hash = hash::sha256_file(&paths[groups[group][0]]).ok()?;
+            Some((group, hash))
+        })
+        .collect();
+    hashes.sort_unstable();
+    let mut by_hash: HashMap<String, usize> = HashMap::new();
+    let mut merged_into: HashMap<usize, usize> = HashMap::new();
+    for (group, hash) in hashes {
+        match by_hash.get(&hash) {
+            Some(&first) => {
+                merged_into.insert(group, first);
+            }
+            None => {
+                by_hash.insert(hash, group);
+            }
+        }
+    }
+    let mut merged: Vec<Vec<usize>> = Vec::with_capacity(groups.len());
+    let mut positions: HashMap<usize, usize> = HashMap::new();
+    for (group, indices) in groups.into_iter().enumerate() {
+        match merged_into.get(&group) {
+            Some(first) => merged[positions[first]].extend(indices),
+            None => {
+                positions.insert(group, merged.len());
+                merged.push(indices);
+            }
+        }
+    }
+    for group in &mut merged {
+        group.sort_unstable();
+    }
+    merged
+}
+
+// Modules are loaded as data files, so scanning never runs code from them. Each payload is
+// only read once, however many hardlinks or copies of it paths names; the others get the
+// same result. Modules not yet started when cancel is signalled are skipped and the whole
+// scan fails with Cancelled.
+pub fn scan_modules(
+    paths: &[PathBuf],
+    policy: &LoadPolicy,
+    cancel: &CancellationToken,
+    progress: &dyn ScanProgress,
+) -> Result<Vec<anyhow::Result<Vec<MessageTable>>>, Cancelled> {
+    progress.modules_discovered(paths.len());
+    let groups = group_duplicates(paths);
+    let group_results: Vec<Result<_, Cancelled>> = groups
+        .par_iter()
+        .map(|group| {
+            cancel.check()?;
+            let result = policy.get_message_tables(
+                &paths[group[0]].to_string_lossy(),
+                LOAD_LIBRARY_AS_DATAFILE | LOAD_LIBRARY_AS_IMAGE_RESOURCE,
+                true,
+            );
+            if let Ok(tables) = &result {
+                progress.entries_parsed(tables.iter().map(|table| table.entries.len()).sum());
+            }
+            for &i in group {
+                progress.module_completed(&paths[i]);
+            }
+            Ok(result)
+        })
+        .collect();
+    cancel.check()?;
+
+    let mut results: Vec<Option<anyhow::Result<Vec<MessageTable>>>> =
+        paths.iter().map(|_| None).collect();
+    for (group, result) in groups.iter().zip(group_results) {
+        let result = result?;
+        for &i in &group[1..] {
+            results[i] = Some(match &result {
+                Ok(tables) => Ok(tables.clone()),
+                Err(e) => Err(anyhow!("{}", e)),
+            });
+        }
+        results[group[0]] = Some(result.map_err(anyhow::Error::from));
+    }
+    Ok(results.into_iter().flatten().collect())
+}
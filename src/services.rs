@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::Security::SC_HANDLE;
+use windows::Win32::System::LibraryLoader::*;
+use windows::Win32::System::Services::*;
+
+use crate::output::Printer;
+use crate::policy::LoadPolicy;
+
+pub struct Service {
+    pub name: String,
+    pub display_name: String,
+    // As stored in the SCM, before resolve_image_path.
+    pub image_path: String,
+}
+
+// Closes the handle on drop.
+struct ScHandle(SC_HANDLE);
+
+impl Drop for ScHandle {
+    fn drop(&mut self) {
+        unsafe { CloseServiceHandle(self.0) };
+    }
+}
+
+// Every installed service and driver, whatever its state.
+pub fn installed_services() -> anyhow::Result<Vec<Service>> {
+    let scm = ScHandle(unsafe {
+        OpenSCManagerW(PCWSTR::null(), PCWSTR::null(), SC_MANAGER_ENUMERATE_SERVICE)
+    }?);
+
+    let mut buf: Vec<u64> = Vec::new();
+    let mut bytes_needed = 0;
+    let mut count = 0;
+    loop {
+        let buf_bytes =
+            unsafe { std::slice::from_raw_parts_mut(buf.as_mut_ptr() as *mut u8, buf.len() * 8) };
+        let ok = unsafe {
+            EnumServicesStatusExW(
+                scm.0,
+                SC_ENUM_PROCESS_INFO,
+                SERVICE_WIN32 | SERVICE_DRIVER,
+                SERVICE_STATE_ALL,
+                Some(buf_bytes),
+                &mut bytes_needed,
+                &mut count,
+                None,
+                PCWSTR::null(),
+            )
+        };
+        if ok.as_bool() {
+            break;
+        }
+        if unsafe { GetLastError() } != ERROR_MORE_DATA {
+            return Err(anyhow!(
+                "failed to enumerate services: {}",
+                wp::last_error()
+            ));
+        }
+        buf.resize(buf.len() + (bytes_needed as usize + 7) / 8, 0);
+    }
+
+    let statuses = unsafe {
+        std::slice::from_raw_parts(
+            buf.as_ptr() as *const ENUM_SERVICE_STATUS_PROCESSW,
+            count as usize,
+        )
+    };
+    let mut services = Vec::new();
+    for status in statuses {
+        let name = unsafe { wp::utf16_to_utf8(status.lpServiceName.0) };
+        let image_path = match query_image_path(&scm, status.lpServiceName) {
+            Ok(image_path) => image_path,
+            Err(e) => {
+                eprintln!("WARNING: {}: {}", name, e);
+                continue;
+            }
+        };
+        services.push(Service {
+            display_name: unsafe { wp::utf16_to_utf8(status.lpDisplayName.0) },
+            name,
+            image_path,
+        });
+    }
+    Ok(services)
+}
+
+fn query_image_path(scm: &ScHandle, name: PWSTR) -> anyhow::Result<String> {
+    let service = ScHandle(unsafe { OpenServiceW(scm.0, PCWSTR(name.0), SERVICE_QUERY_CONFIG) }?);
+    let mut buf: Vec<u64> = Vec::new();
+    let mut bytes_needed = 0;
+    loop {
+        let ok = unsafe {
+            QueryServiceConfigW(
+                service.0,
+                Some(buf.as_mut_ptr() as *mut QUERY_SERVICE_CONFIGW),
+                (buf.len() * 8) as u32,
+                &mut bytes_needed,
+            )
+        };
+        if ok.as_bool() {
+            break;
+        }
+        if unsafe { GetLastError() } != ERROR_INSUFFICIENT_BUFFER {
+            return Err(anyhow!(
+                "failed to query the service configuration: {}",
+                wp::last_error()
+            ));
+        }
+        buf.resize((bytes_needed as usize + 7) / 8, 0);
+    }
+    let config = unsafe { &*(buf.as_ptr() as *const QUERY_SERVICE_CONFIGW) };
+    Ok(unsafe { wp::utf16_to_utf8(config.lpBinaryPathName.0) })
+}
+
+// ImagePath is a command line rather than a path: it may be quoted, carry arguments, use
+// environment variables, or be in one of the kernel's forms for drivers.
+pub fn resolve_image_path(image_path: &str) -> PathBuf {
+    let expanded = expand_env_vars(image_path.trim(), &|name| env::var(name).ok());
+    let path = match expanded.strip_prefix('"') {
+        Some(rest) => rest.split('"').next().unwrap_or(rest).to_string(),
+        None => strip_arguments(&expanded),
+    };
+
+    let system_root = env::var("SystemRoot").unwrap_or_else(|_| "C:\\Windows".to_string());
+    if let Some(rest) = path.strip_prefix("\\??\\") {
+        return PathBuf::from(rest);
+    }
+    if let Some(rest) = strip_prefix_ignore_case(&path, "\\SystemRoot\\") {
+        return Path::new(&system_root).join(rest);
+    }
+    // Drivers are often given relative to SystemRoot, e.g. System32\drivers\x.sys.
+    let path = PathBuf::from(path);
+    if path.is_relative() {
+        return Path::new(&system_root).join(path);
+    }
+    path
+}
+
+// For an unquoted path with arguments, the longest prefix ending before a space that names
+// an existing file is taken, like CreateProcess does; failing that, everything up to the
+// first space.
+fn strip_arguments(command_line: &str) -> String {
+    let ends = command_line
+        .match_indices(' ')
+        .map(|(index, _)| index)
+        .chain([command_line.len()]);
+    let candidates: Vec<&str> = ends.map(|end| &command_line[..end]).collect();
+    candidates
+        .iter()
+        .rev()
+        .find(|candidate| Path::new(candidate).is_file())
+        .or_else(|| candidates.first())
+        .map(|candidate| candidate.to_string())
+        .unwrap_or_default()
+}
+
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = s.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then_some(&s[prefix.len()..])
+}
+
+// Expands %NAME% references with the values lookup finds. Unknown variables are left as
+// they are, like cmd does.
+pub(crate) fn expand_env_vars(s: &str, lookup: &dyn Fn(&str) -> Option<String>) -> String {
+    let mut result = String::new();
+    let mut rest = s;
+    while let Some(start) = rest.find('%') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('%') {
+            Some(end) => {
+                let name = &after[..end];
+                match lookup(name) {
+                    Some(value) if !name.is_empty() => result.push_str(&value),
+                    _ => {
+                        result.push('%');
+                        result.push_str(name);
+                        result.push('%');
+                    }
+                }
+                rest = &after[end + 1..];
+            }
+            None => {
+                result.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+// Services that share a binary, such as the svchost-hosted ones, are grouped so each
+// binary is dumped once.
+pub fn dump_services(printer: &Printer, policy: &LoadPolicy) -> anyhow::Result<()> {
+    let mut binaries: BTreeMap<PathBuf, Vec<String>> = BTreeMap::new();
+    for service in installed_services()? {
+        let name = if service.display_name.is_empty() || service.display_name == service.name {
+            service.name
+        } else {
+            format!("{} ({})", service.name, service.display_name)
+        };
+        binaries
+            .entry(resolve_image_path(&service.image_path))
+            .or_default()
+            .push(name);
+    }
+
+    for (path, names) in &binaries {
+        let entries = match policy.get_message_table_entries(
+            &path.to_string_lossy(),
+            LOAD_LIBRARY_AS_DATAFILE | LOAD_LIBRARY_AS_IMAGE_RESOURCE,
+        ) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("WARNING: {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        if entries.is_empty() {
+            continue;
+        }
+        println!("{}:", path.display());
+        for name in names {
+            println!("  {}", name);
+        }
+        printer.print_entries(&entries, None)?;
+    }
+    Ok(())
+}
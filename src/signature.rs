@@ -0,0 +1,267 @@
+use std::ffi::c_void;
+use std::fmt;
+use std::fs::File;
+use std::mem;
+use std::os::windows::io::AsRawHandle;
+use std::path::Path;
+use std::ptr;
+
+use serde::Serialize;
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::Security::Cryptography::Catalog::*;
+use windows::Win32::Security::Cryptography::*;
+use windows::Win32::Security::WinTrust::*;
+use windows::Win32::System::Time::FileTimeToSystemTime;
+
+use crate::paths;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureStatus {
+    Signed,
+    Unsigned,
+    // Signed, but the signature doesn't verify: tampered with, revoked, untrusted root...
+    Invalid,
+}
+
+// Where the signature that covers the file lives. OS binaries are mostly signed through
+// a security catalog rather than an embedded signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureKind {
+    Embedded,
+    Catalog,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Signature {
+    pub status: SignatureStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<SignatureKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signer: Option<String>,
+    // When the signature was countersigned by a timestamping authority, in UTC.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<String>,
+    // Why verification failed, for invalid signatures.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.status {
+            SignatureStatus::Signed => write!(f, "signed")?,
+            SignatureStatus::Unsigned => return write!(f, "unsigned"),
+            SignatureStatus::Invalid => write!(f, "INVALID signature")?,
+        }
+        if let Some(signer) = &self.signer {
+            write!(f, " by {}", signer)?;
+        }
+        match self.kind {
+            Some(SignatureKind::Embedded) => write!(f, " (embedded)")?,
+            Some(SignatureKind::Catalog) => write!(f, " (catalog)")?,
+            None => {}
+        }
+        if let Some(timestamp) = &self.timestamp {
+            write!(f, ", timestamped {}", timestamp)?;
+        }
+        if let Some(error) = &self.error {
+            write!(f, ": {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+// Checks the file's embedded Authenticode signature and, failing that, whether a catalog
+// installed on this machine vouches for it.
+pub fn verify(path: &Path) -> anyhow::Result<Signature> {
+    let path_utf16 = wp::utf8_to_utf16(&paths::extended_length(&path.to_string_lossy()));
+    let mut file_info = WINTRUST_FILE_INFO {
+        cbStruct: mem::size_of::<WINTRUST_FILE_INFO>() as u32,
+        pcwszFilePath: PCWSTR(path_utf16.as_ptr()),
+        ..Default::default()
+    };
+    let mut data = trust_data(WTD_CHOICE_FILE);
+    data.Anonymous.pFile = &mut file_info;
+    let signature = verify_trust(&mut data, SignatureKind::Embedded);
+    if signature.status != SignatureStatus::Unsigned {
+        return Ok(signature);
+    }
+    Ok(verify_catalog(path)?.unwrap_or(signature))
+}
+
+fn trust_data(choice: WINTRUST_DATA_UNION_CHOICE) -> WINTRUST_DATA {
+    WINTRUST_DATA {
+        cbStruct: mem::size_of::<WINTRUST_DATA>() as u32,
+        dwUIChoice: WTD_UI_NONE,
+        // Revocation checks would go to the network for every module.
+        fdwRevocationChecks: WTD_REVOKE_NONE,
+        dwUnionChoice: choice,
+        dwStateAction: WTD_STATEACTION_VERIFY,
+        dwProvFlags: WTD_CACHE_ONLY_URL_RETRIEVAL,
+        ..Default::default()
+    }
+}
+
+// Runs WinVerifyTrust and, if there's a signature, reads its signer and timestamp from the
+// state data before closing it.
+fn verify_trust(data: &mut WINTRUST_DATA, kind: SignatureKind) -> Signature {
+    let mut action = WINTRUST_ACTION_GENERIC_VERIFY_V2;
+    let result = unsafe {
+        WinVerifyTrust(
+            HWND::default(),
+            &mut action,
+            data as *mut WINTRUST_DATA as *mut c_void,
+        )
+    };
+    let hr = HRESULT(result);
+    let mut signature = Signature {
+        status: if hr.is_ok() {
+            SignatureStatus::Signed
+        } else if hr == TRUST_E_NOSIGNATURE
+            || hr == TRUST_E_SUBJECT_FORM_UNKNOWN
+            || hr == TRUST_E_PROVIDER_UNKNOWN
+        {
+            SignatureStatus::Unsigned
+        } else {
+            SignatureStatus::Invalid
+        },
+        kind: None,
+        signer: None,
+        timestamp: None,
+        error: None,
+    };
+    if signature.status != SignatureStatus::Unsigned {
+        signature.kind = Some(kind);
+        read_signer(data.hWVTStateData, &mut signature);
+    }
+    if signature.status == SignatureStatus::Invalid {
+        signature.error = Some(hr.message().to_string().trim_end().to_string());
+    }
+
+    data.dwStateAction = WTD_STATEACTION_CLOSE;
+    unsafe {
+        WinVerifyTrust(
+            HWND::default(),
+            &mut action,
+            data as *mut WINTRUST_DATA as *mut c_void,
+        )
+    };
+    signature
+}
+
+fn read_signer(state: HANDLE, signature: &mut Signature) {
+    unsafe {
+        let provider_data = WTHelperProvDataFromStateData(state);
+        if provider_data.is_null() {
+            return;
+        }
+        let signer = WTHelperGetProvSignerFromChain(provider_data, 0, false, 0);
+        if signer.is_null() {
+            return;
+        }
+        let cert = WTHelperGetProvCertFromChain(signer, 0);
+        if !cert.is_null() && !(*cert).pCert.is_null() {
+            let mut name = [0u16; 256];
+            let len = CertGetNameStringW(
+                (*cert).pCert,
+                CERT_NAME_SIMPLE_DISPLAY_TYPE,
+                0,
+                None,
+                Some(&mut name),
+            );
+            // The length includes the terminating NUL.
+            if len > 1 {
+                signature.signer = Some(String::from_utf16_lossy(&name[..len as usize - 1]));
+            }
+        }
+        // sftVerifyAsOf is the countersignature's time when there is one, and the current
+        // time otherwise.
+        if (*signer).csCounterSigners > 0 {
+            signature.timestamp = format_filetime(&(*signer).sftVerifyAsOf);
+        }
+    }
+}
+
+pub(crate) fn format_filetime(filetime: &FILETIME) -> Option<String> {
+    let mut time = SYSTEMTIME::default();
+    if !unsafe { FileTimeToSystemTime(filetime, &mut time) }.as_bool() {
+        return None;
+    }
+    Some(format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        time.wYear, time.wMonth, time.wDay, time.wHour, time.wMinute, time.wSecond
+    ))
+}
+
+// None if no installed catalog lists the file's hash.
+fn verify_catalog(path: &Path) -> anyhow::Result<Option<Signature>> {
+    let file = File::open(path)?;
+    let file_handle = HANDLE(file.as_raw_handle() as isize);
+
+    let mut cat_admin = 0;
+    unsafe { CryptCATAdminAcquireContext2(&mut cat_admin, None, w!("SHA256"), None, 0) }.ok()?;
+
+    let mut hash_len = 0;
+    let mut hash = Vec::new();
+    // The first call only fails with the hash's size.
+    unsafe { CryptCATAdminCalcHashFromFileHandle2(cat_admin, file_handle, &mut hash_len, None, 0) };
+    hash.resize(hash_len as usize, 0);
+    let hashed = hash_len > 0
+        && unsafe {
+            CryptCATAdminCalcHashFromFileHandle2(
+                cat_admin,
+                file_handle,
+                &mut hash_len,
+                Some(hash.as_mut_ptr()),
+                0,
+            )
+        }
+        .as_bool();
+    let cat_info = if hashed {
+        unsafe { CryptCATAdminEnumCatalogFromHash(cat_admin, &hash, 0, None) }
+    } else {
+        0
+    };
+    if cat_info == 0 {
+        unsafe { CryptCATAdminReleaseContext(cat_admin, 0) };
+        return Ok(None);
+    }
+
+    let mut catalog = CATALOG_INFO {
+        cbStruct: mem::size_of::<CATALOG_INFO>() as u32,
+        ..Default::default()
+    };
+    let signature =
+        if unsafe { CryptCATCatalogInfoFromContext(cat_info, &mut catalog, 0) }.as_bool() {
+            // Catalogs list members by the uppercase hex of their hash.
+            let tag: String = hash.iter().map(|byte| format!("{:02X}", byte)).collect();
+            let tag_utf16 = wp::utf8_to_utf16(&tag);
+            let path_utf16 = wp::utf8_to_utf16(&paths::extended_length(&path.to_string_lossy()));
+            let mut catalog_info = WINTRUST_CATALOG_INFO {
+                cbStruct: mem::size_of::<WINTRUST_CATALOG_INFO>() as u32,
+                pcwszCatalogFilePath: PCWSTR(catalog.wszCatalogFile.as_ptr()),
+                pcwszMemberTag: PCWSTR(tag_utf16.as_ptr()),
+                pcwszMemberFilePath: PCWSTR(path_utf16.as_ptr()),
+                hMemberFile: file_handle,
+                pbCalculatedFileHash: hash.as_mut_ptr(),
+                cbCalculatedFileHash: hash.len() as u32,
+                pcCatalogContext: ptr::null_mut(),
+                hCatAdmin: cat_admin,
+                ..Default::default()
+            };
+            let mut data = trust_data(WTD_CHOICE_CATALOG);
+            data.Anonymous.pCatalog = &mut catalog_info;
+            Some(verify_trust(&mut data, SignatureKind::Catalog))
+        } else {
+            None
+        };
+
+    unsafe {
+        CryptCATAdminReleaseCatalogContext(cat_admin, cat_info, 0);
+        CryptCATAdminReleaseContext(cat_admin, 0);
+    }
+    Ok(signature)
+}
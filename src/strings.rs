@@ -0,0 +1,118 @@
+use serde::Serialize;
+
+use crate::provider::ResourceProvider;
+use crate::resource::{self, MenuItem};
+use crate::table::{MessageTable, ResourceId, Result};
+
+// The kind of resource a string came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Source {
+    MessageTable,
+    StringTable,
+    DialogCaption,
+    DialogControl,
+    Menu,
+}
+
+// A single user-visible string of a module, in a single language.
+#[derive(Debug, Clone, Serialize)]
+pub struct CatalogString {
+    pub source: Source,
+    // The resource the string is in, as printed by ResourceId's Display.
+    pub resource: String,
+    // The message, string, control or menu item ID. Captions, popups and controls with
+    // no ID of their own have none.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+    pub lang: u16,
+    pub text: String,
+}
+
+// Every user-visible string of a module, for translation-memory tools.
+#[derive(Debug, Serialize)]
+pub struct ModuleStrings {
+    pub module: String,
+    pub strings: Vec<CatalogString>,
+}
+
+// The entries of tables, followed by every string table, dialog and menu of provider. Empty
+// strings and separators are left out.
+pub fn all_strings(
+    tables: &[MessageTable],
+    provider: &dyn ResourceProvider,
+) -> Result<Vec<CatalogString>> {
+    let mut strings = Vec::new();
+    for table in tables {
+        for entry in &table.entries {
+            strings.push(CatalogString {
+                source: Source::MessageTable,
+                resource: table.resource.to_string(),
+                id: Some(entry.id),
+                lang: table.lang,
+                text: entry.text.clone(),
+            });
+        }
+    }
+    for entry in resource::string_tables(provider)? {
+        strings.push(CatalogString {
+            source: Source::StringTable,
+            // Block n holds IDs (n - 1) * 16 to (n - 1) * 16 + 15.
+            resource: (entry.id / 16 + 1).to_string(),
+            id: Some(entry.id as u32),
+            lang: entry.lang,
+            text: entry.text,
+        });
+    }
+    for dialog in resource::dialogs(provider)? {
+        if !dialog.caption.is_empty() {
+            strings.push(CatalogString {
+                source: Source::DialogCaption,
+                resource: dialog.name.to_string(),
+                id: None,
+                lang: dialog.lang,
+                text: dialog.caption.clone(),
+            });
+        }
+        for control in &dialog.controls {
+            // Ordinal text names a resource, such as an icon, rather than being text.
+            if let Some(ResourceId::Name(text)) = &control.text {
+                if text.is_empty() {
+                    continue;
+                }
+                strings.push(CatalogString {
+                    source: Source::DialogControl,
+                    resource: dialog.name.to_string(),
+                    // Statics usually share IDC_STATIC, -1, whichever size it's stored in.
+                    id: u32::try_from(control.id).ok().filter(|&id| id != 0xffff),
+                    lang: dialog.lang,
+                    text: text.clone(),
+                });
+            }
+        }
+    }
+    for menu in resource::menus(provider)? {
+        add_menu_items(&mut strings, &menu.name, menu.lang, &menu.items);
+    }
+    Ok(strings)
+}
+
+fn add_menu_items(
+    strings: &mut Vec<CatalogString>,
+    name: &ResourceId,
+    lang: u16,
+    items: &[MenuItem],
+) {
+    for item in items {
+        if !item.separator && !item.text.is_empty() {
+            strings.push(CatalogString {
+                source: Source::Menu,
+                resource: name.to_string(),
+                id: (!item.popup).then_some(item.id),
+                lang,
+                text: item.text.clone(),
+            });
+        }
+        add_menu_items(strings, name, lang, &item.children);
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context};
+use object::Object;
+use pdb::{FallibleIterator, SymbolData, TypeData, Variant};
+
+use crate::paths;
+
+// Symbolic names recovered from a module's PDB, keyed by value.
+pub struct Symbols {
+    names: HashMap<u32, Vec<String>>,
+}
+
+impl Symbols {
+    pub fn load(pdb_path: &Path) -> anyhow::Result<Symbols> {
+        let file = File::open(pdb_path)
+            .with_context(|| format!("failed to open {}", pdb_path.display()))?;
+        let mut pdb = pdb::PDB::open(file)
+            .with_context(|| format!("failed to parse {}", pdb_path.display()))?;
+
+        let mut names: HashMap<u32, Vec<String>> = HashMap::new();
+        let mut add = |value: Variant, name: String| {
+            if let Some(value) = variant_to_u32(value) {
+                if is_symbolic_name(&name) {
+                    names.entry(value).or_default().push(name);
+                }
+            }
+        };
+
+        // Statics show up as constants, enums as enumerates inside field lists.
+        let global_symbols = pdb.global_symbols()?;
+        let mut symbols = global_symbols.iter();
+        while let Some(symbol) = symbols.next()? {
+            if let Ok(SymbolData::Constant(constant)) = symbol.parse() {
+                add(constant.value, constant.name.to_string().into_owned());
+            }
+        }
+
+        let type_information = pdb.type_information()?;
+        let mut types = type_information.iter();
+        while let Some(typ) = types.next()? {
+            if let Ok(TypeData::FieldList(field_list)) = typ.parse() {
+                for field in field_list.fields {
+                    if let TypeData::Enumerate(enumerate) = field {
+                        add(enumerate.value, enumerate.name.to_string().into_owned());
+                    }
+                }
+            }
+        }
+
+        for candidates in names.values_mut() {
+            candidates.sort();
+            candidates.dedup();
+        }
+        Ok(Symbols { names })
+    }
+
+    pub fn lookup(&self, id: u32) -> Option<&str> {
+        let candidates = self.names.get(&id)?;
+        candidates
+            .iter()
+            .find(|name| name.starts_with("MSG_"))
+            .or_else(|| candidates.first())
+            .map(|name| name.as_str())
+    }
+}
+
+fn variant_to_u32(value: Variant) -> Option<u32> {
+    match value {
+        Variant::U8(value) => Some(value.into()),
+        Variant::U16(value) => Some(value.into()),
+        Variant::U32(value) => Some(value),
+        Variant::U64(value) => u32::try_from(value).ok(),
+        Variant::I8(value) => Some(value as u32),
+        Variant::I16(value) => Some(value as u32),
+        Variant::I32(value) => Some(value as u32),
+        Variant::I64(value) => i32::try_from(value).ok().map(|value| value as u32),
+    }
+}
+
+// Symbolic names in .mc files are conventionally SHOUTING_SNAKE_CASE, which filters out
+// most of the unrelated constants that happen to share a value with a message ID.
+fn is_symbolic_name(name: &str) -> bool {
+    name.contains('_')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+// Finds the PDB matching the module's CodeView record in the symbol stores listed in
+// _NT_SYMBOL_PATH. Symbols are not downloaded; they must already be in a local store.
+pub fn locate_pdb(module_path: &Path) -> anyhow::Result<PathBuf> {
+    let data = paths::read_file(module_path)
+        .with_context(|| format!("failed to read {}", module_path.display()))?;
+    let file = object::File::parse(&*data)
+        .with_context(|| format!("failed to parse {}", module_path.display()))?;
+    let code_view = file
+        .pdb_info()?
+        .ok_or_else(|| anyhow!("{} has no CodeView record", module_path.display()))?;
+
+    let pdb_path = String::from_utf8_lossy(code_view.path()).into_owned();
+    let pdb_name = pdb_path
+        .rsplit(|c: char| c == '\\' || c == '/')
+        .next()
+        .unwrap_or(&pdb_path)
+        .to_string();
+    let guid = code_view.guid();
+    let signature = format!(
+        "{:08X}{:04X}{:04X}{}{:X}",
+        u32::from_le_bytes([guid[0], guid[1], guid[2], guid[3]]),
+        u16::from_le_bytes([guid[4], guid[5]]),
+        u16::from_le_bytes([guid[6], guid[7]]),
+        guid[8..]
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect::<String>(),
+        code_view.age()
+    );
+
+    let mut candidates = vec![PathBuf::from(&pdb_path)];
+    if let Some(module_dir) = module_path.parent() {
+        candidates.push(module_dir.join(&pdb_name));
+    }
+    for store in symbol_stores() {
+        candidates.push(store.join(&pdb_name).join(&signature).join(&pdb_name));
+        candidates.push(store.join(&pdb_name));
+    }
+    candidates
+        .into_iter()
+        .find(|candidate| candidate.is_file())
+        .ok_or_else(|| {
+            anyhow!(
+                "failed to find {} ({}) in any symbol store",
+                pdb_name,
+                signature
+            )
+        })
+}
+
+fn symbol_stores() -> Vec<PathBuf> {
+    let symbol_path = match env::var("_NT_SYMBOL_PATH") {
+        Ok(symbol_path) => symbol_path,
+        Err(_) => return Vec::new(),
+    };
+
+    // Elements look like srv*<cache>*<server>, cache*<dir>, or a plain directory.
+    let mut stores = Vec::new();
+    for element in symbol_path.split(';') {
+        for part in element.split('*') {
+            let is_keyword = part.eq_ignore_ascii_case("srv")
+                || part.eq_ignore_ascii_case("symsrv")
+                || part.eq_ignore_ascii_case("cache");
+            if part.is_empty() || is_keyword || part.contains("://") || part.ends_with(".dll") {
+                continue;
+            }
+            stores.push(PathBuf::from(part));
+        }
+    }
+    stores
+}
@@ -0,0 +1,382 @@
+use std::ffi::c_void;
+use std::mem;
+use std::path::Path;
+
+use windows::core::*;
+use windows::Win32::Foundation::*;
+use windows::Win32::System::Diagnostics::Debug::*;
+use windows::Win32::System::LibraryLoader::*;
+
+use crate::message::MessageEntry;
+use crate::msgtable;
+use crate::paths;
+use crate::provider::{self, ResourceProvider, ResourceRef};
+use crate::resource::{
+    self, AcceleratorTable, Dialog, ExtractedFile, Menu, StringEntry, VersionInfo,
+};
+use crate::table::{Error, MessageTable, RawMessageTable, ResourceId, Result};
+
+// Conversions to and from the names the resource APIs take.
+impl ResourceId {
+    unsafe fn from_pcwstr(name: PCWSTR) -> ResourceId {
+        // IS_INTRESOURCE
+        if (name.0 as usize) >> 16 == 0 {
+            ResourceId::Id(name.0 as usize as u16)
+        } else {
+            ResourceId::Name(wp::utf16_to_utf8(name.0))
+        }
+    }
+
+    // NOTE: For string names the returned pointer borrows name_utf16.
+    fn to_pcwstr(&self, name_utf16: &mut Vec<u16>) -> PCWSTR {
+        match self {
+            ResourceId::Id(id) => PCWSTR(*id as usize as *const u16),
+            ResourceId::Name(name) => {
+                *name_utf16 = wp::utf8_to_utf16(name);
+                PCWSTR(name_utf16.as_ptr())
+            }
+        }
+    }
+}
+
+unsafe extern "system" fn enum_res_types(_module: HINSTANCE, typ: PWSTR, param: isize) -> BOOL {
+    let types = mem::transmute::<isize, &mut Vec<ResourceId>>(param);
+    types.push(ResourceId::from_pcwstr(PCWSTR(typ.0)));
+    true.into()
+}
+
+unsafe extern "system" fn enum_res_names(
+    _module: HINSTANCE,
+    _typ: PCWSTR,
+    name: PCWSTR,
+    param: isize,
+) -> BOOL {
+    let names = mem::transmute::<isize, &mut Vec<ResourceId>>(param);
+    // NOTE: String names are only valid for the duration of the callback.
+    names.push(ResourceId::from_pcwstr(name));
+    true.into()
+}
+
+unsafe extern "system" fn enum_res_langs(
+    _module: HINSTANCE,
+    _typ: PCWSTR,
+    _name: PCWSTR,
+    lang: u16,
+    param: isize,
+) -> BOOL {
+    let langs = mem::transmute::<isize, &mut Vec<u16>>(param);
+    langs.push(lang);
+    true.into()
+}
+
+fn load_module(mod_name: &str, flags: LOAD_LIBRARY_FLAGS) -> Result<HINSTANCE> {
+    let mod_name_utf16 = wp::utf8_to_utf16(&paths::extended_length(mod_name));
+    let res = unsafe {
+        LoadLibraryExW(
+            PCWSTR::from_raw(mod_name_utf16.as_ptr()),
+            HANDLE::default(),
+            flags,
+        )
+    };
+    res.map_err(|e| {
+        Error::from_win_error("failed to load the module", wp::Error::from_win_error(e))
+    })
+}
+
+pub fn get_message_table_entries(
+    mod_name: &str,
+    flags: LOAD_LIBRARY_FLAGS,
+) -> Result<Vec<MessageEntry>> {
+    let tables = get_message_tables(mod_name, flags, false)?;
+    Ok(tables
+        .into_iter()
+        .flat_map(MessageTable::into_message_entries)
+        .collect())
+}
+
+// Without all_langs, only the language the loader would pick is read for each resource.
+pub fn get_message_tables(
+    mod_name: &str,
+    flags: LOAD_LIBRARY_FLAGS,
+    all_langs: bool,
+) -> Result<Vec<MessageTable>> {
+    Module::load(mod_name, flags)?.message_tables(all_langs)
+}
+
+pub fn get_raw_message_tables(
+    mod_name: &str,
+    flags: LOAD_LIBRARY_FLAGS,
+    all_langs: bool,
+) -> Result<Vec<RawMessageTable>> {
+    Module::load(mod_name, flags)?.raw_message_tables(all_langs)
+}
+
+// Every resource of the module, with owned IDs, so the refs outlive the enumeration.
+pub fn enumerate_resources(module: &Module) -> Result<impl Iterator<Item = ResourceRef>> {
+    Ok(provider::enumerate_resources(module)?.into_iter())
+}
+
+// A module loaded with LoadLibraryExW, freed on drop. Resources are found the way the
+// loader finds them, so the default language honors the user's UI language.
+pub struct Module {
+    module: HINSTANCE,
+    // False for handles borrowed with from_handle, which the caller frees.
+    owned: bool,
+}
+
+impl Module {
+    // Loads the module as a data file, so no code in it runs.
+    pub fn open(path: impl AsRef<Path>) -> Result<Module> {
+        Module::load(
+            &path.as_ref().to_string_lossy(),
+            LOAD_LIBRARY_AS_DATAFILE | LOAD_LIBRARY_AS_IMAGE_RESOURCE,
+        )
+    }
+
+    pub fn load(mod_name: &str, flags: LOAD_LIBRARY_FLAGS) -> Result<Module> {
+        Ok(Module {
+            module: load_module(mod_name, flags)?,
+            owned: true,
+        })
+    }
+
+    // Reads resources from a module the caller already has loaded, such as its own host
+    // executable, without loading it again. The handle is not freed on drop.
+    //
+    // Safety: module must be a valid module handle and stay loaded for as long as the
+    // returned Module is used.
+    pub unsafe fn from_handle(module: HMODULE) -> Module {
+        Module {
+            module: HINSTANCE(module.0),
+            owned: false,
+        }
+    }
+
+    // Without all_langs, only the language the loader would pick is read for each resource.
+    pub fn message_tables(&self, all_langs: bool) -> Result<Vec<MessageTable>> {
+        let mut results = Vec::new();
+        for raw in self.raw_message_tables(all_langs)? {
+            let parsed = msgtable::parse(&raw.data)?;
+            results.push(MessageTable {
+                resource: raw.resource,
+                lang: raw.lang,
+                blocks: parsed.blocks,
+                entries: parsed.entries,
+            });
+        }
+        Ok(results)
+    }
+
+    pub fn raw_message_tables(&self, all_langs: bool) -> Result<Vec<RawMessageTable>> {
+        provider::read_raw_message_tables(self, all_langs)
+    }
+
+    pub fn string_tables(&self) -> Result<Vec<StringEntry>> {
+        resource::string_tables(self)
+    }
+
+    pub fn version_info(&self) -> Result<Option<VersionInfo>> {
+        resource::version_info(self)
+    }
+
+    pub fn accelerator_tables(&self) -> Result<Vec<AcceleratorTable>> {
+        resource::accelerator_tables(self)
+    }
+
+    pub fn dialogs(&self) -> Result<Vec<Dialog>> {
+        resource::dialogs(self)
+    }
+
+    pub fn menus(&self) -> Result<Vec<Menu>> {
+        resource::menus(self)
+    }
+
+    pub fn extract_files(&self) -> Result<Vec<ExtractedFile>> {
+        resource::extract_files(self)
+    }
+
+    // The message as FormatMessageW renders it, with inserts left as they are.
+    pub fn format_message(&self, id: u32, lang: u16) -> Result<String> {
+        // 64K characters is the most FormatMessageW returns.
+        let mut buf = vec![0u16; 0x10000];
+        let len = unsafe {
+            FormatMessageW(
+                FORMAT_MESSAGE_FROM_HMODULE | FORMAT_MESSAGE_IGNORE_INSERTS,
+                Some(self.module.0 as *const c_void),
+                id,
+                lang as u32,
+                PWSTR(buf.as_mut_ptr()),
+                buf.len() as u32,
+                None,
+            )
+        };
+        if len == 0 {
+            return Err(Error::from_win_error(
+                "failed to format the message",
+                wp::last_error(),
+            ));
+        }
+        Ok(String::from_utf16_lossy(&buf[..len as usize]))
+    }
+
+    pub fn resources(&self) -> Result<Vec<ResourceRef>> {
+        provider::enumerate_resources(self)
+    }
+
+    fn find(&self, typ: &ResourceId, name: &ResourceId, lang: u16) -> Result<HRSRC> {
+        let mut type_utf16 = Vec::new();
+        let mut name_utf16 = Vec::new();
+        let resource = unsafe {
+            FindResourceExW(
+                self.module,
+                typ.to_pcwstr(&mut type_utf16),
+                name.to_pcwstr(&mut name_utf16),
+                lang,
+            )
+        };
+        if resource.is_invalid() {
+            return Err(Error::from_win_error(
+                "failed to find the resource",
+                wp::last_error(),
+            ));
+        }
+        Ok(resource)
+    }
+}
+
+impl Drop for Module {
+    fn drop(&mut self) {
+        if self.owned {
+            unsafe { FreeLibrary(self.module) };
+        }
+    }
+}
+
+impl ResourceProvider for Module {
+    fn types(&self) -> Result<Vec<ResourceId>> {
+        let mut types: Vec<ResourceId> = Vec::new();
+        let param = unsafe { mem::transmute::<&mut Vec<ResourceId>, isize>(&mut types) };
+        if !unsafe { EnumResourceTypesW(self.module, Some(enum_res_types), param) }.as_bool() {
+            // Modules without any resources are not an error.
+            if unsafe { GetLastError() } == ERROR_RESOURCE_DATA_NOT_FOUND {
+                return Ok(Vec::new());
+            }
+            return Err(Error::from_win_error(
+                "failed to enumerate resource types",
+                wp::last_error(),
+            ));
+        }
+        Ok(types)
+    }
+
+    fn names(&self, typ: &ResourceId) -> Result<Vec<ResourceId>> {
+        let mut names: Vec<ResourceId> = Vec::new();
+        let param = unsafe { mem::transmute::<&mut Vec<ResourceId>, isize>(&mut names) };
+        let mut type_utf16 = Vec::new();
+        if !unsafe {
+            EnumResourceNamesW(
+                self.module,
+                typ.to_pcwstr(&mut type_utf16),
+                Some(enum_res_names),
+                param,
+            )
+        }
+        .as_bool()
+        {
+            // A type the module doesn't have is not an error.
+            if unsafe { GetLastError() } == ERROR_RESOURCE_TYPE_NOT_FOUND {
+                return Ok(Vec::new());
+            }
+            return Err(Error::from_win_error(
+                "failed to enumerate resource names",
+                wp::last_error(),
+            ));
+        }
+        Ok(names)
+    }
+
+    fn langs(&self, typ: &ResourceId, name: &ResourceId) -> Result<Vec<u16>> {
+        let mut langs: Vec<u16> = Vec::new();
+        let param = unsafe { mem::transmute::<&mut Vec<u16>, isize>(&mut langs) };
+        let mut type_utf16 = Vec::new();
+        let mut name_utf16 = Vec::new();
+        if !unsafe {
+            EnumResourceLanguagesW(
+                self.module,
+                typ.to_pcwstr(&mut type_utf16),
+                name.to_pcwstr(&mut name_utf16),
+                Some(enum_res_langs),
+                param,
+            )
+        }
+        .as_bool()
+        {
+            return Err(Error::from_win_error(
+                "failed to enumerate resource languages",
+                wp::last_error(),
+            ));
+        }
+        Ok(langs)
+    }
+
+    // The resource data is copied so that it outlives the module.
+    fn data(&self, typ: &ResourceId, name: &ResourceId, lang: u16) -> Result<Vec<u8>> {
+        let resource = self.find(typ, name, lang)?;
+        Ok(read_resource(self, resource)?.to_vec())
+    }
+
+    // The loader's pick is identified by comparing resource handles.
+    fn default_lang(&self, typ: &ResourceId, name: &ResourceId) -> Result<Option<u16>> {
+        let mut type_utf16 = Vec::new();
+        let mut name_utf16 = Vec::new();
+        let default_resource = unsafe {
+            FindResourceW(
+                self.module,
+                name.to_pcwstr(&mut name_utf16),
+                typ.to_pcwstr(&mut type_utf16),
+            )
+        };
+        if default_resource.is_invalid() {
+            return Err(Error::from_win_error(
+                "failed to find the resource",
+                wp::last_error(),
+            ));
+        }
+        for lang in self.langs(typ, name)? {
+            if self.find(typ, name, lang)? == default_resource {
+                return Ok(Some(lang));
+            }
+        }
+        Ok(None)
+    }
+}
+
+// The returned slice borrows the module, since it's only valid while the module stays
+// loaded.
+fn read_resource<'a>(module: &'a Module, resource: HRSRC) -> Result<&'a [u8]> {
+    let res_data = unsafe { LoadResource(module.module, resource) };
+    if res_data == 0 {
+        return Err(Error::from_win_error(
+            "failed to load the resource",
+            wp::last_error(),
+        ));
+    }
+
+    let res_mem = unsafe { LockResource(res_data) };
+    if res_mem.is_null() {
+        return Err(Error::from_win_error(
+            "failed to lock the resource",
+            wp::last_error(),
+        ));
+    }
+
+    let res_size = unsafe { SizeofResource(module.module, resource) };
+    if res_size == 0 {
+        return Err(Error::from_win_error(
+            "failed to get the size of the resource",
+            wp::last_error(),
+        ));
+    }
+
+    Ok(unsafe { std::slice::from_raw_parts(res_mem as *const u8, res_size as usize) })
+}
@@ -0,0 +1,122 @@
+use std::fmt;
+
+use crate::message::MessageEntry;
+use crate::msgtable::{self, Block, Decoding, Entry, ParseError};
+
+#[derive(Debug)]
+pub struct Error {
+    err_msg: String,
+    // None when the failure didn't come from a Win32 call, e.g. when a resource was read
+    // successfully but could not be parsed.
+    #[cfg(feature = "live")]
+    win_err: Option<wp::Error>,
+}
+
+impl Error {
+    pub fn new(err_msg: impl Into<String>) -> Error {
+        Error {
+            err_msg: err_msg.into(),
+            #[cfg(feature = "live")]
+            win_err: None,
+        }
+    }
+
+    #[cfg(feature = "live")]
+    pub fn from_win_error(err_msg: impl Into<String>, win_err: wp::Error) -> Error {
+        Error {
+            err_msg: err_msg.into(),
+            win_err: Some(win_err),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        #[cfg(feature = "live")]
+        if let Some(win_err) = &self.win_err {
+            return write!(
+                f,
+                "failed to get message table entries: {}: {}",
+                self.err_msg, win_err
+            );
+        }
+        write!(f, "failed to get message table entries: {}", self.err_msg)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Error {
+        Error::new(e.to_string())
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+// Resources are named either by an integer or by a string.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ResourceId {
+    Id(u16),
+    Name(String),
+}
+
+impl fmt::Display for ResourceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceId::Id(id) => write!(f, "{}", id),
+            ResourceId::Name(name) => write!(f, "\"{}\"", name),
+        }
+    }
+}
+
+// The undecoded bytes of a single language of a single MESSAGETABLE resource.
+pub struct RawMessageTable {
+    pub resource: ResourceId,
+    pub lang: u16,
+    pub data: Vec<u8>,
+}
+
+// A single language of a single MESSAGETABLE resource.
+#[derive(Debug, Clone)]
+pub struct MessageTable {
+    pub resource: ResourceId,
+    pub lang: u16,
+    pub blocks: Vec<Block>,
+    pub entries: Vec<Entry>,
+}
+
+impl MessageTable {
+    // Parses a MESSAGE_RESOURCE_DATA blob obtained by some other means, such as from a memory
+    // dump. Nothing says where the data came from, so the table is labelled as resource 1
+    // in LANG_NEUTRAL; set resource and lang if they're known.
+    pub fn from_bytes(data: &[u8]) -> std::result::Result<MessageTable, ParseError> {
+        MessageTable::from_bytes_with(data, Decoding::Lossy)
+    }
+
+    pub fn from_bytes_with(
+        data: &[u8],
+        decoding: Decoding,
+    ) -> std::result::Result<MessageTable, ParseError> {
+        let parsed = msgtable::parse_with(data, decoding)?;
+        Ok(MessageTable {
+            resource: ResourceId::Id(1),
+            lang: 0,
+            blocks: parsed.blocks,
+            entries: parsed.entries,
+        })
+    }
+
+    pub fn into_message_entries(self) -> Vec<MessageEntry> {
+        let MessageTable {
+            resource,
+            lang,
+            entries,
+            ..
+        } = self;
+        entries
+            .into_iter()
+            .map(|entry| MessageEntry::new(entry, &resource, lang))
+            .collect()
+    }
+}
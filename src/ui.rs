@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+
+use windows::Win32::Globalization::GetUserDefaultUILanguage;
+
+use crate::msgtable::{Encoding, Entry};
+use crate::pe;
+use crate::table::{MessageTable, ResourceId};
+
+// Translations live in the message table of a resource-only DLL next to the executable,
+// compiled with mc and rc from the .mc that --emit-ui-catalog writes, and are read back with
+// this crate's own parser.
+const CATALOG_FILE_NAME: &str = "dump_message_tables_ui.dll";
+
+const LANG_ENGLISH_US: u16 = 0x409;
+
+// The tool's own user-facing strings. Text uses FormatMessage inserts (%1, %2, ...) and %n
+// for line breaks, so it can be handed to translators as ordinary .mc source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Msg {
+    Error = 1,
+    ModuleHeader = 2,
+    Signature = 3,
+    Untranslated = 4,
+    ProblemsFound = 5,
+    RowsUnresolved = 6,
+    Mismatch = 7,
+    NotFound = 8,
+    EntriesDiffer = 9,
+    CreateFailed = 10,
+    OpenFailed = 11,
+    CatalogUnreadable = 12,
+    ModuleHeaderBitness = 13,
+    ResourceOnly = 14,
+    ModuleFailed = 15,
+    ModulesFailed = 16,
+    ResolvedPath = 17,
+    EventsUnresolved = 18,
+    IdConflict = 19,
+    IdsConflict = 20,
+    RoundtripDifference = 21,
+    RoundtripFailed = 22,
+    Undocumented = 23,
+    UndocumentedFound = 24,
+}
+
+impl Msg {
+    const ALL: [Msg; 24] = [
+        Msg::Error,
+        Msg::ModuleHeader,
+        Msg::Signature,
+        Msg::Untranslated,
+        Msg::ProblemsFound,
+        Msg::RowsUnresolved,
+        Msg::Mismatch,
+        Msg::NotFound,
+        Msg::EntriesDiffer,
+        Msg::CreateFailed,
+        Msg::OpenFailed,
+        Msg::CatalogUnreadable,
+        Msg::ModuleHeaderBitness,
+        Msg::ResourceOnly,
+        Msg::ModuleFailed,
+        Msg::ModulesFailed,
+        Msg::ResolvedPath,
+        Msg::EventsUnresolved,
+        Msg::IdConflict,
+        Msg::IdsConflict,
+        Msg::RoundtripDifference,
+        Msg::RoundtripFailed,
+        Msg::Undocumented,
+        Msg::UndocumentedFound,
+    ];
+
+    fn english(self) -> &'static str {
+        match self {
+            Msg::Error => "ERROR: %1",
+            Msg::ModuleHeader => "%1:",
+            Msg::Signature => "signature: %1",
+            Msg::Untranslated => "%1 [%2]: %3 of %4 entries untranslated",
+            Msg::ProblemsFound => "%1 problems found",
+            Msg::RowsUnresolved => "%1 rows could not be resolved",
+            Msg::Mismatch => "MISMATCH: %1 %2 [%3]:%n  ours: %4%n  os:   %5",
+            Msg::NotFound => "not found",
+            Msg::EntriesDiffer => "%1 entries differ from FormatMessageW",
+            Msg::CreateFailed => "failed to create %1",
+            Msg::OpenFailed => "failed to open %1",
+            Msg::CatalogUnreadable => "WARNING: %1: %2; using English",
+            Msg::ModuleHeaderBitness => "%1 (%2-bit):",
+            Msg::ResourceOnly => "resource-only DLL",
+            Msg::ModuleFailed => "WARNING: %1: %2",
+            Msg::ModulesFailed => "%1 modules failed; see manifest.json",
+            Msg::ResolvedPath => "path: %1",
+            Msg::EventsUnresolved => "%1 of %2 event IDs can't be resolved on this machine",
+            Msg::IdConflict => "ERROR: %1 %2: defined more than once with different text, in %3",
+            Msg::IdsConflict => "%1 IDs are defined more than once with different text",
+            Msg::RoundtripDifference => "ROUNDTRIP: %1 resource %2 [%3]: %4",
+            Msg::RoundtripFailed => "%1 differences found re-encoding message tables",
+            Msg::Undocumented => "UNDOCUMENTED: %1 %2: %3",
+            Msg::UndocumentedFound => "%1 IDs are not in the reference",
+        }
+    }
+}
+
+pub struct Ui {
+    translations: HashMap<u32, String>,
+}
+
+impl Ui {
+    // Every lookup falls back to the built-in English.
+    pub fn english() -> Ui {
+        Ui {
+            translations: HashMap::new(),
+        }
+    }
+
+    // Picks lang, or the user's UI language, from the catalog. A language the catalog
+    // doesn't have falls back to another sublanguage of the same primary language, then to
+    // English, and so does every string a translation leaves out.
+    pub fn load(lang: Option<u16>) -> Ui {
+        let lang = lang.unwrap_or_else(|| unsafe { GetUserDefaultUILanguage() });
+        let mut ui = Ui::english();
+        if lang == LANG_ENGLISH_US {
+            return ui;
+        }
+        let path = match catalog_path() {
+            Some(path) if path.exists() => path,
+            _ => return ui,
+        };
+        let tables = match read_catalog(&path) {
+            Ok(tables) => tables,
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    ui.text(
+                        Msg::CatalogUnreadable,
+                        &[&path.display().to_string(), &e.to_string()]
+                    )
+                );
+                return ui;
+            }
+        };
+        let table = tables.iter().find(|table| table.lang == lang).or_else(|| {
+            tables
+                .iter()
+                .find(|table| primary(table.lang) == primary(lang))
+        });
+        if let Some(table) = table {
+            ui.translations = table
+                .entries
+                .iter()
+                .map(|entry| (entry.id, entry.text.clone()))
+                .collect();
+        }
+        ui
+    }
+
+    // Expands msg's inserts with args, which are numbered from %1.
+    pub fn text(&self, msg: Msg, args: &[&str]) -> String {
+        let text = self
+            .translations
+            .get(&(msg as u32))
+            .map_or(msg.english(), String::as_str);
+        expand(text, args)
+    }
+}
+
+// The English strings as a message table, for export::write_mc.
+pub fn english_table() -> MessageTable {
+    MessageTable {
+        resource: ResourceId::Id(1),
+        lang: LANG_ENGLISH_US,
+        blocks: Vec::new(),
+        entries: Msg::ALL
+            .iter()
+            .map(|&msg| {
+                // mc ends every message with CRLF.
+                let text = format!("{}\r\n", msg.english());
+                Entry {
+                    id: msg as u32,
+                    length: 0,
+                    encoding: Encoding::Unicode,
+                    offset: 0,
+                    raw: Vec::new(),
+                    text,
+                }
+            })
+            .collect(),
+    }
+}
+
+fn catalog_path() -> Option<PathBuf> {
+    let exe = env::current_exe().ok()?;
+    Some(exe.parent()?.join(CATALOG_FILE_NAME))
+}
+
+fn read_catalog(path: &Path) -> anyhow::Result<Vec<MessageTable>> {
+    let mut tables = Vec::new();
+    for raw in pe::read_raw_message_tables(path, true)? {
+        let mut table = MessageTable::from_bytes(&raw.data)?;
+        table.resource = raw.resource;
+        table.lang = raw.lang;
+        tables.push(table);
+    }
+    Ok(tables)
+}
+
+fn primary(lang: u16) -> u16 {
+    lang & 0x3ff
+}
+
+// A subset of FormatMessage's escapes: inserts, %n, %% and the %0 that suppresses the
+// trailing line break. The line break mc appends is dropped either way.
+fn expand(text: &str, args: &[&str]) -> String {
+    let text = text.strip_suffix("\r\n").unwrap_or(text);
+    let mut expanded = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.peek().copied() {
+            Some(c) if c.is_ascii_digit() => {
+                let mut index = 0;
+                let mut digits = 0;
+                while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                    if digits == 2 {
+                        break;
+                    }
+                    index = index * 10 + digit as usize;
+                    digits += 1;
+                    chars.next();
+                }
+                if index > 0 {
+                    expanded.push_str(args.get(index - 1).copied().unwrap_or_default());
+                }
+            }
+            Some('n') => {
+                expanded.push('\n');
+                chars.next();
+            }
+            Some(c) => {
+                expanded.push(c);
+                chars.next();
+            }
+            None => expanded.push('%'),
+        }
+    }
+    expanded
+}
@@ -0,0 +1,42 @@
+use crate::sys::Module;
+use crate::table::MessageTable;
+
+// An entry our parser and FormatMessageW disagree on. os is None when FormatMessageW
+// failed to find the message at all.
+pub struct Discrepancy {
+    pub id: u32,
+    pub lang: u16,
+    pub ours: String,
+    pub os: Option<String>,
+}
+
+// Renders about sample entries, spread evenly over the tables, through the OS and compares
+// them with what we decoded. A sample of 0 checks every entry.
+pub fn verify_against_os(
+    module: &Module,
+    tables: &[MessageTable],
+    sample: usize,
+) -> Vec<Discrepancy> {
+    let total: usize = tables.iter().map(|table| table.entries.len()).sum();
+    let step = match sample {
+        0 => 1,
+        sample => (total / sample).max(1),
+    };
+    tables
+        .iter()
+        .flat_map(|table| table.entries.iter().map(move |entry| (table.lang, entry)))
+        .step_by(step)
+        .filter_map(|(lang, entry)| {
+            let os = module.format_message(entry.id, lang).ok();
+            if os.as_deref() == Some(entry.text.as_str()) {
+                return None;
+            }
+            Some(Discrepancy {
+                id: entry.id,
+                lang,
+                ours: entry.text.clone(),
+                os,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,142 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::anyhow;
+use windows::core::*;
+use windows::Win32::Storage::FileSystem::GetVolumePathNameW;
+use windows::Win32::Storage::Vss::*;
+use windows::Win32::System::Com::*;
+
+// How long to wait for the snapshot to be prepared and taken. VSS gives up on its own well
+// before this.
+const SNAPSHOT_TIMEOUT_MS: u32 = 10 * 60 * 1000;
+
+// Non-persistent snapshots of the volumes modules are read from, so files that are locked or
+// being replaced can still be read. The snapshots are deleted when this is dropped.
+pub struct ShadowCopies {
+    // Releasing the last reference deletes the snapshots.
+    _backup: IVssBackupComponents,
+    // Each volume's root, lowercased, and the device its snapshot can be read through.
+    snapshots: Vec<(PathBuf, PathBuf)>,
+}
+
+impl ShadowCopies {
+    // Snapshots every volume holding one of paths. Needs administrator rights.
+    pub fn create(paths: &[PathBuf]) -> anyhow::Result<ShadowCopies> {
+        let mut volumes = BTreeSet::new();
+        for path in paths {
+            volumes.insert(volume_of(path)?);
+        }
+        unsafe {
+            // Either call may already have been made on this thread, which is fine.
+            let _ = CoInitializeEx(None, COINIT_MULTITHREADED);
+            let _ = CoInitializeSecurity(
+                None,
+                -1,
+                None,
+                None,
+                RPC_C_AUTHN_LEVEL_PKT_PRIVACY,
+                RPC_C_IMP_LEVEL_IDENTIFY,
+                None,
+                EOAC_DYNAMIC_CLOAKING,
+                None,
+            );
+        }
+        let backup = unsafe { CreateVssBackupComponentsInternal() }
+            .map_err(|e| vss_error("failed to start a shadow copy", e))?;
+        let mut ids = Vec::new();
+        unsafe {
+            backup
+                .InitializeForBackup(&BSTR::new())
+                .map_err(|e| vss_error("failed to start a shadow copy", e))?;
+            // A snapshot without writers, which only needs the files as they are on disk.
+            backup
+                .SetContext(VSS_CTX_FILE_SHARE_BACKUP.0)
+                .map_err(|e| vss_error("failed to start a shadow copy", e))?;
+            backup
+                .SetBackupState(false, false, VSS_BT_COPY, false)
+                .map_err(|e| vss_error("failed to start a shadow copy", e))?;
+            backup
+                .StartSnapshotSet()
+                .map_err(|e| vss_error("failed to start a shadow copy", e))?;
+            for volume in &volumes {
+                let volume_utf16 = wp::utf8_to_utf16(&volume.to_string_lossy());
+                let id = backup
+                    .AddToSnapshotSet(PCWSTR(volume_utf16.as_ptr()), GUID::zeroed())
+                    .map_err(|e| {
+                        vss_error(&format!("failed to snapshot {}", volume.display()), e)
+                    })?;
+                ids.push((volume.clone(), id));
+            }
+            wait(
+                backup.PrepareForBackup(),
+                "failed to prepare the shadow copy",
+            )?;
+            wait(backup.DoSnapshotSet(), "failed to create the shadow copy")?;
+        }
+
+        let mut snapshots = Vec::new();
+        for (volume, id) in ids {
+            let mut prop = VSS_SNAPSHOT_PROP::default();
+            unsafe {
+                backup.GetSnapshotProperties(id, &mut prop).map_err(|e| {
+                    vss_error(
+                        &format!("failed to find the snapshot of {}", volume.display()),
+                        e,
+                    )
+                })?;
+                let device = wp::utf16_to_utf8(prop.m_pwszSnapshotDeviceObject.0);
+                VssFreeSnapshotProperties(&mut prop);
+                snapshots.push((
+                    PathBuf::from(volume.to_string_lossy().to_lowercase()),
+                    PathBuf::from(device),
+                ));
+            }
+        }
+        Ok(ShadowCopies {
+            _backup: backup,
+            snapshots,
+        })
+    }
+
+    // Each snapshotted volume's root and the device its snapshot is read through, as
+    // LoadPolicy::with_snapshots takes them.
+    pub fn snapshots(&self) -> &[(PathBuf, PathBuf)] {
+        &self.snapshots
+    }
+}
+
+// The root of the volume path is on, e.g. C:\ or a mounted folder.
+fn volume_of(path: &Path) -> anyhow::Result<PathBuf> {
+    let path_utf16 = wp::utf8_to_utf16(&path.to_string_lossy());
+    let mut buf = [0u16; 1024];
+    if !unsafe { GetVolumePathNameW(PCWSTR(path_utf16.as_ptr()), &mut buf) }.as_bool() {
+        return Err(anyhow!(
+            "failed to find the volume of {}: {}",
+            path.display(),
+            Error::from_win32().message()
+        ));
+    }
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    Ok(PathBuf::from(String::from_utf16_lossy(&buf[..len])))
+}
+
+// Waits for an asynchronous VSS operation, failing with whatever it failed with.
+unsafe fn wait(operation: Result<IVssAsync>, context: &str) -> anyhow::Result<()> {
+    let operation = operation.map_err(|e| vss_error(context, e))?;
+    operation
+        .Wait(SNAPSHOT_TIMEOUT_MS)
+        .map_err(|e| vss_error(context, e))?;
+    let mut status = HRESULT(0);
+    operation
+        .QueryStatus(&mut status, None)
+        .map_err(|e| vss_error(context, e))?;
+    if status != VSS_S_ASYNC_FINISHED {
+        return Err(vss_error(context, Error::from(status)));
+    }
+    Ok(())
+}
+
+fn vss_error(context: &str, e: Error) -> anyhow::Error {
+    anyhow!("{}: {} ({:#010x})", context, e.message(), e.code().0)
+}
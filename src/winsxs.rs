@@ -0,0 +1,171 @@
+use std::collections::hash_map::{DefaultHasher, Entry};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use windows::Win32::System::LibraryLoader::*;
+
+use crate::message::MessageEntry;
+use crate::output::Printer;
+use crate::policy::LoadPolicy;
+use crate::scan::{self, CancellationToken, NameFilter};
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Version(Vec<u32>);
+
+impl Version {
+    fn parse(s: &str) -> Option<Version> {
+        s.split('.')
+            .map(|part| part.parse().ok())
+            .collect::<Option<Vec<u32>>>()
+            .map(Version)
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self.0.iter().map(|part| part.to_string()).collect();
+        write!(f, "{}", parts.join("."))
+    }
+}
+
+struct ComponentDir {
+    name: String,
+    version: Version,
+    path: PathBuf,
+}
+
+fn parse_component_dir(path: PathBuf) -> Option<ComponentDir> {
+    let dir_name = path.file_name()?.to_str()?;
+    // <arch>_<name>_<public key token>_<version>_<culture>_<hash>
+    let mut parts = dir_name.rsplitn(5, '_');
+    let _hash = parts.next()?;
+    let culture = parts.next()?;
+    let version = Version::parse(parts.next()?)?;
+    let _public_key_token = parts.next()?;
+    let (arch, name) = parts.next()?.split_once('_')?;
+
+    let name = if culture == "none" {
+        format!("{} ({})", name, arch)
+    } else {
+        format!("{} ({}, {})", name, arch, culture)
+    };
+    Some(ComponentDir {
+        name,
+        version,
+        path,
+    })
+}
+
+fn component_store_root() -> PathBuf {
+    let system_root = env::var("SystemRoot").unwrap_or_else(|_| "C:\\Windows".to_string());
+    Path::new(&system_root).join("WinSxS")
+}
+
+fn payload_hash(entries: &[MessageEntry]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    entries.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub fn dump_component_store(
+    printer: &Printer,
+    policy: &LoadPolicy,
+    filter: &NameFilter,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let root = component_store_root();
+    let mut components: BTreeMap<String, BTreeMap<Version, PathBuf>> = BTreeMap::new();
+    for dir_entry in
+        fs::read_dir(&root).with_context(|| format!("failed to read {}", root.display()))?
+    {
+        let path = dir_entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+        if let Some(component) = parse_component_dir(path) {
+            components
+                .entry(component.name)
+                .or_default()
+                .insert(component.version, component.path);
+        }
+    }
+
+    let mut files: Vec<(&str, &Version, PathBuf)> = Vec::new();
+    for (name, versions) in &components {
+        for (version, dir) in versions {
+            for file in scan::module_files(dir, filter)? {
+                files.push((name.as_str(), version, file));
+            }
+        }
+    }
+    // Files in the component store are hardlinked into each other and into System32, so
+    // most payloads are reached through several paths. Each is only loaded through the
+    // first of them.
+    let paths: Vec<PathBuf> = files.iter().map(|(_, _, file)| file.clone()).collect();
+    let mut first_of: Vec<usize> = (0..files.len()).collect();
+    for group in scan::group_duplicates(&paths) {
+        for &i in &group {
+            first_of[i] = group[0];
+        }
+    }
+
+    // NOTE: Identical tables also show up in files that aren't copies of each other, such as
+    // successive versions of a component. Only the first occurrence of a payload is printed.
+    let mut seen: HashMap<u64, String> = HashMap::new();
+    let mut printed: HashMap<usize, String> = HashMap::new();
+    let mut printed_name = None;
+    for (i, (name, version, file)) in files.iter().enumerate() {
+        cancel.check()?;
+        let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+        let label = format!("{} {} {}", name, version, file_name);
+        if first_of[i] != i {
+            // A duplicate of a file that was empty or failed to load is skipped like it.
+            if let Some(first) = printed.get(&first_of[i]) {
+                print_component_name(&mut printed_name, name);
+                println!("  {} {}: identical to {}", version, file_name, first);
+            }
+            continue;
+        }
+        let entries = match policy.get_message_table_entries(
+            &file.to_string_lossy(),
+            LOAD_LIBRARY_AS_DATAFILE | LOAD_LIBRARY_AS_IMAGE_RESOURCE,
+        ) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("WARNING: {}: {}", file.display(), e);
+                continue;
+            }
+        };
+        if entries.is_empty() {
+            continue;
+        }
+
+        print_component_name(&mut printed_name, name);
+        match seen.entry(payload_hash(&entries)) {
+            Entry::Occupied(first) => {
+                println!("  {} {}: identical to {}", version, file_name, first.get());
+                printed.insert(i, first.get().clone());
+            }
+            Entry::Vacant(slot) => {
+                println!("  {} {}:", version, file_name);
+                printer.print_entries(&entries, None)?;
+                slot.insert(label.clone());
+                printed.insert(i, label);
+            }
+        }
+    }
+    Ok(())
+}
+
+// Prints a component's name before the first of its files that's printed.
+fn print_component_name<'a>(printed_name: &mut Option<&'a str>, name: &'a str) {
+    if *printed_name != Some(name) {
+        println!("{}", name);
+        *printed_name = Some(name);
+    }
+}